@@ -11,9 +11,9 @@ async fn main() {
 
     let conf_str = &fs::read_to_string("./config.json").unwrap();
     let config: ProviderConfig = serde_json::from_str(conf_str).expect("Invalid configuration");
-    let mut app = Client::new(config, None).await.unwrap();
+    let app = Client::new(config, None).await.unwrap();
     let shares = app.list_shares().await.unwrap();
-    if shares.len() == 0 {
+    if shares.is_empty() {
         println!("At least 1 Delta Share is required");
     } else {
         let share_name = &shares[0].name;
@@ -25,7 +25,7 @@ async fn main() {
         let schemas = app.list_schemas(&shares[0]).await.unwrap();
         println!("Found {} schemas in share [{}]", schemas.len(), &share_name);
 
-        if schemas.len() == 0 {
+        if !schemas.is_empty() {
             let schema_tables = app.list_tables(&schemas[0]).await.unwrap();
             println!(
                 "Found {} tables in schema [{}]",
@@ -35,7 +35,7 @@ async fn main() {
         }
 
         let tables = app.list_all_tables(&shares[0]).await.unwrap();
-        if shares.len() == 0 {
+        if shares.is_empty() {
             println!(
                 "Need at least one table in share {} (or use a different share)",
                 shares[0].name
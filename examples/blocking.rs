@@ -10,13 +10,13 @@ fn main() {
     let conf_str = &fs::read_to_string("./config.json").unwrap();
 
     let config: ProviderConfig = serde_json::from_str(conf_str).expect("Invalid configuration");
-    let mut app = Client::new(config, None).unwrap();
+    let app = Client::new(config, None).unwrap();
     let shares = app.list_shares().unwrap();
-    if shares.len() == 0 {
+    if shares.is_empty() {
         println!("At least 1 Delta Share is required");
     } else {
         let tables = app.list_all_tables(&shares[0]).unwrap();
-        if shares.len() == 0 {
+        if shares.is_empty() {
             println!(
                 "Need at least one table in share {} (or use a different share)",
                 shares[0].name
@@ -0,0 +1,341 @@
+//! Command-line tool to explore and download Delta Sharing tables
+//!
+//! ```text
+//! delta-sharing --profile profile.share shares
+//! delta-sharing --profile profile.share tables my_share
+//! delta-sharing --profile profile.share --output json head my_share.my_schema.my_table -n 5
+//! delta-sharing --profile profile.share mirror my_share.my_schema.my_table /data/my_table
+//! ```
+
+use clap::{Parser, Subcommand, ValueEnum};
+use delta_sharing::protocol::*;
+use delta_sharing::remote::{batches_to_dataframe, ReadOptions};
+use delta_sharing::{Client, Error};
+use reqwest::StatusCode;
+use serde::Serialize;
+use std::fs;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+/// Exit code of failures which are not specific to the sharing protocol
+const EXIT_FAILURE: u8 = 1;
+/// The server rejected the credentials of the profile
+const EXIT_UNAUTHORIZED: u8 = 3;
+/// The share, schema or table does not exist or is not accessible
+const EXIT_NOT_FOUND: u8 = 4;
+/// The table requires a reader version or features this client does not support
+const EXIT_UNSUPPORTED: u8 = 5;
+/// Any other error response of the sharing server
+const EXIT_SERVER_ERROR: u8 = 6;
+
+#[derive(Parser)]
+#[command(
+    name = "delta-sharing",
+    version,
+    about = "Explore and download Delta Sharing tables"
+)]
+struct Cli {
+    /// Path of the profile file with the endpoint and the bearer token of the sharing server
+    #[arg(short, long)]
+    profile: PathBuf,
+
+    /// Output format
+    #[arg(short, long, value_enum, default_value_t = Output::Table)]
+    output: Output,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Output {
+    Table,
+    Json,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List the shares
+    Shares,
+    /// List the schemas of a share
+    Schemas { share: String },
+    /// List the tables of a share, or only those of one of its schemas
+    Tables {
+        share: String,
+        #[arg(short, long)]
+        schema: Option<String>,
+    },
+    /// Show the protocol and metadata of a table, given as `share.schema.table`
+    Metadata {
+        table: String,
+        #[arg(long, conflicts_with = "timestamp")]
+        version: Option<i64>,
+        /// Timestamp in ISO 8601 format
+        #[arg(long)]
+        timestamp: Option<String>,
+    },
+    /// Show the current version of a table, or its version at a timestamp
+    Version {
+        table: String,
+        /// Timestamp in ISO 8601 format
+        #[arg(long)]
+        timestamp: Option<String>,
+    },
+    /// List the data files of a table
+    Files {
+        table: String,
+        #[arg(long)]
+        version: Option<i64>,
+    },
+    /// Download the data files of a table into a directory
+    Download { table: String, directory: PathBuf },
+    /// Mirror a table into a local Delta table, applying the changes since the previous run
+    Mirror { table: String, directory: PathBuf },
+    /// Print the first rows of a table
+    Head {
+        table: String,
+        #[arg(short = 'n', long, default_value_t = 10)]
+        rows: usize,
+    },
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    env_logger::init();
+    let cli = Cli::parse();
+    match run(cli).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            ExitCode::from(exit_code(&e))
+        }
+    }
+}
+
+fn exit_code(error: &anyhow::Error) -> u8 {
+    let status = match error.downcast_ref::<Error>() {
+        Some(Error::UnsupportedReaderVersion { .. } | Error::UnsupportedReaderFeatures(_)) => {
+            return EXIT_UNSUPPORTED
+        }
+        Some(Error::NotFound(_)) => return EXIT_NOT_FOUND,
+        Some(Error::Http(e)) => e.status(),
+        _ => error
+            .downcast_ref::<reqwest::Error>()
+            .and_then(|e| e.status()),
+    };
+    match status {
+        Some(StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) => EXIT_UNAUTHORIZED,
+        Some(StatusCode::NOT_FOUND) => EXIT_NOT_FOUND,
+        Some(_) => EXIT_SERVER_ERROR,
+        None => EXIT_FAILURE,
+    }
+}
+
+fn parse_table(name: &str) -> Result<Table, anyhow::Error> {
+    match name.split('.').collect::<Vec<_>>()[..] {
+        [share, schema, table] => Ok(Table {
+            share: share.to_string(),
+            schema: schema.to_string(),
+            name: table.to_string(),
+            share_id: None,
+            id: None,
+        }),
+        _ => Err(anyhow::anyhow!(
+            "Invalid table {}, expected share.schema.table",
+            name
+        )),
+    }
+}
+
+async fn run(cli: Cli) -> Result<(), anyhow::Error> {
+    let profile = fs::read_to_string(&cli.profile)?;
+    let config: ProviderConfig = serde_json::from_str(&profile)?;
+    let mut builder = Client::builder(config);
+    if let Command::Download { directory, .. } = &cli.command {
+        builder = builder.data_root(directory.to_string_lossy());
+    }
+    let client = builder.build()?;
+    let output = cli.output;
+
+    match cli.command {
+        Command::Shares => {
+            let shares = client.list_shares().await?;
+            let rows = shares.iter().map(|s| vec![s.name.clone()]).collect();
+            print(output, &shares, &["share"], rows)
+        }
+        Command::Schemas { share } => {
+            let share = client.get_share(&share).await?;
+            let schemas = client.list_schemas(&share).await?;
+            let rows = schemas.iter().map(|s| vec![s.name.clone()]).collect();
+            print(output, &schemas, &["schema"], rows)
+        }
+        Command::Tables { share, schema } => {
+            let tables = match schema {
+                Some(schema) => {
+                    client
+                        .list_tables(&Schema {
+                            name: schema,
+                            share,
+                        })
+                        .await?
+                }
+                None => {
+                    let share = client.get_share(&share).await?;
+                    client.list_all_tables(&share).await?
+                }
+            };
+            let rows = tables
+                .iter()
+                .map(|t| vec![t.schema.clone(), t.name.clone()])
+                .collect();
+            print(output, &tables, &["schema", "table"], rows)
+        }
+        Command::Metadata {
+            table,
+            version,
+            timestamp,
+        } => {
+            let table = parse_table(&table)?;
+            let (version, metadata) = client
+                .get_table_metadata_at(&table, version, timestamp.as_deref())
+                .await?;
+            let m = &metadata.metadata;
+            let rows = vec![
+                vec!["version".to_string(), version.to_string()],
+                vec!["id".to_string(), m.id.clone()],
+                vec!["name".to_string(), m.name.clone().unwrap_or_default()],
+                vec![
+                    "description".to_string(),
+                    m.description.clone().unwrap_or_default(),
+                ],
+                vec!["format".to_string(), m.format.provider.clone()],
+                vec![
+                    "partition columns".to_string(),
+                    m.partition_columns.join(", "),
+                ],
+                vec![
+                    "min reader version".to_string(),
+                    metadata.protocol.min_reader_version.to_string(),
+                ],
+                vec!["schema".to_string(), m.schema_string.clone()],
+            ];
+            print(output, &metadata, &["property", "value"], rows)
+        }
+        Command::Version { table, timestamp } => {
+            let table = parse_table(&table)?;
+            let version = match timestamp {
+                Some(timestamp) => client.get_table_version_at(&table, &timestamp).await?,
+                None => client.get_table_version(&table).await?,
+            };
+            print(
+                output,
+                &version,
+                &["version"],
+                vec![vec![version.to_string()]],
+            )
+        }
+        Command::Files { table, version } => {
+            let table = parse_table(&table)?;
+            let table_files = client.list_table_files(&table, None, None, version).await?;
+            let rows = table_files
+                .files
+                .iter()
+                .map(|f| {
+                    vec![
+                        f.id.clone(),
+                        f.size.to_string(),
+                        serde_json::to_string(&f.partition_values).unwrap_or_default(),
+                    ]
+                })
+                .collect();
+            print(
+                output,
+                &table_files.files,
+                &["id", "size", "partition values"],
+                rows,
+            )
+        }
+        Command::Download { table, .. } => {
+            let table = parse_table(&table)?;
+            let paths = client.get_files(&table).await?;
+            let rows = paths
+                .iter()
+                .map(|p| vec![p.display().to_string()])
+                .collect();
+            print(output, &paths, &["path"], rows)
+        }
+        Command::Mirror { table, directory } => {
+            let table = parse_table(&table)?;
+            let summary = client.mirror_table(&table, &directory).await?;
+            let rows = vec![vec![
+                summary.source_version.to_string(),
+                summary.version.to_string(),
+                summary.files_added.to_string(),
+                summary.files_removed.to_string(),
+            ]];
+            let value = serde_json::json!({
+                "sourceVersion": summary.source_version,
+                "version": summary.version,
+                "filesAdded": summary.files_added,
+                "filesRemoved": summary.files_removed,
+            });
+            print(
+                output,
+                &value,
+                &["source version", "version", "added", "removed"],
+                rows,
+            )
+        }
+        Command::Head { table, rows } => {
+            let table = parse_table(&table)?;
+            let options = ReadOptions {
+                columns: None,
+                limit: Some(rows),
+            };
+            let batches = client.read_record_batches(&table, &options).await?;
+            match output {
+                Output::Table => println!("{}", batches_to_dataframe(&batches)?),
+                Output::Json => {
+                    let rows = arrow::json::writer::record_batches_to_json_rows(&batches)?;
+                    println!("{}", serde_json::to_string_pretty(&rows)?);
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Prints the value as JSON, or the rows as a table with aligned columns
+fn print<T: Serialize + ?Sized>(
+    output: Output,
+    value: &T,
+    headers: &[&str],
+    rows: Vec<Vec<String>>,
+) -> Result<(), anyhow::Error> {
+    match output {
+        Output::Json => println!("{}", serde_json::to_string_pretty(value)?),
+        Output::Table => {
+            let mut widths = headers.iter().map(|h| h.len()).collect::<Vec<_>>();
+            for row in &rows {
+                for (width, cell) in widths.iter_mut().zip(row) {
+                    *width = (*width).max(cell.len());
+                }
+            }
+            let line = |cells: Vec<&str>| {
+                cells
+                    .iter()
+                    .zip(&widths)
+                    .map(|(cell, width)| format!("{:width$}", cell, width = width))
+                    .collect::<Vec<_>>()
+                    .join("  ")
+                    .trim_end()
+                    .to_string()
+            };
+            println!("{}", line(headers.to_vec()));
+            for row in &rows {
+                println!("{}", line(row.iter().map(|c| c.as_str()).collect()));
+            }
+        }
+    }
+    Ok(())
+}
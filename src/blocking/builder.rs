@@ -0,0 +1,48 @@
+use crate::blocking::Client;
+use crate::builder::{build_http_client, settings_methods, Settings};
+use crate::protocol::ProviderConfig;
+
+/// A builder to configure a blocking [Client]
+///
+/// See the async [ClientBuilder][crate::ClientBuilder] for an example.
+pub struct ClientBuilder {
+    provider_config: ProviderConfig,
+    settings: Settings,
+    http_client: Option<reqwest::blocking::Client>,
+}
+
+impl ClientBuilder {
+    pub(crate) fn new(provider_config: ProviderConfig) -> Self {
+        Self {
+            provider_config,
+            settings: Settings::default(),
+            http_client: None,
+        }
+    }
+
+    settings_methods!();
+
+    /// Uses the given reqwest client for the requests. The timeout, proxy and TLS settings
+    /// of this builder are ignored for these requests, they have to be configured on the given
+    /// client instead
+    pub fn http_client(mut self, http_client: reqwest::blocking::Client) -> Self {
+        self.http_client = Some(http_client);
+        self
+    }
+
+    /// Builds the [Client]
+    pub fn build(self) -> Result<Client, anyhow::Error> {
+        let http_client = match self.http_client {
+            Some(http_client) => http_client,
+            None => build_http_client!(reqwest::blocking::Client::builder(), self.settings)?,
+        };
+        // The parquet reader used by `read_record_batches` requires an async client
+        let range_client = build_http_client!(reqwest::Client::builder(), self.settings)?;
+        Client::from_settings(
+            self.provider_config,
+            self.settings,
+            http_client,
+            range_client,
+        )
+    }
+}
@@ -26,7 +26,7 @@
 //!      endpoint: "<your Delta Share endpoinit URL>".to_string(),
 //!      bearer_token: "<your Delta Share access token>".to_string(),
 //!  };
-//!  let mut app = Client::new(config, None).unwrap();
+//!  let app = Client::new(config, None).unwrap();
 //!  let shares = app.list_shares().unwrap();
 //!  if shares.len() == 0 {
 //!      println!("At least 1 Delta Share is required");
@@ -45,6 +45,8 @@
 //!  # }
 //!  ```
 
+pub use self::builder::ClientBuilder;
 pub use self::client::Client;
 
+mod builder;
 mod client;
@@ -0,0 +1,220 @@
+use crate::cache::CacheStore;
+use crate::client::Client;
+use crate::credentials::CredentialProvider;
+use crate::metrics::Metrics;
+use crate::protocol::{ProviderConfig, ResponseFormat};
+use reqwest::header::HeaderMap;
+use reqwest::{Certificate, Identity, Proxy};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Settings shared by the async and the blocking [ClientBuilder]
+#[derive(Default)]
+pub(crate) struct Settings {
+    pub data_root: Option<String>,
+    pub response_format: ResponseFormat,
+    pub best_effort_read: bool,
+    pub connect_timeout: Option<Duration>,
+    pub timeout: Option<Duration>,
+    pub proxies: Vec<Proxy>,
+    pub root_certificates: Vec<Certificate>,
+    pub identity: Option<Identity>,
+    pub headers: HeaderMap,
+    pub user_agent_suffix: Option<String>,
+    pub credential_provider: Option<Arc<dyn CredentialProvider>>,
+    pub log_response_bodies: bool,
+    pub metrics: Option<Arc<dyn Metrics>>,
+    pub cache_store: Option<Arc<dyn CacheStore>>,
+}
+
+/// Setters of the [Settings], shared by the async and the blocking builder
+macro_rules! settings_methods {
+    () => {
+        /// Local directory path to store the downloaded cached files. Temp location is used if not set
+        pub fn data_root(mut self, data_root: impl Into<String>) -> Self {
+            self.settings.data_root = Some(data_root.into());
+            self
+        }
+
+        /// Keeps the downloaded table files in the store instead of the `data_root` directory,
+        /// e.g. to share them between processes, see [cache][crate::cache]
+        pub fn cache_store(mut self, store: std::sync::Arc<dyn crate::cache::CacheStore>) -> Self {
+            self.settings.cache_store = Some(store);
+            self
+        }
+
+        /// Keeps the downloaded table files in memory only, evicting the least recently used
+        /// ones beyond `max_bytes`, for environments with a read-only filesystem. Shorthand for
+        /// a [MemoryCacheStore][crate::cache::MemoryCacheStore] passed to `cache_store`
+        pub fn memory_cache(self, max_bytes: u64) -> Self {
+            self.cache_store(std::sync::Arc::new(
+                crate::cache::MemoryCacheStore::with_capacity(max_bytes),
+            ))
+        }
+
+        /// Format requested for the table metadata and file listings, see [ResponseFormat][crate::protocol::ResponseFormat]
+        pub fn response_format(mut self, response_format: crate::protocol::ResponseFormat) -> Self {
+            self.settings.response_format = response_format;
+            self
+        }
+
+        /// Read tables whose protocol requires a newer reader version or unsupported reader
+        /// features instead of failing. The returned data may be incorrect
+        pub fn best_effort_read(mut self, best_effort_read: bool) -> Self {
+            self.settings.best_effort_read = best_effort_read;
+            self
+        }
+
+        /// Timeout for establishing connections
+        pub fn connect_timeout(mut self, timeout: std::time::Duration) -> Self {
+            self.settings.connect_timeout = Some(timeout);
+            self
+        }
+
+        /// Timeout of each request, from connecting until the response body is read.
+        /// Applies to the file downloads as well
+        pub fn timeout(mut self, timeout: std::time::Duration) -> Self {
+            self.settings.timeout = Some(timeout);
+            self
+        }
+
+        /// Adds an HTTP or HTTPS proxy, e.g. `reqwest::Proxy::all("http://proxy:8080")`.
+        /// Proxies from the environment variables are used if none are given
+        pub fn proxy(mut self, proxy: reqwest::Proxy) -> Self {
+            self.settings.proxies.push(proxy);
+            self
+        }
+
+        /// Adds a trusted root certificate, e.g. a corporate CA
+        pub fn add_root_certificate(mut self, certificate: reqwest::Certificate) -> Self {
+            self.settings.root_certificates.push(certificate);
+            self
+        }
+
+        /// Client certificate to authenticate with when the server requires mutual TLS
+        pub fn identity(mut self, identity: reqwest::Identity) -> Self {
+            self.settings.identity = Some(identity);
+            self
+        }
+
+        /// Headers sent with every request to the sharing server, but not with the file downloads
+        pub fn default_headers(mut self, headers: reqwest::header::HeaderMap) -> Self {
+            self.settings.headers.extend(headers);
+            self
+        }
+
+        /// Text appended to the `User-Agent` header, e.g. the name and version of the application
+        pub fn user_agent_suffix(mut self, suffix: impl Into<String>) -> Self {
+            self.settings.user_agent_suffix = Some(suffix.into());
+            self
+        }
+
+        /// Supplies the bearer token of each request, instead of the `bearer_token` of the
+        /// provider configuration. See [credentials][crate::credentials]
+        pub fn credential_provider(
+            mut self,
+            provider: std::sync::Arc<dyn crate::credentials::CredentialProvider>,
+        ) -> Self {
+            self.settings.credential_provider = Some(provider);
+            self
+        }
+
+        /// Logs the full response bodies of the sharing server at debug level. They include
+        /// the presigned file URLs and the table metadata, so only their size is logged by default
+        pub fn log_response_bodies(mut self, log_response_bodies: bool) -> Self {
+            self.settings.log_response_bodies = log_response_bodies;
+            self
+        }
+
+        /// Reports the requests, downloads and cache lookups of the client, see [metrics][crate::metrics]
+        pub fn metrics(mut self, metrics: std::sync::Arc<dyn crate::metrics::Metrics>) -> Self {
+            self.settings.metrics = Some(metrics);
+            self
+        }
+    };
+}
+#[cfg(feature = "blocking")]
+pub(crate) use settings_methods;
+
+/// Builds a reqwest client from the timeout, proxy and TLS settings
+macro_rules! build_http_client {
+    ($builder:expr, $settings:expr) => {{
+        let settings = &$settings;
+        let mut builder = $builder;
+        if let Some(timeout) = settings.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(timeout) = settings.timeout {
+            builder = builder.timeout(timeout);
+        }
+        for proxy in &settings.proxies {
+            builder = builder.proxy(proxy.clone());
+        }
+        for certificate in &settings.root_certificates {
+            builder = builder.add_root_certificate(certificate.clone());
+        }
+        if let Some(identity) = &settings.identity {
+            builder = builder.identity(identity.clone());
+        }
+        builder.build()
+    }};
+}
+#[cfg(feature = "blocking")]
+pub(crate) use build_http_client;
+
+/// A builder to configure an async [Client]
+///
+/// # Example
+///
+/// ```rust
+/// use delta_sharing::Client;
+/// use delta_sharing::protocol::ProviderConfig;
+/// use std::time::Duration;
+///
+/// # fn run() -> Result<(), anyhow::Error> {
+/// let config = ProviderConfig {
+///     share_credentials_version: 1,
+///     endpoint: "<your Delta Share endpoinit URL>".to_string(),
+///     bearer_token: "<your Delta Share access token>".to_string(),
+/// };
+/// let client = Client::builder(config)
+///     .connect_timeout(Duration::from_secs(10))
+///     .proxy(reqwest::Proxy::all("http://proxy.example.com:8080")?)
+///     .user_agent_suffix("my-app/1.0")
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct ClientBuilder {
+    provider_config: ProviderConfig,
+    settings: Settings,
+    http_client: Option<reqwest::Client>,
+}
+
+impl ClientBuilder {
+    pub(crate) fn new(provider_config: ProviderConfig) -> Self {
+        Self {
+            provider_config,
+            settings: Settings::default(),
+            http_client: None,
+        }
+    }
+
+    settings_methods!();
+
+    /// Uses the given reqwest client for all the requests. The timeout, proxy and TLS settings
+    /// of this builder are ignored, they have to be configured on the given client instead
+    pub fn http_client(mut self, http_client: reqwest::Client) -> Self {
+        self.http_client = Some(http_client);
+        self
+    }
+
+    /// Builds the [Client]
+    pub fn build(self) -> Result<Client, anyhow::Error> {
+        let http_client = match self.http_client {
+            Some(http_client) => http_client,
+            None => build_http_client!(reqwest::Client::builder(), self.settings)?,
+        };
+        Client::from_settings(self.provider_config, self.settings, http_client)
+    }
+}
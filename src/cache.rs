@@ -0,0 +1,351 @@
+//! Stores of the downloaded table files.
+//!
+//! [Client::get_files][crate::Client::get_files] and
+//! [Client::get_dataframe][crate::Client::get_dataframe] keep the data files of each table,
+//! together with a manifest of the table metadata they belong to and their checksums, in a
+//! [CacheStore]. Keys are
+//! `/`-separated paths: `<share>.<schema>.<table>/metadata.json` for the manifest and
+//! `<share>.<schema>.<table>/<file id>.snappy.parquet` for the files.
+//!
+//! [LocalCacheStore] keeps them in a local directory and is used with the `data_root` of the
+//! client unless another store is set with the `cache_store` method of the client builder.
+//! [MemoryCacheStore] keeps them for the lifetime of the process. With the `object-store`
+//! feature, [ObjectCacheStore] keeps them in any [object_store::ObjectStore], e.g. an S3, GCS
+//! or Azure bucket shared by a fleet of workers, so that each shared file is only downloaded
+//! from the sharing server once.
+//!
+//! Downloaded files are checked against the size of the listing and for a parquet footer.
+//! Cached files are checked against the checksums of the manifest when a client first reads
+//! them and are downloaded again if they are missing or corrupt, see
+//! [Client::verify_cache][crate::Client::verify_cache] to check a whole table on demand.
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tokio::fs;
+
+/// Result of [Client::verify_cache][crate::Client::verify_cache]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheVerification {
+    /// Number of listed files of the table
+    pub files: usize,
+    /// Ids of the files which were missing, corrupt or outdated and were downloaded again
+    pub repaired: Vec<String>,
+}
+
+/// Keeps the content of the cached table files by key
+#[async_trait]
+pub trait CacheStore: Send + Sync {
+    /// Content of the entry, None if there is no entry for the key
+    async fn get(&self, key: &str) -> Result<Option<Bytes>, anyhow::Error>;
+
+    /// Adds or replaces the entry
+    async fn put(&self, key: &str, content: Bytes) -> Result<(), anyhow::Error>;
+
+    /// Whether there is an entry for the key
+    async fn contains(&self, key: &str) -> Result<bool, anyhow::Error> {
+        Ok(self.get(key).await?.is_some())
+    }
+
+    /// Deletes the entries whose keys start with `{dir}/`
+    async fn delete_dir(&self, dir: &str) -> Result<(), anyhow::Error>;
+
+    /// Path of the entry or directory on the local filesystem, if the store keeps its entries
+    /// there. Local entries are scanned in place, the others are read into memory
+    fn local_path(&self, _key: &str) -> Option<PathBuf> {
+        None
+    }
+}
+
+/// Keeps the entries as files below a local directory
+#[derive(Debug, Clone)]
+pub struct LocalCacheStore {
+    root: PathBuf,
+}
+
+impl LocalCacheStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Path of the entry under the root. Keys are relative paths separated by `/`, the ones
+    /// which could point outside of the root are rejected
+    fn path(&self, key: &str) -> Result<PathBuf, anyhow::Error> {
+        let valid = key.split('/').all(|part| {
+            !matches!(part, "" | "." | "..") && !part.contains(std::path::is_separator)
+        });
+        if !valid {
+            return Err(anyhow::anyhow!("Invalid cache key {}", key));
+        }
+        Ok(self.root.join(key))
+    }
+}
+
+#[async_trait]
+impl CacheStore for LocalCacheStore {
+    async fn get(&self, key: &str) -> Result<Option<Bytes>, anyhow::Error> {
+        match fs::read(self.path(key)?).await {
+            Ok(content) => Ok(Some(content.into())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn put(&self, key: &str, content: Bytes) -> Result<(), anyhow::Error> {
+        let path = self.path(key)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        fs::write(path, content).await?;
+        Ok(())
+    }
+
+    async fn contains(&self, key: &str) -> Result<bool, anyhow::Error> {
+        match fs::metadata(self.path(key)?).await {
+            Ok(metadata) => Ok(metadata.is_file()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn delete_dir(&self, dir: &str) -> Result<(), anyhow::Error> {
+        match fs::remove_dir_all(self.path(dir)?).await {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn local_path(&self, key: &str) -> Option<PathBuf> {
+        self.path(key).ok()
+    }
+}
+
+/// Keeps the entries in memory, shared by the clients using the same store. With a capacity,
+/// the least recently used entries are evicted once their total size exceeds it
+#[derive(Default)]
+pub struct MemoryCacheStore {
+    capacity: Option<u64>,
+    entries: Mutex<MemoryEntries>,
+}
+
+#[derive(Default)]
+struct MemoryEntries {
+    entries: BTreeMap<String, MemoryEntry>,
+    size: u64,
+    /// Incremented on every access, to order the entries by their last use
+    clock: u64,
+}
+
+struct MemoryEntry {
+    content: Bytes,
+    last_used: u64,
+}
+
+impl MemoryCacheStore {
+    /// A store without size limit
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A store keeping at most `max_bytes` of entries
+    pub fn with_capacity(max_bytes: u64) -> Self {
+        Self {
+            capacity: Some(max_bytes),
+            entries: Mutex::default(),
+        }
+    }
+
+    /// Total size of the entries in bytes
+    pub fn size(&self) -> u64 {
+        self.entries.lock().unwrap().size
+    }
+}
+
+impl fmt::Debug for MemoryCacheStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let entries = self.entries.lock().unwrap();
+        f.debug_struct("MemoryCacheStore")
+            .field("capacity", &self.capacity)
+            .field("entries", &entries.entries.len())
+            .field("size", &entries.size)
+            .finish()
+    }
+}
+
+#[async_trait]
+impl CacheStore for MemoryCacheStore {
+    async fn get(&self, key: &str) -> Result<Option<Bytes>, anyhow::Error> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.clock += 1;
+        let clock = entries.clock;
+        Ok(entries.entries.get_mut(key).map(|entry| {
+            entry.last_used = clock;
+            entry.content.clone()
+        }))
+    }
+
+    async fn put(&self, key: &str, content: Bytes) -> Result<(), anyhow::Error> {
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(replaced) = entries.entries.remove(key) {
+            entries.size -= replaced.content.len() as u64;
+        }
+        let size = content.len() as u64;
+        if self.capacity.is_some_and(|capacity| size > capacity) {
+            debug!(
+                "--> {} of {} bytes exceeds the capacity of the memory cache",
+                key, size
+            );
+            return Ok(());
+        }
+        while self
+            .capacity
+            .is_some_and(|capacity| entries.size + size > capacity)
+        {
+            let evicted = entries
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone())
+                .unwrap();
+            debug!("--> Evicting {} from the memory cache", evicted);
+            let entry = entries.entries.remove(&evicted).unwrap();
+            entries.size -= entry.content.len() as u64;
+        }
+        entries.clock += 1;
+        let last_used = entries.clock;
+        entries.size += size;
+        entries
+            .entries
+            .insert(key.to_string(), MemoryEntry { content, last_used });
+        Ok(())
+    }
+
+    async fn contains(&self, key: &str) -> Result<bool, anyhow::Error> {
+        Ok(self.entries.lock().unwrap().entries.contains_key(key))
+    }
+
+    async fn delete_dir(&self, dir: &str) -> Result<(), anyhow::Error> {
+        let prefix = format!("{}/", dir);
+        let mut entries = self.entries.lock().unwrap();
+        let mut size = entries.size;
+        entries.entries.retain(|key, entry| {
+            let keep = !key.starts_with(&prefix);
+            if !keep {
+                size -= entry.content.len() as u64;
+            }
+            keep
+        });
+        entries.size = size;
+        Ok(())
+    }
+}
+
+/// Keeps the entries in an [object_store::ObjectStore]. Wrap the store in an
+/// [object_store::prefix::PrefixStore] to keep them below a prefix
+#[cfg(feature = "object-store")]
+pub struct ObjectCacheStore {
+    store: std::sync::Arc<dyn object_store::ObjectStore>,
+}
+
+#[cfg(feature = "object-store")]
+impl ObjectCacheStore {
+    pub fn new(store: std::sync::Arc<dyn object_store::ObjectStore>) -> Self {
+        Self { store }
+    }
+}
+
+#[cfg(feature = "object-store")]
+impl fmt::Debug for ObjectCacheStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ObjectCacheStore")
+            .field("store", &self.store.to_string())
+            .finish()
+    }
+}
+
+#[cfg(feature = "object-store")]
+#[async_trait]
+impl CacheStore for ObjectCacheStore {
+    async fn get(&self, key: &str) -> Result<Option<Bytes>, anyhow::Error> {
+        match self.store.get(&object_store::path::Path::from(key)).await {
+            Ok(result) => Ok(Some(result.bytes().await?)),
+            Err(object_store::Error::NotFound { .. }) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn put(&self, key: &str, content: Bytes) -> Result<(), anyhow::Error> {
+        self.store
+            .put(&object_store::path::Path::from(key), content.into())
+            .await?;
+        Ok(())
+    }
+
+    async fn contains(&self, key: &str) -> Result<bool, anyhow::Error> {
+        match self.store.head(&object_store::path::Path::from(key)).await {
+            Ok(_) => Ok(true),
+            Err(object_store::Error::NotFound { .. }) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn delete_dir(&self, dir: &str) -> Result<(), anyhow::Error> {
+        use futures::TryStreamExt;
+        let prefix = object_store::path::Path::from(dir);
+        let entries = self
+            .store
+            .list(Some(&prefix))
+            .try_collect::<Vec<_>>()
+            .await?;
+        for entry in entries {
+            match self.store.delete(&entry.location).await {
+                Err(e) if !matches!(e, object_store::Error::NotFound { .. }) => {
+                    return Err(e.into())
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn evicts_least_recently_used() {
+        let store = MemoryCacheStore::with_capacity(10);
+        store.put("t/a", Bytes::from(vec![0; 4])).await.unwrap();
+        store.put("t/b", Bytes::from(vec![0; 4])).await.unwrap();
+        assert!(store.get("t/a").await.unwrap().is_some());
+        store.put("t/c", Bytes::from(vec![0; 4])).await.unwrap();
+        assert!(store.contains("t/a").await.unwrap());
+        assert!(!store.contains("t/b").await.unwrap());
+        assert_eq!(store.size(), 8);
+
+        store.put("t/d", Bytes::from(vec![0; 11])).await.unwrap();
+        assert!(!store.contains("t/d").await.unwrap());
+        store.delete_dir("t").await.unwrap();
+        assert_eq!(store.size(), 0);
+    }
+
+    #[tokio::test]
+    async fn rejects_keys_outside_of_root() {
+        let root = std::env::temp_dir().join("delta-sharing-cache-keys");
+        let store = LocalCacheStore::new(&root);
+        for key in ["../a", "t/../../a", "/a", "t//a", "t/./a"] {
+            assert!(store.put(key, Bytes::new()).await.is_err(), "{}", key);
+            assert!(store.get(key).await.is_err(), "{}", key);
+            assert!(store.local_path(key).is_none(), "{}", key);
+        }
+        store.put("t/a", Bytes::from_static(b"a")).await.unwrap();
+        assert_eq!(store.get("t/a").await.unwrap().unwrap(), "a");
+        store.delete_dir("t").await.unwrap();
+        assert!(!store.contains("t/a").await.unwrap());
+    }
+}
@@ -0,0 +1,126 @@
+//! Stores of the last processed table versions, so that consumers resume after a restart.
+//!
+//! A [CheckpointStore] maps keys, by default the fully qualified table names, to
+//! [Checkpoint]s. [MemoryCheckpointStore] keeps them for the lifetime of the process and
+//! [FileCheckpointStore] in a JSON file. Implement the trait to keep checkpoints in a database
+//! or any other shared storage.
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex as AsyncMutex;
+
+/// Position of a consumer in the history of a table
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// Last version of the table which was processed completely
+    pub version: i64,
+    /// Number of processed file actions of the following version, if it was only processed
+    /// partially by an [incremental read][crate::incremental]
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub index: usize,
+}
+
+fn is_zero(index: &usize) -> bool {
+    *index == 0
+}
+
+impl Checkpoint {
+    /// The checkpoint after the version was processed completely
+    pub fn version(version: i64) -> Self {
+        Self { version, index: 0 }
+    }
+}
+
+/// Loads and saves the last [Checkpoint] per key
+#[async_trait]
+pub trait CheckpointStore: Send + Sync {
+    /// The last saved checkpoint, None if no checkpoint was saved for the key
+    async fn load(&self, key: &str) -> Result<Option<Checkpoint>, anyhow::Error>;
+
+    /// Saves the checkpoint of the key
+    async fn save(&self, key: &str, checkpoint: Checkpoint) -> Result<(), anyhow::Error>;
+}
+
+/// Keeps the checkpoints in memory
+#[derive(Debug, Default)]
+pub struct MemoryCheckpointStore {
+    checkpoints: Mutex<BTreeMap<String, Checkpoint>>,
+}
+
+impl MemoryCheckpointStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl CheckpointStore for MemoryCheckpointStore {
+    async fn load(&self, key: &str) -> Result<Option<Checkpoint>, anyhow::Error> {
+        Ok(self.checkpoints.lock().unwrap().get(key).copied())
+    }
+
+    async fn save(&self, key: &str, checkpoint: Checkpoint) -> Result<(), anyhow::Error> {
+        self.checkpoints
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), checkpoint);
+        Ok(())
+    }
+}
+
+/// Keeps the checkpoints of all keys in a JSON file, which is replaced atomically on save
+#[derive(Debug)]
+pub struct FileCheckpointStore {
+    path: PathBuf,
+    lock: AsyncMutex<()>,
+}
+
+impl FileCheckpointStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            lock: AsyncMutex::new(()),
+        }
+    }
+
+    async fn read(&self) -> Result<BTreeMap<String, Checkpoint>, anyhow::Error> {
+        match fs::read_to_string(&self.path).await {
+            Ok(content) => serde_json::from_str(&content).map_err(|e| {
+                anyhow::anyhow!("Invalid checkpoint file {}: {}", self.path.display(), e)
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[async_trait]
+impl CheckpointStore for FileCheckpointStore {
+    async fn load(&self, key: &str) -> Result<Option<Checkpoint>, anyhow::Error> {
+        let _guard = self.lock.lock().await;
+        Ok(self.read().await?.get(key).copied())
+    }
+
+    async fn save(&self, key: &str, checkpoint: Checkpoint) -> Result<(), anyhow::Error> {
+        let _guard = self.lock.lock().await;
+        let mut checkpoints = self.read().await?;
+        checkpoints.insert(key.to_string(), checkpoint);
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        // The file is only replaced once the new content is on disk, so a crash leaves
+        // either the previous or the new checkpoints
+        let tmp = self.path.with_extension("tmp");
+        let mut file = fs::File::create(&tmp).await?;
+        file.write_all(serde_json::to_string_pretty(&checkpoints)?.as_bytes())
+            .await?;
+        file.sync_all().await?;
+        fs::rename(&tmp, &self.path).await?;
+        Ok(())
+    }
+}
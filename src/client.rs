@@ -1,28 +1,39 @@
+use crate::builder::{ClientBuilder, Settings};
+use crate::cache::CacheVerification;
+use crate::core::Core;
+use crate::error::Error;
+use crate::export::{ExportFormat, ExportOptions};
+use crate::incremental::{IncrementalOptions, IncrementalReader, MicroBatch};
+use crate::mirror::MirrorSummary;
 use crate::protocol::*;
-use crate::reader::*;
-use crate::utils::*;
-use parquet::data_type::AsBytes;
-use polars::prelude::{LazyFrame, Result as PolarResult};
-use reqwest::{header, header::HeaderValue};
-use serde_json::{Map, Number, Value};
-use std::collections::HashMap;
-use std::env;
-use std::{fs, io, path::Path, path::PathBuf};
-use url::Url;
-
-const METADATA_FILE: &str = "metadata.json";
+use crate::remote::ReadOptions;
+use crate::session::Session;
+use crate::watch::{VersionEvent, WatchOptions, Watcher};
+use arrow::record_batch::RecordBatch;
+use futures::stream::{self, Stream};
+use polars::prelude::{DataFrame, LazyFrame};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 /// An asynchronous Client for working with Data Sharing
+///
+/// The Client is cheap to clone and can be shared across tasks. Clones share the
+/// cached table files
+#[derive(Clone)]
 pub struct Client {
     http_client: reqwest::Client,
-    base_url: Url,
+    core: Core,
     /// Local directory path to store the downloaded cached files
     pub data_root: String,
-    cache: HashMap<String, FileCache>,
+    /// Format requested for the table metadata and file listings, see [ResponseFormat]
+    pub response_format: ResponseFormat,
+    /// Read tables whose protocol requires a newer reader version or unsupported reader
+    /// features instead of failing. The returned data may be incorrect
+    pub best_effort_read: bool,
 }
 
 impl Client {
-    /// Constructs a new async Client
+    /// Constructs a new async Client. Use [Client::builder] for more options
     /// # Arguments
     ///
     /// * `provider_config` - Delta Sharing Provider Configuration of type [ProviderConfig]
@@ -31,171 +42,91 @@ impl Client {
         provider_config: ProviderConfig,
         data_root: Option<String>,
     ) -> Result<Self, anyhow::Error> {
-        if provider_config.share_credentials_version > CREDENTIALS_VERSION {
-            panic!("'share_credentials_version' in the provider configuration is {}, which is newer than the \
-                    version {} supported by the current release. Please upgrade to a newer release.", 
-                    provider_config.share_credentials_version,
-                    CREDENTIALS_VERSION);
+        let mut builder = Self::builder(provider_config);
+        if let Some(data_root) = data_root {
+            builder = builder.data_root(data_root);
         }
-        let cache: HashMap<String, FileCache> = HashMap::new();
-        Ok(Self {
-            http_client: Self::get_client(&provider_config)?,
-            base_url: Self::build_base_url(&provider_config.endpoint)?,
-            data_root: data_root.unwrap_or(
-                env::temp_dir()
-                    .as_path()
-                    .join("delta_sharing")
-                    .to_str()
-                    .unwrap()
-                    .to_string(),
-            ),
-            cache: cache,
-        })
-    }
-
-    fn get_client(config: &ProviderConfig) -> Result<reqwest::Client, reqwest::Error> {
-        let rust_version: &str = &format!("{}", rustc_version_runtime::version());
-        let user_agent: &str = &format!("Delta-Sharing-Rust/{VERSION} Rust/{rust_version}");
-        let bearer_token = &format!("Bearer {}", config.bearer_token);
-        let mut headers = header::HeaderMap::new();
-        headers.insert(
-            header::AUTHORIZATION,
-            header::HeaderValue::from_str(bearer_token).unwrap(),
-        );
-        headers.insert(
-            header::USER_AGENT,
-            header::HeaderValue::from_str(user_agent).unwrap(),
-        );
-        reqwest::Client::builder().default_headers(headers).build()
+        builder.build()
     }
 
-    fn build_base_url(endpoint: &String) -> Result<Url, url::ParseError> {
-        let mut root_path = endpoint.trim_end_matches('/').to_string();
-        root_path.push('/');
-        Url::parse(&root_path)
+    /// Returns a [ClientBuilder] to configure the Client
+    pub fn builder(provider_config: ProviderConfig) -> ClientBuilder {
+        ClientBuilder::new(provider_config)
     }
 
-    async fn get(&self, target: &str) -> Result<String, reqwest::Error> {
-        let url = self.base_url.join(target).unwrap();
-        debug!("--> HTTP GET to: {}", &url);
-        let resp = self.http_client.get(url.as_str()).send().await?;
-        let resp_text = resp.text().await?;
-        debug!("--> Reponse body: {}", &resp_text);
-        return Ok(resp_text);
+    pub(crate) fn from_settings(
+        provider_config: ProviderConfig,
+        settings: Settings,
+        http_client: reqwest::Client,
+    ) -> Result<Self, anyhow::Error> {
+        Ok(Self {
+            core: Core::new(&provider_config, &settings)?,
+            http_client,
+            data_root: settings.data_root.unwrap_or_else(Core::default_data_root),
+            response_format: settings.response_format,
+            best_effort_read: settings.best_effort_read,
+        })
     }
 
-    async fn head(&self, target: &str, key: &str) -> Option<HeaderValue> {
-        let url = self.base_url.join(target).unwrap();
-        debug!("HTTP HEAD to: {}", &url);
-        let resp = self
-            .http_client
-            .head(url.as_str())
-            .send()
-            .await
-            .expect("Invalid request");
-        let version = resp.headers().get(key);
-        match version {
-            Some(h) => Some(h.clone()),
-            None => None,
+    fn session(&self) -> Session<'_, reqwest::Client> {
+        Session {
+            core: &self.core,
+            transport: &self.http_client,
+            range_client: &self.http_client,
+            data_root: &self.data_root,
+            response_format: self.response_format,
+            best_effort_read: self.best_effort_read,
         }
     }
 
-    async fn post(
-        &self,
-        target: &str,
-        json: &Map<String, Value>,
-    ) -> Result<String, reqwest::Error> {
-        let url = self.base_url.join(target).unwrap();
-        debug!("--> HTTP POST to: {}", &url);
-        let resp = self
-            .http_client
-            .post(url.as_str())
-            .json(json)
-            .send()
-            .await?;
-        let resp_text = resp.text().await?;
-        debug!("--> Reponse body: {}", &resp_text);
-        return Ok(resp_text);
-    }
-
-    async fn download(&self, url: String, dest_path: &Path) {
-        debug!("--> Download {} to {}", &url, dest_path.display());
-        let resp = reqwest::get(url).await.unwrap();
-        let mut out = fs::File::create(dest_path).expect("Failed to create an output file");
-        let content = resp.bytes().await.unwrap();
-        io::copy(&mut content.as_bytes(), &mut out)
-            .expect("Failed to save the content to output file");
+    pub async fn list_shares(&self) -> Result<Vec<Share>, anyhow::Error> {
+        self.session().list_shares().await
     }
 
-    pub async fn list_shares(&self) -> Result<Vec<Share>, anyhow::Error> {
-        let shares = self.get("shares").await?;
-        let parsed: ShareResponse = serde_json::from_str(&shares).expect("Invalid response");
-        return Ok(parsed.items.clone());
+    /// Returns the share with the given name, failing if it does not exist or is not accessible
+    pub async fn get_share(&self, name: &str) -> Result<Share, anyhow::Error> {
+        self.session().get_share(name).await
     }
 
     pub async fn list_schemas(&self, share: &Share) -> Result<Vec<Schema>, anyhow::Error> {
-        let schemas = self.get(&format!("shares/{}/schemas", share.name)).await?;
-        let parsed: SchemaResponse = serde_json::from_str(&schemas).expect("Invalid response");
-        return Ok(parsed.items.clone());
+        self.session().list_schemas(share).await
     }
 
     pub async fn list_tables(&self, schema: &Schema) -> Result<Vec<Table>, anyhow::Error> {
-        let tables = self
-            .get(&format!(
-                "shares/{}/schemas/{}/tables",
-                schema.share, schema.name
-            ))
-            .await?;
-        let parsed: TableResponse = serde_json::from_str(&tables).expect("Invalid response");
-        return Ok(parsed.items.clone());
+        self.session().list_tables(schema).await
     }
 
     pub async fn list_all_tables(&self, share: &Share) -> Result<Vec<Table>, anyhow::Error> {
-        let tables = self
-            .get(&format!("shares/{}/all-tables", share.name))
-            .await?;
-        let parsed: TableResponse = serde_json::from_str(&tables).expect("Invalid response");
-        return Ok(parsed.items.clone());
+        self.session().list_all_tables(share).await
     }
 
     pub async fn get_table_metadata(&self, table: &Table) -> Result<TableMetadata, anyhow::Error> {
-        let meta = self
-            .get(&format!(
-                "shares/{}/schemas/{}/tables/{}/metadata",
-                table.share, table.schema, table.name
-            ))
-            .await?;
-        let mut meta_lines = meta.lines();
-        let protocol: ProtocolResponse =
-            serde_json::from_str(meta_lines.next().expect("Invalid response"))
-                .expect("Invalid protocol");
-        let metadata: MetadataResponse =
-            serde_json::from_str(meta_lines.next().expect("Invalid response"))
-                .expect("Invalid metadata");
-        Ok(TableMetadata {
-            protocol: protocol.protocol,
-            metadata: metadata.metadata,
-        })
+        self.session().get_table_metadata(table).await
     }
 
-    pub async fn get_table_version(&self, table: &Table) -> i32 {
-        let version = self
-            .head(
-                &format!(
-                    "shares/{}/schemas/{}/tables/{}",
-                    table.share, table.schema, table.name
-                ),
-                "delta-table-version",
-            )
-            .await;
-        match version {
-            Some(v) => v
-                .to_str()
-                .expect("Invalid version number")
-                .parse::<i32>()
-                .expect("Invalid version number"),
-            None => -1,
-        }
+    /// Returns the table metadata at the given version or timestamp (in ISO 8601 format),
+    /// together with the table version it belongs to. The latest metadata is returned if
+    /// neither is given
+    pub async fn get_table_metadata_at(
+        &self,
+        table: &Table,
+        version: Option<i64>,
+        timestamp: Option<&str>,
+    ) -> Result<(i64, TableMetadata), anyhow::Error> {
+        self.session()
+            .get_table_metadata_at(table, version, timestamp)
+            .await
+    }
+
+    /// Returns the current version of the table
+    pub async fn get_table_version(&self, table: &Table) -> Result<i64, Error> {
+        self.session().table_version(table, None).await
+    }
+
+    /// Returns the version of the table at the given timestamp, in ISO 8601 format
+    /// (e.g. `2022-01-01T00:00:00Z`). Fails if the timestamp is after the latest table version
+    pub async fn get_table_version_at(&self, table: &Table, timestamp: &str) -> Result<i64, Error> {
+        self.session().table_version(table, Some(timestamp)).await
     }
 
     pub async fn list_table_files(
@@ -203,151 +134,159 @@ impl Client {
         table: &Table,
         predicate_hints: Option<Vec<String>>,
         limit_hint: Option<i32>,
-        version: Option<i32>,
+        version: Option<i64>,
     ) -> Result<TableFiles, anyhow::Error> {
-        let mut map = Map::new();
-        if predicate_hints.is_some() {
-            map.insert(
-                "predicateHints".to_string(),
-                Value::Array(
-                    predicate_hints
-                        .unwrap()
-                        .iter()
-                        .map(|s| Value::String(s.to_string()))
-                        .collect::<Vec<_>>(),
-                ),
-            );
-        }
-        if limit_hint.is_some() {
-            map.insert(
-                "limitHint".to_string(),
-                Value::Number(Number::from(limit_hint.unwrap())),
-            );
-        }
-        if version.is_some() {
-            map.insert(
-                "version".to_string(),
-                Value::Number(Number::from(version.unwrap())),
-            );
-        }
-        let response = self
-            .post(
-                &format!(
-                    "shares/{}/schemas/{}/tables/{}/query",
-                    table.share, table.schema, table.name
-                ),
-                &map,
-            )
-            .await?;
-        let mut lines = response.lines();
-        let protocol: ProtocolResponse =
-            serde_json::from_str(lines.next().expect("Invalid response"))
-                .expect("Invalid protocol");
-        let metadata: MetadataResponse =
-            serde_json::from_str(lines.next().expect("Invalid response"))
-                .expect("Invalid metadata");
-        let mut files: Vec<File> = Vec::new();
-        for l in lines {
-            let file: FileResponse = serde_json::from_str(l).expect("Invalid file info");
-            files.push(file.file.clone());
-        }
-        Ok(TableFiles {
-            metadata: TableMetadata {
-                protocol: protocol.protocol,
-                metadata: metadata.metadata,
-            },
-            files,
-        })
+        self.session()
+            .list_table_files(table, predicate_hints, limit_hint, version)
+            .await
     }
 
-    async fn download_files(&self, table_path: &PathBuf, table_files: &TableFiles) -> Vec<PathBuf> {
-        if Path::exists(&table_path) {
-            fs::remove_dir_all(&table_path).unwrap();
-        }
-        fs::create_dir_all(&table_path).unwrap();
-        let mut file_paths: Vec<PathBuf> = Vec::new();
-        for file in table_files.files.clone() {
-            let dst_path = &table_path.join(format!("{}.snappy.parquet", &file.id));
-            self.download(file.url, &dst_path).await;
-            file_paths.push(dst_path.clone());
-        }
-        file_paths.clone()
+    /// Lists the files added and removed by the versions from `starting_version` to
+    /// `ending_version`, or to the current version of the table. Requires history sharing
+    /// to be enabled for the table on the server
+    pub async fn list_table_changes(
+        &self,
+        table: &Table,
+        starting_version: i64,
+        ending_version: Option<i64>,
+    ) -> Result<TableChanges, anyhow::Error> {
+        self.session()
+            .list_table_changes(table, starting_version, ending_version)
+            .await
+    }
+
+    /// Downloads the table files unless they are cached already and returns their local
+    /// paths. Files of a [cache store][crate::cache] which is not on the local filesystem are
+    /// copied below `data_root`
+    pub async fn get_files(&self, table: &Table) -> Result<Vec<PathBuf>, anyhow::Error> {
+        self.session().get_files(table).await
     }
 
-    async fn load_cached(
+    /// Checks the cached files of the table against the checksums of the cache manifest and
+    /// downloads the missing, corrupt or outdated ones again
+    pub async fn verify_cache(&self, table: &Table) -> Result<CacheVerification, anyhow::Error> {
+        self.session().verify_cache(table).await
+    }
+
+    /// Downloads the table files unless they are cached already and returns them as a
+    /// [LazyFrame]. Files of a [cache store][crate::cache] which is not on the local
+    /// filesystem are read from memory, without writing to `data_root`
+    pub async fn get_dataframe(&self, table: &Table) -> Result<LazyFrame, anyhow::Error> {
+        self.session().get_dataframe(table).await
+    }
+
+    /// Reads the table data as Arrow record batches directly from the presigned file URLs,
+    /// without downloading the files into `data_root`.
+    ///
+    /// Only the parquet footers and the row groups and columns selected by `options` are fetched.
+    pub async fn read_record_batches(
         &self,
-        table_path: &PathBuf,
-        table_files: &TableFiles,
-    ) -> Option<Vec<PathBuf>> {
-        // Check if the files exist, load and compare the files.
-        let metadata_path = &table_path.join(METADATA_FILE);
-        if Path::exists(&metadata_path) {
-            let metadata_str = &fs::read_to_string(&metadata_path).unwrap();
-            let metadata: TableMetadata = serde_json::from_str(&metadata_str).expect(&format!(
-                "Invalid configuration in {}",
-                metadata_path.display()
-            ));
-            let mut download = metadata != table_files.metadata;
+        table: &Table,
+        options: &ReadOptions,
+    ) -> Result<Vec<RecordBatch>, anyhow::Error> {
+        self.session().read_record_batches(table, options).await
+    }
 
-            if !download {
-                let mut file_paths: Vec<PathBuf> = Vec::new();
-                for file in &table_files.files {
-                    let file_path = &table_path.join(format!("{}.snappy.parquet", &file.id));
-                    if !Path::exists(&file_path) {
-                        // File is missing, invalidate cache
-                        download = true;
-                        fs::remove_dir(&table_path).unwrap();
-                        break;
-                    }
-                    file_paths.push(file_path.clone());
-                }
-                if !download {
-                    return Some(file_paths.clone());
-                }
-            }
-        }
-        None
+    /// Reads the table data as a polars [DataFrame] directly from the presigned file URLs,
+    /// without downloading the files into `data_root`. See [Client::read_record_batches]
+    pub async fn read_dataframe(
+        &self,
+        table: &Table,
+        options: &ReadOptions,
+    ) -> Result<DataFrame, anyhow::Error> {
+        self.session().read_dataframe(table, options).await
     }
 
-    pub async fn get_files(&mut self, table: &Table) -> Result<Vec<PathBuf>, anyhow::Error> {
-        let key = table.fully_qualified_name();
-        let mut download = true;
-        let table_path = Path::new(&self.data_root).join(table.fully_qualified_name());
-        let table_files = self.list_table_files(table, None, None, None).await?;
-        if let Some(cached) = self.cache.get(&key) {
-            download = cached.table_files.metadata != table_files.metadata;
-        } else if let Some(cached) = self.load_cached(&table_path, &table_files).await {
-            download = false;
-            self.cache.insert(
-                key.clone(),
-                FileCache {
-                    table_files: table_files.clone(),
-                    file_paths: cached,
-                },
-            );
-        }
-        if download {
-            info!("--> Downloading data files to {}", &table_path.display());
-            let paths = self.download_files(&table_path, &table_files).await;
-            serde_json::to_writer(
-                &fs::File::create(&table_path.join(METADATA_FILE))?,
-                &table_files.metadata,
-            )?;
-            self.cache.insert(
-                key.clone(),
-                FileCache {
-                    table_files: table_files,
-                    file_paths: paths,
-                },
-            );
-        }
-        Ok(self.cache.get(&key).unwrap().file_paths.clone())
+    /// Exports the table into a single file at `destination`, streaming the data from the
+    /// presigned file URLs one record batch at a time. Returns the number of exported rows
+    /// # Arguments
+    ///
+    /// * `table` - The table to export
+    /// * `format` - Format of the exported file, see [ExportFormat]
+    /// * `destination` - Path of the exported file, it is overwritten if it exists
+    /// * `options` - Compression, column projection and filters, see [ExportOptions]
+    pub async fn export_table(
+        &self,
+        table: &Table,
+        format: ExportFormat,
+        destination: impl AsRef<Path>,
+        options: &ExportOptions,
+    ) -> Result<usize, anyhow::Error> {
+        self.session()
+            .export_table(table, format, destination.as_ref(), options)
+            .await
+    }
+
+    /// Mirrors the table into a local Delta table at `destination`, see [mirror][crate::mirror].
+    ///
+    /// The first call downloads all the data files of the current version of the table.
+    /// Later calls query the current version and, if it changed, only download the files
+    /// added since the previous call and commit the difference as a new version of the mirror
+    pub async fn mirror_table(
+        &self,
+        table: &Table,
+        destination: impl AsRef<Path>,
+    ) -> Result<MirrorSummary, anyhow::Error> {
+        self.session()
+            .mirror_table(table, destination.as_ref())
+            .await
+    }
+
+    /// Reads the files added and removed since the last checkpoint in bounded batches, up to
+    /// the version of the table when the read started, see [incremental][crate::incremental].
+    /// The stream ends once all the versions were read
+    pub fn read_incremental(
+        &self,
+        table: &Table,
+        options: IncrementalOptions,
+    ) -> impl Stream<Item = Result<MicroBatch, anyhow::Error>> + Send + 'static {
+        let reader = IncrementalReader::new(table, options);
+        stream::unfold((self.clone(), reader), |(client, mut reader)| async move {
+            let batch = client
+                .session()
+                .next_micro_batch(&mut reader)
+                .await
+                .transpose()?;
+            Some((batch, (client, reader)))
+        })
     }
 
-    pub async fn get_dataframe(&mut self, table: &Table) -> PolarResult<LazyFrame> {
-        self.get_files(&table).await?;
-        let table_path = Path::new(&self.data_root).join(table.fully_qualified_name());
-        load_parquet_files_as_dataframe(&table_path)
+    /// Reads the data of the files added by the batch directly from their presigned URLs
+    pub async fn read_micro_batch(
+        &self,
+        table: &Table,
+        batch: &MicroBatch,
+        options: &ReadOptions,
+    ) -> Result<Vec<RecordBatch>, anyhow::Error> {
+        self.session().read_micro_batch(table, batch, options).await
+    }
+
+    /// Polls the version of the table every `interval` and emits an event for every new
+    /// version, see [watch][crate::watch]
+    pub fn watch(
+        &self,
+        table: &Table,
+        interval: Duration,
+    ) -> impl Stream<Item = Result<VersionEvent, anyhow::Error>> + Send + 'static {
+        self.watch_with_options(table, interval, WatchOptions::default())
+    }
+
+    /// Polls the version of the table like [Client::watch], optionally listing the changes of
+    /// every version and resuming from the checkpoint of the last processed version
+    pub fn watch_with_options(
+        &self,
+        table: &Table,
+        interval: Duration,
+        options: WatchOptions,
+    ) -> impl Stream<Item = Result<VersionEvent, anyhow::Error>> + Send + 'static {
+        let watcher = Watcher::new(table, interval, options);
+        stream::unfold(
+            (self.clone(), watcher),
+            |(client, mut watcher)| async move {
+                let event = client.session().next_version_event(&mut watcher).await;
+                Some((event, (client, watcher)))
+            },
+        )
     }
 }
 
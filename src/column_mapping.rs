@@ -0,0 +1,233 @@
+//! Delta column mapping.
+//!
+//! Tables with `delta.columnMapping.mode` set to `name` or `id` store their columns in
+//! parquet under physical names (or field ids) which differ from the logical names in
+//! the table schema. See the [Delta protocol](https://github.com/delta-io/delta/blob/master/PROTOCOL.md#column-mapping).
+//!
+//! Only top level columns are renamed. Tables with renamed fields nested in structs, arrays
+//! or maps are refused instead of being read with their physical names.
+
+use crate::protocol::Metadata;
+use parquet::schema::types::TypePtr;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+const MODE_KEY: &str = "delta.columnMapping.mode";
+const PHYSICAL_NAME_KEY: &str = "delta.columnMapping.physicalName";
+const ID_KEY: &str = "delta.columnMapping.id";
+
+/// Resolves the logical column names of the parquet columns
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColumnMapping {
+    /// Parquet column names are the logical names
+    None,
+    /// Logical names keyed by the physical parquet column name
+    Name(HashMap<String, String>),
+    /// Logical names keyed by the parquet field id
+    Id(HashMap<i32, String>),
+}
+
+#[derive(Deserialize)]
+struct StructType {
+    fields: Vec<StructField>,
+}
+
+#[derive(Deserialize)]
+struct StructField {
+    name: String,
+    #[serde(rename = "type")]
+    data_type: Value,
+    #[serde(default)]
+    metadata: Map<String, Value>,
+}
+
+/// Path of the first field nested in the type whose physical name differs from its logical
+/// name. Only top level columns are renamed when reading, so such fields are not supported
+fn renamed_nested_field(data_type: &Value, path: &str) -> Option<String> {
+    let children: Vec<(&Value, String)> = match data_type.get("type").and_then(|t| t.as_str()) {
+        Some("struct") => {
+            let fields = data_type.get("fields").and_then(|f| f.as_array())?;
+            let mut children = Vec::new();
+            for field in fields {
+                let name = field
+                    .get("name")
+                    .and_then(|n| n.as_str())
+                    .unwrap_or_default();
+                let path = format!("{}.{}", path, name);
+                let physical_name = field
+                    .get("metadata")
+                    .and_then(|m| m.get(PHYSICAL_NAME_KEY))
+                    .and_then(|n| n.as_str());
+                if physical_name.is_some_and(|n| n != name) {
+                    return Some(path);
+                }
+                if let Some(data_type) = field.get("type") {
+                    children.push((data_type, path));
+                }
+            }
+            children
+        }
+        Some("array") => data_type
+            .get("elementType")
+            .map(|t| vec![(t, format!("{}.element", path))])
+            .unwrap_or_default(),
+        Some("map") => ["keyType", "valueType"]
+            .iter()
+            .filter_map(|key| {
+                data_type
+                    .get(*key)
+                    .map(|t| (t, format!("{}.{}", path, key)))
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+    children
+        .into_iter()
+        .find_map(|(data_type, path)| renamed_nested_field(data_type, &path))
+}
+
+impl ColumnMapping {
+    /// Builds the column mapping from the table configuration and schema
+    pub fn from_metadata(metadata: &Metadata) -> Result<Self, anyhow::Error> {
+        let mode = metadata
+            .configuration
+            .get(MODE_KEY)
+            .and_then(|m| m.as_str())
+            .unwrap_or("none");
+        if mode == "none" {
+            return Ok(ColumnMapping::None);
+        }
+        let schema: StructType = serde_json::from_str(&metadata.schema_string)?;
+        if let Some(path) = schema
+            .fields
+            .iter()
+            .find_map(|field| renamed_nested_field(&field.data_type, &field.name))
+        {
+            return Err(anyhow::anyhow!(
+                "Column mapping of nested field {} is not supported",
+                path
+            ));
+        }
+        match mode {
+            "name" => {
+                let mut names = HashMap::new();
+                for field in schema.fields {
+                    let physical_name = field
+                        .metadata
+                        .get(PHYSICAL_NAME_KEY)
+                        .and_then(|n| n.as_str())
+                        .ok_or_else(|| {
+                            anyhow::anyhow!("Column {} has no physical name", field.name)
+                        })?;
+                    names.insert(physical_name.to_string(), field.name);
+                }
+                Ok(ColumnMapping::Name(names))
+            }
+            "id" => {
+                let mut ids = HashMap::new();
+                for field in schema.fields {
+                    let id = field
+                        .metadata
+                        .get(ID_KEY)
+                        .and_then(|id| id.as_i64())
+                        .ok_or_else(|| anyhow::anyhow!("Column {} has no field id", field.name))?;
+                    ids.insert(id as i32, field.name);
+                }
+                Ok(ColumnMapping::Id(ids))
+            }
+            mode => Err(anyhow::anyhow!("Unsupported column mapping mode {}", mode)),
+        }
+    }
+
+    /// Returns the logical names keyed by the physical names of the given top level parquet fields.
+    /// Fields which are not renamed are left out
+    pub fn renames(&self, fields: &[TypePtr]) -> HashMap<String, String> {
+        let mut renames = HashMap::new();
+        for field in fields {
+            let info = field.get_basic_info();
+            let logical_name = match self {
+                ColumnMapping::None => None,
+                ColumnMapping::Name(names) => names.get(info.name()),
+                ColumnMapping::Id(ids) if info.has_id() => ids.get(&info.id()),
+                ColumnMapping::Id(_) => None,
+            };
+            if let Some(logical_name) = logical_name {
+                if logical_name != info.name() {
+                    renames.insert(info.name().to_string(), logical_name.clone());
+                }
+            }
+        }
+        renames
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::Format;
+    use parquet::schema::parser::parse_message_type;
+
+    fn metadata(mode: &str) -> Metadata {
+        let mut configuration = Map::new();
+        configuration.insert(MODE_KEY.to_string(), Value::String(mode.to_string()));
+        Metadata {
+            id: "1".to_string(),
+            name: None,
+            description: None,
+            format: Format {
+                provider: "parquet".to_string(),
+                options: None,
+            },
+            schema_string: r#"{"type":"struct","fields":[{"name":"a","type":"integer","nullable":true,"metadata":{"delta.columnMapping.id":1,"delta.columnMapping.physicalName":"col-1"}},{"name":"b","type":"string","nullable":true,"metadata":{"delta.columnMapping.id":2,"delta.columnMapping.physicalName":"col-2"}}]}"#.to_string(),
+            configuration,
+            partition_columns: vec![],
+            version: None,
+            size: None,
+            num_files: None,
+        }
+    }
+
+    #[test]
+    fn reject_renamed_nested_fields() {
+        let mut nested = metadata("name");
+        nested.schema_string = r#"{"type":"struct","fields":[{"name":"a","type":{"type":"struct","fields":[{"name":"b","type":"integer","nullable":true,"metadata":{"delta.columnMapping.id":2,"delta.columnMapping.physicalName":"b"}},{"name":"c","type":{"type":"array","elementType":{"type":"struct","fields":[{"name":"d","type":"string","nullable":true,"metadata":{"delta.columnMapping.id":4,"delta.columnMapping.physicalName":"col-4"}}]},"containsNull":true},"nullable":true,"metadata":{"delta.columnMapping.id":3,"delta.columnMapping.physicalName":"c"}}]},"nullable":true,"metadata":{"delta.columnMapping.id":1,"delta.columnMapping.physicalName":"col-1"}}]}"#.to_string();
+        let err = ColumnMapping::from_metadata(&nested).unwrap_err();
+        assert!(err.to_string().contains("a.c.element.d"), "{}", err);
+
+        // Nested fields keeping their names are read as they are
+        nested.schema_string = nested.schema_string.replace("col-4", "d");
+        let mapping = ColumnMapping::from_metadata(&nested).unwrap();
+        assert_eq!(
+            mapping,
+            ColumnMapping::Name(HashMap::from([("col-1".to_string(), "a".to_string())]))
+        );
+    }
+
+    #[test]
+    fn rename_by_physical_name() {
+        let mapping = ColumnMapping::from_metadata(&metadata("name")).unwrap();
+        let schema =
+            parse_message_type("message m { optional int32 col-1; optional binary col-2; }")
+                .unwrap();
+        let renames = mapping.renames(schema.get_fields());
+
+        assert_eq!(renames.len(), 2);
+        assert_eq!(renames["col-1"], "a");
+        assert_eq!(renames["col-2"], "b");
+    }
+
+    #[test]
+    fn rename_by_field_id() {
+        let mapping = ColumnMapping::from_metadata(&metadata("id")).unwrap();
+        let schema = parse_message_type(
+            "message m { optional int32 x = 2; optional binary y = 1; optional int32 z; }",
+        )
+        .unwrap();
+        let renames = mapping.renames(schema.get_fields());
+
+        assert_eq!(renames.len(), 2);
+        assert_eq!(renames["x"], "b");
+        assert_eq!(renames["y"], "a");
+    }
+}
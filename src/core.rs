@@ -0,0 +1,896 @@
+//! Transport independent part of the clients.
+//!
+//! [Session][crate::session::Session] drives the protocol calls and the local cache of both
+//! clients through [Core], which builds the requests and parses the responses without
+//! performing any network I/O.
+
+use crate::builder::Settings;
+use crate::cache::{CacheStore, LocalCacheStore};
+use crate::column_mapping::ColumnMapping;
+use crate::credentials::{CredentialProvider, StaticToken};
+use crate::deletion_vector;
+use crate::error::Error;
+use crate::metrics::{Metrics, NoMetrics};
+use crate::protocol::*;
+use crate::reader::*;
+use crate::remote::DownloadMetrics;
+use crate::telemetry::Span;
+use crate::utils::*;
+use bytes::Bytes;
+use polars::prelude::LazyFrame;
+use reqwest::{header, header::HeaderMap, Method, StatusCode};
+use roaring::RoaringTreemap;
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Number, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Mutex as AsyncMutex;
+use url::Url;
+
+const METADATA_FILE: &str = "metadata.json";
+const PARQUET_MAGIC: [u8; 4] = *b"PAR1";
+
+/// A request to the sharing server
+#[derive(Clone)]
+pub(crate) struct Request {
+    /// Name of the protocol call, reported in the spans and metrics
+    pub operation: &'static str,
+    /// Fully qualified name of the table the request is about
+    pub table: Option<String>,
+    pub method: Method,
+    pub url: Url,
+    pub headers: HeaderMap,
+    pub body: Option<Map<String, Value>>,
+}
+
+/// A successful response of the sharing server
+pub(crate) struct Response {
+    pub headers: HeaderMap,
+    pub body: String,
+}
+
+/// The listed files of a table after they were synced with the cache store
+pub(crate) struct SyncedFiles {
+    pub table_files: TableFiles,
+    /// Content of the files, only kept if the cache store is not on the local filesystem
+    pub buffers: Option<Vec<Bytes>>,
+    /// Ids of the files which were downloaded because they were missing, corrupt or outdated
+    pub downloaded: Vec<String>,
+}
+
+/// State shared by all the clones of a client
+#[derive(Clone)]
+pub(crate) struct Core {
+    base_url: Url,
+    headers: HeaderMap,
+    credentials: Arc<dyn CredentialProvider>,
+    log_bodies: bool,
+    metrics: Arc<dyn Metrics>,
+    cache: Arc<Mutex<HashMap<String, FileCache>>>,
+    cache_store: Option<Arc<dyn CacheStore>>,
+    table_locks: Arc<Mutex<HashMap<String, Arc<AsyncMutex<()>>>>>,
+}
+
+impl Core {
+    pub fn new(
+        provider_config: &ProviderConfig,
+        settings: &Settings,
+    ) -> Result<Self, anyhow::Error> {
+        if provider_config.share_credentials_version > CREDENTIALS_VERSION {
+            panic!("'share_credentials_version' in the provider configuration is {}, which is newer than the \
+                    version {} supported by the current release. Please upgrade to a newer release.",
+                    provider_config.share_credentials_version,
+                    CREDENTIALS_VERSION);
+        }
+        Ok(Self {
+            base_url: Self::build_base_url(&provider_config.endpoint)?,
+            headers: Self::build_headers(settings)?,
+            credentials: match &settings.credential_provider {
+                Some(provider) => provider.clone(),
+                None => Arc::new(StaticToken::new(provider_config.bearer_token.clone())),
+            },
+            log_bodies: settings.log_response_bodies,
+            metrics: match &settings.metrics {
+                Some(metrics) => metrics.clone(),
+                None => Arc::new(NoMetrics),
+            },
+            cache: Arc::new(Mutex::new(HashMap::new())),
+            cache_store: settings.cache_store.clone(),
+            table_locks: Arc::new(Mutex::new(HashMap::new())),
+        })
+    }
+
+    fn build_base_url(endpoint: &str) -> Result<Url, url::ParseError> {
+        let mut root_path = endpoint.trim_end_matches('/').to_string();
+        root_path.push('/');
+        Url::parse(&root_path)
+    }
+
+    /// Headers sent with every request to the sharing server. They are set per request rather
+    /// than on the HTTP client, so they are never sent with the file downloads
+    fn build_headers(settings: &Settings) -> Result<HeaderMap, anyhow::Error> {
+        let rust_version: &str = &format!("{}", rustc_version_runtime::version());
+        let mut user_agent = format!("Delta-Sharing-Rust/{VERSION} Rust/{rust_version}");
+        if let Some(suffix) = &settings.user_agent_suffix {
+            user_agent.push(' ');
+            user_agent.push_str(suffix);
+        }
+        let mut headers = settings.headers.clone();
+        headers.insert(
+            header::USER_AGENT,
+            header::HeaderValue::from_str(&user_agent)?,
+        );
+        Ok(headers)
+    }
+
+    /// Provider of the bearer token, asked on every request to the sharing server
+    pub fn credentials(&self) -> &dyn CredentialProvider {
+        self.credentials.as_ref()
+    }
+
+    /// Starts measuring the request, see [Span]
+    pub fn request_span(&self, request: &Request) -> Span {
+        Span::request(request, self.metrics.clone())
+    }
+
+    /// Starts measuring the download of a file of the table, see [Span]
+    pub fn download_span(&self, table: &Table, url: &str) -> Span {
+        Span::download(&table.fully_qualified_name(), url, self.metrics.clone())
+    }
+
+    /// Reports the range requests of the table readers as downloads
+    pub fn download_metrics(&self, table: &Table) -> DownloadMetrics {
+        DownloadMetrics {
+            table: table.fully_qualified_name(),
+            metrics: self.metrics.clone(),
+        }
+    }
+
+    pub fn metrics(&self) -> &dyn Metrics {
+        self.metrics.as_ref()
+    }
+
+    /// Logs a response of the sharing server. The body holds presigned file URLs and table
+    /// metadata, so only its size is logged unless body logging is enabled
+    pub fn log_response(&self, response: &Response) {
+        if self.log_bodies {
+            debug!("--> Response body: {}", &response.body);
+        } else {
+            debug!("--> Response body of {} bytes", response.body.len());
+        }
+    }
+
+    /// Default local directory for the cached files
+    pub fn default_data_root() -> String {
+        env::temp_dir()
+            .as_path()
+            .join("delta_sharing")
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    fn request(
+        &self,
+        operation: &'static str,
+        method: Method,
+        target: &str,
+        query: &[(&str, String)],
+    ) -> Request {
+        let mut url = self.base_url.join(target).unwrap();
+        if !query.is_empty() {
+            url.query_pairs_mut().extend_pairs(query);
+        }
+        Request {
+            operation,
+            table: None,
+            method,
+            url,
+            headers: self.headers.clone(),
+            body: None,
+        }
+    }
+
+    fn table_request(
+        &self,
+        operation: &'static str,
+        method: Method,
+        table: &Table,
+        endpoint: &str,
+        query: &[(&str, String)],
+    ) -> Request {
+        let mut target = format!(
+            "shares/{}/schemas/{}/tables/{}",
+            table.share, table.schema, table.name
+        );
+        if !endpoint.is_empty() {
+            target.push('/');
+            target.push_str(endpoint);
+        }
+        let mut request = self.request(operation, method, &target, query);
+        request.table = Some(table.fully_qualified_name());
+        request
+    }
+
+    pub fn list_shares_request(&self, page_token: Option<&str>) -> Request {
+        self.request(
+            "list_shares",
+            Method::GET,
+            "shares",
+            &page_query(page_token),
+        )
+    }
+
+    pub fn get_share_request(&self, name: &str) -> Request {
+        self.request("get_share", Method::GET, &format!("shares/{}", name), &[])
+    }
+
+    pub fn list_schemas_request(&self, share: &Share, page_token: Option<&str>) -> Request {
+        self.request(
+            "list_schemas",
+            Method::GET,
+            &format!("shares/{}/schemas", share.name),
+            &page_query(page_token),
+        )
+    }
+
+    pub fn list_tables_request(&self, schema: &Schema, page_token: Option<&str>) -> Request {
+        let target = format!("shares/{}/schemas/{}/tables", schema.share, schema.name);
+        self.request("list_tables", Method::GET, &target, &page_query(page_token))
+    }
+
+    pub fn list_all_tables_request(&self, share: &Share, page_token: Option<&str>) -> Request {
+        self.request(
+            "list_all_tables",
+            Method::GET,
+            &format!("shares/{}/all-tables", share.name),
+            &page_query(page_token),
+        )
+    }
+
+    pub fn table_metadata_request(
+        &self,
+        table: &Table,
+        version: Option<i64>,
+        timestamp: Option<&str>,
+    ) -> Result<Request, anyhow::Error> {
+        let mut query = Vec::new();
+        match (version, timestamp) {
+            (Some(_), Some(_)) => {
+                return Err(anyhow::anyhow!(
+                    "Only one of version and timestamp can be given"
+                ))
+            }
+            (Some(version), None) => query.push(("version", version.to_string())),
+            (None, Some(timestamp)) => query.push(("timestamp", timestamp.to_string())),
+            (None, None) => {}
+        }
+        Ok(self.table_request(
+            "query_table_metadata",
+            Method::GET,
+            table,
+            "metadata",
+            &query,
+        ))
+    }
+
+    pub fn table_version_request(&self, table: &Table, timestamp: Option<&str>) -> Request {
+        let query = match timestamp {
+            Some(timestamp) => vec![("startingTimestamp", timestamp.to_string())],
+            None => vec![],
+        };
+        self.table_request("query_table_version", Method::GET, table, "version", &query)
+    }
+
+    /// Servers implementing older versions of the protocol only return the table version
+    /// for HEAD requests of the table
+    pub fn table_head_request(&self, table: &Table) -> Request {
+        self.table_request("query_table_version", Method::HEAD, table, "", &[])
+    }
+
+    pub fn list_table_files_request(
+        &self,
+        table: &Table,
+        predicate_hints: Option<Vec<String>>,
+        limit_hint: Option<i32>,
+        version: Option<i64>,
+    ) -> Request {
+        let mut map = Map::new();
+        if let Some(predicate_hints) = predicate_hints {
+            map.insert(
+                "predicateHints".to_string(),
+                Value::Array(
+                    predicate_hints
+                        .iter()
+                        .map(|s| Value::String(s.to_string()))
+                        .collect::<Vec<_>>(),
+                ),
+            );
+        }
+        if let Some(limit_hint) = limit_hint {
+            map.insert(
+                "limitHint".to_string(),
+                Value::Number(Number::from(limit_hint)),
+            );
+        }
+        if let Some(version) = version {
+            map.insert("version".to_string(), Value::Number(Number::from(version)));
+        }
+        let mut request = self.table_request("query_table", Method::POST, table, "query", &[]);
+        request.body = Some(map);
+        request
+    }
+
+    /// Lists the add and remove actions of the versions from `starting_version` to
+    /// `ending_version`, or to the current version
+    pub fn list_table_changes_request(
+        &self,
+        table: &Table,
+        starting_version: i64,
+        ending_version: Option<i64>,
+    ) -> Request {
+        let mut map = Map::new();
+        map.insert(
+            "startingVersion".to_string(),
+            Value::Number(Number::from(starting_version)),
+        );
+        if let Some(ending_version) = ending_version {
+            map.insert(
+                "endingVersion".to_string(),
+                Value::Number(Number::from(ending_version)),
+            );
+        }
+        let mut request = self.table_request("query_table", Method::POST, table, "query", &[]);
+        request.body = Some(map);
+        request
+    }
+
+    pub fn parse<T: DeserializeOwned>(response: &Response) -> Result<T, anyhow::Error> {
+        serde_json::from_str(&response.body).map_err(|e| anyhow::anyhow!("Invalid response: {}", e))
+    }
+
+    /// Error of a request which failed with the status of `error`. A 404 with an error body
+    /// of the server is a missing share, schema or table, see [Error::NotFound]
+    pub fn status_error(error: reqwest::Error, body: &str) -> Error {
+        if error.status() != Some(StatusCode::NOT_FOUND) {
+            return error.into();
+        }
+        match serde_json::from_str::<Value>(body) {
+            Ok(Value::Object(body)) if body.contains_key("errorCode") => {
+                let message = body
+                    .get("message")
+                    .or_else(|| body.get("errorCode"))
+                    .and_then(Value::as_str)
+                    .unwrap_or_default();
+                Error::NotFound(message.to_string())
+            }
+            _ => error.into(),
+        }
+    }
+
+    pub fn parse_table_metadata(response: &Response) -> Result<TableMetadata, anyhow::Error> {
+        let mut lines = response.body.lines();
+        Self::parse_metadata_lines(&mut lines)
+    }
+
+    fn parse_metadata_lines<'a>(
+        lines: &mut impl Iterator<Item = &'a str>,
+    ) -> Result<TableMetadata, anyhow::Error> {
+        let mut next_line = || {
+            lines
+                .next()
+                .ok_or_else(|| anyhow::anyhow!("Invalid response"))
+        };
+        let protocol: ProtocolResponse = serde_json::from_str(next_line()?)
+            .map_err(|e| anyhow::anyhow!("Invalid protocol: {}", e))?;
+        let metadata: MetadataResponse = serde_json::from_str(next_line()?)
+            .map_err(|e| anyhow::anyhow!("Invalid metadata: {}", e))?;
+        Ok(TableMetadata {
+            protocol: protocol.protocol.into(),
+            metadata: metadata.metadata.into(),
+        })
+    }
+
+    pub fn parse_table_files(response: &Response) -> Result<TableFiles, anyhow::Error> {
+        let version = parse_table_version(&response.headers).ok();
+        let mut lines = response.body.lines();
+        let metadata = Self::parse_metadata_lines(&mut lines)?;
+        let mut files: Vec<File> = Vec::new();
+        for l in lines.filter(|l| !l.trim().is_empty()) {
+            let line: FileResponse =
+                serde_json::from_str(l).map_err(|e| anyhow::anyhow!("Invalid file info: {}", e))?;
+            match line.into_change() {
+                Some(FileChange::Add(file)) => files.push(file),
+                _ => debug!(
+                    "--> Skipping unsupported action {}",
+                    serde_json::from_str::<Map<String, Value>>(l)
+                        .map(|a| a.keys().cloned().collect::<Vec<_>>().join(", "))
+                        .unwrap_or_default()
+                ),
+            }
+        }
+        Ok(TableFiles {
+            metadata,
+            files,
+            version,
+        })
+    }
+
+    pub fn parse_table_changes(response: &Response) -> Result<TableChanges, anyhow::Error> {
+        let mut lines = response.body.lines();
+        let metadata = Self::parse_metadata_lines(&mut lines)?;
+        let mut changes = Vec::new();
+        for l in lines.filter(|l| !l.trim().is_empty()) {
+            let line: FileResponse =
+                serde_json::from_str(l).map_err(|e| anyhow::anyhow!("Invalid file info: {}", e))?;
+            match line.into_change() {
+                Some(change) => changes.push(change),
+                None => debug!(
+                    "--> Skipping unsupported action {}",
+                    serde_json::from_str::<Map<String, Value>>(l)
+                        .map(|a| a.keys().cloned().collect::<Vec<_>>().join(", "))
+                        .unwrap_or_default()
+                ),
+            }
+        }
+        if changes.iter().any(|c| c.version().is_none()) {
+            return Err(anyhow::anyhow!(
+                "Invalid response: file action without version"
+            ));
+        }
+        // Servers return the actions grouped by version, the sort keeps their order within a
+        // version
+        changes.sort_by_key(|c| c.version());
+        Ok(TableChanges { metadata, changes })
+    }
+
+    /// Fails with [Error] if the table protocol is not supported, unless `best_effort_read` is set
+    pub fn check_protocol(protocol: &Protocol, best_effort_read: bool) -> Result<(), Error> {
+        match protocol.check_supported() {
+            Err(e) if best_effort_read => {
+                warn!("--> {}, reading on a best-effort basis", e);
+                Ok(())
+            }
+            res => res,
+        }
+    }
+
+    /// Replaces the presigned URLs of the files with the ones from a newer listing
+    pub fn apply_refreshed_urls(
+        table: &Table,
+        table_files: &mut TableFiles,
+        refreshed: TableFiles,
+    ) -> Result<(), anyhow::Error> {
+        for file in table_files.files.iter_mut() {
+            let refreshed_file = refreshed
+                .files
+                .iter()
+                .find(|f| f.id == file.id)
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "File {} is no longer listed for table {}",
+                        file.id,
+                        table.fully_qualified_name()
+                    )
+                })?;
+            file.url = refreshed_file.url.clone();
+            file.expiration_timestamp = refreshed_file.expiration_timestamp;
+        }
+        Ok(())
+    }
+
+    pub fn table_path(data_root: &str, table: &Table) -> PathBuf {
+        Path::new(data_root).join(table.fully_qualified_name())
+    }
+
+    pub fn file_path(table_path: &Path, file: &File) -> PathBuf {
+        table_path.join(format!("{}.snappy.parquet", &file.id))
+    }
+
+    /// Returns the lock serializing the downloads of the table, so that concurrent reads
+    /// of the same table share a single download
+    pub fn table_lock(&self, table: &Table) -> Arc<AsyncMutex<()>> {
+        let mut locks = self.table_locks.lock().unwrap();
+        locks
+            .entry(table.fully_qualified_name())
+            .or_default()
+            .clone()
+    }
+
+    /// Store of the downloaded table files, a directory below `data_root` unless another
+    /// store was set on the builder
+    pub fn cache_store(&self, data_root: &str) -> Arc<dyn CacheStore> {
+        match &self.cache_store {
+            Some(store) => store.clone(),
+            None => Arc::new(LocalCacheStore::new(data_root)),
+        }
+    }
+
+    /// Key of the directory of the cached files of the table, see [cache][crate::cache]
+    pub fn table_key(table: &Table) -> String {
+        table.fully_qualified_name()
+    }
+
+    /// Key of the manifest with the metadata and the checksums of the cached files of the table
+    pub fn manifest_key(table: &Table) -> String {
+        format!("{}/{}", Self::table_key(table), METADATA_FILE)
+    }
+
+    pub fn file_key(table: &Table, file: &File) -> String {
+        format!("{}/{}.snappy.parquet", Self::table_key(table), file.id)
+    }
+
+    /// Checksums of the files of the listing if all of them were cached or verified by this
+    /// process. The latest listing is kept, deletion vectors may change without changing the
+    /// metadata
+    pub fn cached_checksums(
+        &self,
+        table: &Table,
+        table_files: &TableFiles,
+    ) -> Option<BTreeMap<String, String>> {
+        let mut cache = self.cache.lock().unwrap();
+        match cache.get_mut(&table.fully_qualified_name()) {
+            Some(cached)
+                if cached.table_files.metadata == table_files.metadata
+                    && table_files
+                        .files
+                        .iter()
+                        .all(|file| cached.checksums.contains_key(&file.id)) =>
+            {
+                cached.table_files = table_files.clone();
+                Some(cached.checksums.clone())
+            }
+            _ => None,
+        }
+    }
+
+    /// Checksums of the cached files by file id, None if the manifest was written for other
+    /// table metadata
+    pub fn parse_manifest(
+        manifest: &[u8],
+        table_files: &TableFiles,
+    ) -> Option<BTreeMap<String, String>> {
+        match serde_json::from_slice::<CacheManifest>(manifest) {
+            Ok(manifest) if manifest.metadata == table_files.metadata => Some(manifest.checksums),
+            Ok(_) => None,
+            Err(e) => {
+                warn!(
+                    "--> Invalid cache manifest, downloading the files again: {}",
+                    e
+                );
+                None
+            }
+        }
+    }
+
+    pub fn manifest(
+        table_files: &TableFiles,
+        checksums: &BTreeMap<String, String>,
+    ) -> Result<Bytes, anyhow::Error> {
+        let manifest = CacheManifest {
+            metadata: table_files.metadata.clone(),
+            checksums: checksums.clone(),
+        };
+        Ok(serde_json::to_vec(&manifest)?.into())
+    }
+
+    /// Records the files of the listing as cached, so that the cache store is only checked
+    /// again once the table metadata changes
+    pub fn record_cached(
+        &self,
+        table: &Table,
+        table_files: &TableFiles,
+        checksums: BTreeMap<String, String>,
+    ) {
+        self.cache.lock().unwrap().insert(
+            table.fully_qualified_name(),
+            FileCache {
+                table_files: table_files.clone(),
+                checksums,
+            },
+        );
+    }
+
+    /// Checksum of the content of a file, recorded in the cache manifest
+    pub fn checksum(content: &[u8]) -> String {
+        format!("{:08x}", crc32fast::hash(content))
+    }
+
+    /// Checks that a downloaded data file is complete: it must have the size of the listing
+    /// and start and end with the parquet magic, with a footer fitting into the file
+    pub fn verify_download(file: &File, content: &[u8]) -> Result<(), anyhow::Error> {
+        if content.len() as i64 != file.size {
+            return Err(anyhow::anyhow!(
+                "File {} has {} bytes instead of {}",
+                file.id,
+                content.len(),
+                file.size
+            ));
+        }
+        let len = content.len();
+        // The footer length must point inside the file, after the leading magic number
+        let valid = len >= 12
+            && content[..4] == PARQUET_MAGIC
+            && content[len - 4..] == PARQUET_MAGIC
+            && u32::from_le_bytes(content[len - 8..len - 4].try_into().unwrap()) as usize
+                <= len - 12;
+        if !valid {
+            return Err(anyhow::anyhow!(
+                "File {} is not a valid parquet file",
+                file.id
+            ));
+        }
+        Ok(())
+    }
+
+    /// Whether the cached content of a file matches the listing and the checksum of the
+    /// cache manifest
+    pub fn is_intact(file: &File, content: &[u8], checksum: &str) -> bool {
+        content.len() as i64 == file.size && Self::checksum(content) == checksum
+    }
+
+    /// Writes the content of the table files below `data_root` and returns their paths
+    pub fn write_files(
+        data_root: &str,
+        table: &Table,
+        table_files: &TableFiles,
+        buffers: Vec<Bytes>,
+    ) -> Result<Vec<PathBuf>, anyhow::Error> {
+        let table_path = Self::table_path(data_root, table);
+        fs::create_dir_all(&table_path)?;
+        let mut file_paths = Vec::with_capacity(buffers.len());
+        for (file, content) in table_files.files.iter().zip(buffers) {
+            let path = Self::file_path(&table_path, file);
+            fs::write(&path, content)?;
+            file_paths.push(path);
+        }
+        Ok(file_paths)
+    }
+
+    /// Builds the dataframe from the cached files of the listing, other files in the table
+    /// directory are ignored
+    /// # Arguments
+    ///
+    /// * `table_path` - Local directory of the cached files
+    /// * `table_files` - The listed table files
+    /// * `deleted_rows` - Rows marked as deleted by the deletion vector of each file, in listing order
+    pub fn load_dataframe(
+        table_path: &Path,
+        table_files: &TableFiles,
+        deleted_rows: Vec<Option<RoaringTreemap>>,
+    ) -> Result<LazyFrame, anyhow::Error> {
+        let column_mapping = ColumnMapping::from_metadata(&table_files.metadata.metadata)?;
+        let files_with_deletions = table_files
+            .files
+            .iter()
+            .map(|f| Self::file_path(table_path, f))
+            .zip(deleted_rows)
+            .collect::<Vec<_>>();
+        Ok(load_parquet_files(&files_with_deletions, &column_mapping)?)
+    }
+
+    /// Builds the dataframe from the content of the cached files, in listing order, for cache
+    /// stores which are not on the local filesystem
+    pub fn load_dataframe_from_buffers(
+        table_files: &TableFiles,
+        buffers: Vec<Bytes>,
+        deleted_rows: Vec<Option<RoaringTreemap>>,
+    ) -> Result<LazyFrame, anyhow::Error> {
+        let column_mapping = ColumnMapping::from_metadata(&table_files.metadata.metadata)?;
+        let files_with_deletions = buffers.into_iter().zip(deleted_rows).collect::<Vec<_>>();
+        Ok(load_parquet_buffers(
+            &files_with_deletions,
+            &column_mapping,
+        )?)
+    }
+
+    /// Returns the URL to fetch a deletion vector from, or None if it is stored inline
+    pub fn deletion_vector_url(
+        descriptor: &DeletionVectorDescriptor,
+    ) -> Result<Option<&str>, anyhow::Error> {
+        match descriptor.storage_type.as_str() {
+            "i" => Ok(None),
+            "p" => Ok(Some(&descriptor.path_or_inline_dv)),
+            storage_type => Err(anyhow::anyhow!(
+                "Unsupported deletion vector storage type {}",
+                storage_type
+            )),
+        }
+    }
+
+    /// Decodes a deletion vector, `content` is the content fetched from its URL if it is not inline
+    pub fn decode_deletion_vector(
+        descriptor: &DeletionVectorDescriptor,
+        content: Option<&[u8]>,
+    ) -> Result<RoaringTreemap, anyhow::Error> {
+        match content {
+            Some(content) => deletion_vector::decode_stored(descriptor, content),
+            None => deletion_vector::decode_inline(descriptor),
+        }
+    }
+}
+
+/// Query parameters of a page of the share, schema and table listings
+fn page_query(page_token: Option<&str>) -> Vec<(&'static str, String)> {
+    let mut query = vec![("maxResults", LIST_PAGE_SIZE.to_string())];
+    if let Some(page_token) = page_token {
+        query.push(("pageToken", page_token.to_string()));
+    }
+    query
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn core() -> Core {
+        let config = ProviderConfig {
+            share_credentials_version: 1,
+            endpoint: "https://sharing.delta.io/delta-sharing".to_string(),
+            bearer_token: "token".to_string(),
+        };
+        Core::new(&config, &Settings::default()).unwrap()
+    }
+
+    fn table() -> Table {
+        Table {
+            name: "table_1".to_string(),
+            share: "share_1".to_string(),
+            schema: "schema_1".to_string(),
+            share_id: None,
+            id: None,
+        }
+    }
+
+    #[test]
+    fn build_requests() {
+        let core = core();
+
+        let request = core.table_version_request(&table(), Some("2022-01-01T00:00:00Z"));
+        assert_eq!(request.method, Method::GET);
+        assert_eq!(
+            request.url.as_str(),
+            "https://sharing.delta.io/delta-sharing/shares/share_1/schemas/schema_1/tables/table_1/version?startingTimestamp=2022-01-01T00%3A00%3A00Z"
+        );
+
+        let request = core.list_table_files_request(&table(), None, Some(10), Some(3));
+        assert_eq!(request.method, Method::POST);
+        assert_eq!(
+            request.url.path(),
+            "/delta-sharing/shares/share_1/schemas/schema_1/tables/table_1/query"
+        );
+        assert_eq!(
+            Value::Object(request.body.unwrap()),
+            serde_json::json!({ "limitHint": 10, "version": 3 })
+        );
+
+        let request = core.list_table_changes_request(&table(), 2, Some(4));
+        assert_eq!(
+            Value::Object(request.body.unwrap()),
+            serde_json::json!({ "startingVersion": 2, "endingVersion": 4 })
+        );
+
+        assert!(core
+            .table_metadata_request(&table(), Some(1), Some("2022-01-01T00:00:00Z"))
+            .is_err());
+    }
+
+    #[test]
+    fn parse_table_files() {
+        let mut headers = HeaderMap::new();
+        headers.insert(TABLE_VERSION_HEADER, "5".parse().unwrap());
+        let response = Response {
+            headers,
+            body: r#"{ "protocol": { "minReaderVersion": 1 } }
+                {"metaData": { "id": "1", "format": { "provider": "parquet" }, "schemaString": "{}", "partitionColumns": [], "configuration": {} } }
+                {"file": { "url": "https://example.com/1", "id": "1", "partitionValues": {}, "size": 10 } }"#
+                .to_string(),
+        };
+        let table_files = Core::parse_table_files(&response).unwrap();
+
+        assert_eq!(table_files.version, Some(5));
+        assert_eq!(table_files.metadata.metadata.id, "1");
+        assert_eq!(table_files.files.len(), 1);
+        assert_eq!(table_files.files[0].url, "https://example.com/1");
+
+        let response = Response {
+            headers: HeaderMap::new(),
+            body: r#"{ "protocol": { "minReaderVersion": 1 } }"#.to_string(),
+        };
+        assert!(Core::parse_table_files(&response).is_err());
+
+        // The size of the Delta actions is required
+        let delta_response = |add: &str| Response {
+            headers: HeaderMap::new(),
+            body: format!(
+                r#"{{ "protocol": {{ "deltaProtocol": {{ "minReaderVersion": 1, "minWriterVersion": 2 }} }} }}
+                {{"metaData": {{ "deltaMetadata": {{ "id": "1", "format": {{ "provider": "parquet" }}, "schemaString": "{{}}", "partitionColumns": [], "configuration": {{}} }} }} }}
+                {{"file": {{ "id": "1", "deltaSingleAction": {{ "add": {} }} }} }}"#,
+                add
+            ),
+        };
+        let response = delta_response(
+            r#"{ "path": "https://example.com/1", "partitionValues": {}, "size": 10 }"#,
+        );
+        assert_eq!(
+            Core::parse_table_files(&response).unwrap().files[0].size,
+            10
+        );
+        let response =
+            delta_response(r#"{ "path": "https://example.com/1", "partitionValues": {} }"#);
+        assert!(Core::parse_table_files(&response).is_err());
+    }
+
+    #[test]
+    fn parse_table_changes() {
+        let response = Response {
+            headers: HeaderMap::new(),
+            body: r#"{ "protocol": { "minReaderVersion": 1 } }
+                {"metaData": { "id": "1", "format": { "provider": "parquet" }, "schemaString": "{}", "partitionColumns": [], "configuration": {} } }
+                {"add": { "url": "https://example.com/2", "id": "2", "partitionValues": {}, "size": 10, "version": 3, "timestamp": 1 } }
+                {"remove": { "url": "https://example.com/1", "id": "1", "partitionValues": {}, "size": 10, "version": 3, "timestamp": 1 } }
+                {"cdf": { "url": "https://example.com/3", "id": "3", "partitionValues": {}, "size": 10, "version": 3, "timestamp": 1 } }
+                {"add": { "url": "https://example.com/1", "id": "1", "partitionValues": {}, "size": 10, "version": 2, "timestamp": 0 } }"#
+                .to_string(),
+        };
+        let changes = Core::parse_table_changes(&response).unwrap();
+        let summary = changes
+            .changes
+            .iter()
+            .map(|c| match c {
+                FileChange::Add(f) => ("add", f.id.as_str(), c.version()),
+                FileChange::Remove(f) => ("remove", f.id.as_str(), c.version()),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            [
+                ("add", "1", Some(2)),
+                ("add", "2", Some(3)),
+                ("remove", "1", Some(3))
+            ]
+        );
+    }
+
+    #[test]
+    fn verify_download() {
+        let mut content = b"PAR1".to_vec();
+        content.extend_from_slice(&[0; 8]);
+        content.extend_from_slice(&8u32.to_le_bytes());
+        content.extend_from_slice(b"PAR1");
+        let file = File {
+            id: "1".to_string(),
+            url: "https://example.com/1".to_string(),
+            partition_values: Map::new(),
+            size: content.len() as i64,
+            stats: None,
+            version: None,
+            timestamp: None,
+            expiration_timestamp: None,
+            deletion_vector: None,
+        };
+        assert!(Core::verify_download(&file, &content).is_ok());
+        assert!(Core::is_intact(&file, &content, &Core::checksum(&content)));
+
+        let truncated = &content[..content.len() - 1];
+        assert!(Core::verify_download(&file, truncated).is_err());
+        assert!(!Core::is_intact(
+            &file,
+            truncated,
+            &Core::checksum(&content)
+        ));
+
+        let mut corrupt = content.clone();
+        corrupt[0] = b'X';
+        assert!(Core::verify_download(&file, &corrupt).is_err());
+        assert!(!Core::is_intact(&file, &corrupt, &Core::checksum(&content)));
+
+        let mut footer = content.clone();
+        let len = footer.len();
+        footer[len - 8..len - 4].copy_from_slice(&100u32.to_le_bytes());
+        assert!(Core::verify_download(&file, &footer).is_err());
+    }
+}
@@ -0,0 +1,227 @@
+//! Credentials used to authenticate with the sharing server.
+//!
+//! The clients ask their [CredentialProvider] for the bearer token on every request, so the
+//! token can be rotated without rebuilding the client and losing its cached files. When the
+//! server rejects a token with `401 Unauthorized`, the provider is asked for a fresh token and
+//! the request is retried once.
+
+use crate::protocol::ProviderConfig;
+use anyhow::Context;
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::env;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::fs;
+use tokio::sync::Mutex as AsyncMutex;
+
+/// OAuth tokens are renewed this long before they expire
+const TOKEN_EXPIRY_MARGIN: Duration = Duration::from_secs(30);
+
+/// Supplies the bearer token sent with each request to the sharing server
+#[async_trait]
+pub trait CredentialProvider: Send + Sync {
+    /// Returns the token to authenticate the next request with
+    async fn token(&self) -> Result<String, anyhow::Error>;
+
+    /// Called after the server rejected the `rejected` token. Returns the token to retry the
+    /// request with, or None if there is no newer one. By default the provider is asked for
+    /// the current token, which is used if it differs from the rejected one
+    async fn refresh(&self, rejected: &str) -> Result<Option<String>, anyhow::Error> {
+        let token = self.token().await?;
+        Ok((token != rejected).then_some(token))
+    }
+}
+
+/// A token which never changes, e.g. the `bearerToken` of a [ProviderConfig]
+pub struct StaticToken {
+    token: String,
+}
+
+impl StaticToken {
+    pub fn new(token: impl Into<String>) -> Self {
+        Self {
+            token: token.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl CredentialProvider for StaticToken {
+    async fn token(&self) -> Result<String, anyhow::Error> {
+        Ok(self.token.clone())
+    }
+}
+
+/// A token read from an environment variable on each request
+pub struct EnvToken {
+    var: String,
+}
+
+impl EnvToken {
+    /// # Arguments
+    ///
+    /// * `var` - Name of the environment variable holding the token
+    pub fn new(var: impl Into<String>) -> Self {
+        Self { var: var.into() }
+    }
+}
+
+#[async_trait]
+impl CredentialProvider for EnvToken {
+    async fn token(&self) -> Result<String, anyhow::Error> {
+        env::var(&self.var).with_context(|| format!("Failed to read the token from {}", self.var))
+    }
+}
+
+/// The `bearerToken` of a profile file, which is read again whenever the file is modified
+pub struct ProfileFileToken {
+    path: PathBuf,
+    loaded: Mutex<Option<(SystemTime, String)>>,
+}
+
+impl ProfileFileToken {
+    /// # Arguments
+    ///
+    /// * `path` - Path of the profile file, in the format of [ProviderConfig]
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            loaded: Mutex::new(None),
+        }
+    }
+
+    async fn load(&self, force: bool) -> Result<String, anyhow::Error> {
+        let context = || format!("Failed to read the profile file {}", self.path.display());
+        let modified = fs::metadata(&self.path)
+            .await
+            .and_then(|m| m.modified())
+            .with_context(context)?;
+        if let Some((loaded_modified, token)) = &*self.loaded.lock().unwrap() {
+            if !force && *loaded_modified == modified {
+                return Ok(token.clone());
+            }
+        }
+        debug!("--> Loading the token from {}", self.path.display());
+        let content = fs::read_to_string(&self.path).await.with_context(context)?;
+        let config: ProviderConfig = serde_json::from_str(&content).with_context(context)?;
+        *self.loaded.lock().unwrap() = Some((modified, config.bearer_token.clone()));
+        Ok(config.bearer_token)
+    }
+}
+
+#[async_trait]
+impl CredentialProvider for ProfileFileToken {
+    async fn token(&self) -> Result<String, anyhow::Error> {
+        self.load(false).await
+    }
+
+    async fn refresh(&self, rejected: &str) -> Result<Option<String>, anyhow::Error> {
+        // The file may have been rewritten within the resolution of its modification time
+        let token = self.load(true).await?;
+        Ok((token != rejected).then_some(token))
+    }
+}
+
+#[derive(Deserialize)]
+struct OAuthTokenResponse {
+    access_token: String,
+    expires_in: Option<u64>,
+}
+
+/// Tokens obtained with the OAuth 2.0 client credentials flow. A token is reused until it
+/// is about to expire or is rejected by the sharing server
+pub struct OAuthClientCredentials {
+    http_client: reqwest::Client,
+    token_endpoint: String,
+    client_id: String,
+    client_secret: String,
+    scope: Option<String>,
+    current: AsyncMutex<Option<(String, Option<Instant>)>>,
+}
+
+impl OAuthClientCredentials {
+    /// # Arguments
+    ///
+    /// * `token_endpoint` - URL of the token endpoint of the authorization server
+    /// * `client_id` - Client ID registered with the authorization server
+    /// * `client_secret` - Secret of the client
+    pub fn new(
+        token_endpoint: impl Into<String>,
+        client_id: impl Into<String>,
+        client_secret: impl Into<String>,
+    ) -> Self {
+        Self {
+            http_client: reqwest::Client::new(),
+            token_endpoint: token_endpoint.into(),
+            client_id: client_id.into(),
+            client_secret: client_secret.into(),
+            scope: None,
+            current: AsyncMutex::new(None),
+        }
+    }
+
+    /// Scope requested with the token
+    pub fn with_scope(mut self, scope: impl Into<String>) -> Self {
+        self.scope = Some(scope.into());
+        self
+    }
+
+    /// Uses the given reqwest client to request the tokens
+    pub fn with_http_client(mut self, http_client: reqwest::Client) -> Self {
+        self.http_client = http_client;
+        self
+    }
+
+    async fn request_token(&self) -> Result<(String, Option<Instant>), anyhow::Error> {
+        debug!("--> Requesting a token from {}", &self.token_endpoint);
+        let mut form = vec![("grant_type", "client_credentials")];
+        if let Some(scope) = &self.scope {
+            form.push(("scope", scope));
+        }
+        let resp: OAuthTokenResponse = self
+            .http_client
+            .post(&self.token_endpoint)
+            .basic_auth(&self.client_id, Some(&self.client_secret))
+            .form(&form)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+            .context("Failed to parse the token response")?;
+        let expiry = resp
+            .expires_in
+            .map(|secs| Instant::now() + Duration::from_secs(secs));
+        Ok((resp.access_token, expiry))
+    }
+}
+
+#[async_trait]
+impl CredentialProvider for OAuthClientCredentials {
+    async fn token(&self) -> Result<String, anyhow::Error> {
+        let mut current = self.current.lock().await;
+        if let Some((token, expiry)) = &*current {
+            if expiry.is_none_or(|e| e > Instant::now() + TOKEN_EXPIRY_MARGIN) {
+                return Ok(token.clone());
+            }
+        }
+        let (token, expiry) = self.request_token().await?;
+        *current = Some((token.clone(), expiry));
+        Ok(token)
+    }
+
+    async fn refresh(&self, rejected: &str) -> Result<Option<String>, anyhow::Error> {
+        let mut current = self.current.lock().await;
+        // Another request may have replaced the rejected token already
+        if let Some((token, _)) = &*current {
+            if token != rejected {
+                return Ok(Some(token.clone()));
+            }
+        }
+        let (token, expiry) = self.request_token().await?;
+        *current = Some((token.clone(), expiry));
+        Ok((token != rejected).then_some(token))
+    }
+}
@@ -0,0 +1,158 @@
+//! Decoding of Delta deletion vectors.
+//!
+//! A deletion vector marks rows of a data file as deleted without rewriting the file.
+//! See the [Delta protocol](https://github.com/delta-io/delta/blob/master/PROTOCOL.md#deletion-vectors)
+//! for the serialization formats handled here.
+
+use crate::protocol::DeletionVectorDescriptor;
+use roaring::RoaringTreemap;
+use std::io::Cursor;
+
+/// Magic number at the start of every serialized deletion vector
+const MAGIC_NUMBER: u32 = 1681511377;
+
+const Z85_ALPHABET: &[u8] =
+    b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ.-:+=^!/*?&<>()[]{}@%$#";
+
+/// Decodes a deletion vector stored inline in its descriptor
+pub fn decode_inline(
+    descriptor: &DeletionVectorDescriptor,
+) -> Result<RoaringTreemap, anyhow::Error> {
+    let bytes = z85_decode(&descriptor.path_or_inline_dv)?;
+    let size = descriptor.size_in_bytes as usize;
+    if bytes.len() < size {
+        return Err(anyhow::anyhow!(
+            "Inline deletion vector is {} bytes, expected {}",
+            bytes.len(),
+            size
+        ));
+    }
+    deserialize(&bytes[..size])
+}
+
+/// Decodes a deletion vector from the content of the file it is stored in
+pub fn decode_stored(
+    descriptor: &DeletionVectorDescriptor,
+    content: &[u8],
+) -> Result<RoaringTreemap, anyhow::Error> {
+    let offset = descriptor.offset.unwrap_or(1) as usize;
+    let size = descriptor.size_in_bytes as usize;
+    // The data is prefixed with its size and followed by its CRC-32 checksum, both 4 bytes
+    if content.len() < offset + 4 + size + 4 {
+        return Err(anyhow::anyhow!(
+            "Deletion vector file is too short ({} bytes)",
+            content.len()
+        ));
+    }
+    let stored_size = u32::from_be_bytes(content[offset..offset + 4].try_into()?) as usize;
+    if stored_size != size {
+        return Err(anyhow::anyhow!(
+            "Deletion vector size is {}, expected {}",
+            stored_size,
+            size
+        ));
+    }
+    let data = &content[offset + 4..offset + 4 + size];
+    let checksum = u32::from_be_bytes(content[offset + 4 + size..offset + 8 + size].try_into()?);
+    if crc32fast::hash(data) != checksum {
+        return Err(anyhow::anyhow!("Deletion vector checksum mismatch"));
+    }
+    deserialize(data)
+}
+
+fn deserialize(bytes: &[u8]) -> Result<RoaringTreemap, anyhow::Error> {
+    if bytes.len() < 4 || u32::from_le_bytes(bytes[..4].try_into()?) != MAGIC_NUMBER {
+        return Err(anyhow::anyhow!("Invalid deletion vector magic number"));
+    }
+    Ok(RoaringTreemap::deserialize_from(Cursor::new(&bytes[4..]))?)
+}
+
+pub(crate) fn z85_decode(encoded: &str) -> Result<Vec<u8>, anyhow::Error> {
+    let encoded = encoded.as_bytes();
+    if !encoded.len().is_multiple_of(5) {
+        return Err(anyhow::anyhow!(
+            "Z85 encoded data length must be a multiple of 5"
+        ));
+    }
+    let mut decoded = Vec::with_capacity(encoded.len() / 5 * 4);
+    for chunk in encoded.chunks(5) {
+        let mut value: u64 = 0;
+        for c in chunk {
+            let digit = Z85_ALPHABET
+                .iter()
+                .position(|a| a == c)
+                .ok_or_else(|| anyhow::anyhow!("Invalid Z85 character {}", *c as char))?;
+            value = value * 85 + digit as u64;
+        }
+        let value = u32::try_from(value)?;
+        decoded.extend_from_slice(&value.to_be_bytes());
+    }
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn z85_encode(bytes: &[u8]) -> String {
+        let mut encoded = String::new();
+        for chunk in bytes.chunks(4) {
+            let mut value = u32::from_be_bytes(chunk.try_into().unwrap()) as u64;
+            let mut digits = [0u8; 5];
+            for d in digits.iter_mut().rev() {
+                *d = Z85_ALPHABET[(value % 85) as usize];
+                value /= 85;
+            }
+            encoded.push_str(std::str::from_utf8(&digits).unwrap());
+        }
+        encoded
+    }
+
+    fn serialize(rows: &[u64]) -> Vec<u8> {
+        let bitmap = rows.iter().copied().collect::<RoaringTreemap>();
+        let mut bytes = MAGIC_NUMBER.to_le_bytes().to_vec();
+        bitmap.serialize_into(&mut bytes).unwrap();
+        bytes
+    }
+
+    fn descriptor(
+        storage_type: &str,
+        path_or_inline_dv: String,
+        size: usize,
+    ) -> DeletionVectorDescriptor {
+        DeletionVectorDescriptor {
+            storage_type: storage_type.to_string(),
+            path_or_inline_dv,
+            offset: Some(1),
+            size_in_bytes: size as i32,
+            cardinality: 2,
+        }
+    }
+
+    #[test]
+    fn decode_inline_deletion_vector() {
+        let mut bytes = serialize(&[0, 2]);
+        let size = bytes.len();
+        bytes.resize(size.div_ceil(4) * 4, 0);
+        let dv = decode_inline(&descriptor("i", z85_encode(&bytes), size)).unwrap();
+
+        assert_eq!(dv.iter().collect::<Vec<_>>(), vec![0, 2]);
+    }
+
+    #[test]
+    fn decode_stored_deletion_vector() {
+        let bytes = serialize(&[1, 3]);
+        let mut content = vec![1u8];
+        content.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+        content.extend_from_slice(&bytes);
+        content.extend_from_slice(&crc32fast::hash(&bytes).to_be_bytes());
+        let descriptor = descriptor("p", "".to_string(), bytes.len());
+        let dv = decode_stored(&descriptor, &content).unwrap();
+
+        assert_eq!(dv.iter().collect::<Vec<_>>(), vec![1, 3]);
+
+        let len = content.len();
+        content[len - 1] ^= 0xff;
+        assert!(decode_stored(&descriptor, &content).is_err());
+    }
+}
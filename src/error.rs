@@ -0,0 +1,31 @@
+//! Errors reported by the Delta Sharing clients
+
+use thiserror::Error;
+
+/// Errors which callers may want to handle specifically.
+///
+/// Most client methods return [anyhow::Error], use [anyhow::Error::downcast_ref] to match these.
+#[derive(Error, Debug)]
+pub enum Error {
+    /// The table requires a Delta reader version newer than supported by this client
+    #[error("Table requires reader version {required}, but the client supports up to version {supported}")]
+    UnsupportedReaderVersion { required: i32, supported: i32 },
+    /// The table requires Delta reader features not supported by this client
+    #[error("Table requires unsupported reader features: {}", .0.join(", "))]
+    UnsupportedReaderFeatures(Vec<String>),
+    /// The server response has no table version header
+    #[error("Response has no table version header")]
+    MissingTableVersion,
+    /// The server returned a table version which is not a number
+    #[error("Invalid table version {0}")]
+    InvalidTableVersion(String),
+    /// The share, schema or table does not exist or is not accessible
+    #[error("Not found: {0}")]
+    NotFound(String),
+    /// The credential provider failed to supply a token
+    #[error("Failed to obtain credentials: {0:#}")]
+    Credentials(anyhow::Error),
+    /// The request to the sharing server failed
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+}
@@ -0,0 +1,508 @@
+//! Export of shared tables into a single CSV, JSON Lines or Parquet file.
+//!
+//! The table is read with HTTP range requests like the [remote][crate::remote] readers and
+//! written out one record batch at a time, so the table never has to fit into memory.
+//!
+//! The exported columns follow the table schema: partition columns are filled in from the
+//! partition values of the files, and columns written with another type or missing from
+//! older files are cast or left null. The file is written next to `destination` and only
+//! renamed to it once complete.
+
+use crate::protocol::{File, Metadata};
+use arrow::array::{new_null_array, ArrayRef, BooleanArray, DecimalBuilder, StringArray};
+use arrow::compute::{cast, filter_record_batch};
+use arrow::datatypes::{DataType, Field, Schema as ArrowSchema, SchemaRef, TimeUnit};
+use arrow::error::ArrowError;
+use arrow::record_batch::RecordBatch;
+use flate2::write::GzEncoder;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression as ParquetCompression;
+use parquet::file::properties::WriterProperties;
+use serde_json::Value;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Format of the exported file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// Comma separated values with a header row
+    Csv,
+    /// One JSON object per row and line
+    NdJson,
+    /// A single Parquet file
+    Parquet,
+}
+
+/// Compression of the exported file. CSV and JSON Lines files only support [Compression::Gzip]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Snappy,
+    Zstd,
+}
+
+/// Selects the rows to export from a record batch
+pub type RowFilter =
+    Arc<dyn Fn(&RecordBatch) -> Result<BooleanArray, ArrowError> + Send + Sync + 'static>;
+
+/// Options of [Client::export_table][crate::Client::export_table]
+#[derive(Clone, Default)]
+pub struct ExportOptions {
+    /// Compression of the exported file, uncompressed if None
+    pub compression: Option<Compression>,
+    /// Names of the columns to export. All the columns are exported if None
+    pub columns: Option<Vec<String>>,
+    /// SQL boolean expressions sent to the server to skip files, see the `predicateHints` of
+    /// the protocol. The server applies them on a best-effort basis, use `filter` to drop rows
+    pub predicate_hints: Option<Vec<String>>,
+    /// Selects the rows to export. All the rows are exported if None
+    pub filter: Option<RowFilter>,
+}
+
+impl fmt::Debug for ExportOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExportOptions")
+            .field("compression", &self.compression)
+            .field("columns", &self.columns)
+            .field("predicate_hints", &self.predicate_hints)
+            .field("filter", &self.filter.as_ref().map(|_| "<filter>"))
+            .finish()
+    }
+}
+
+/// Output file of the CSV and JSON Lines exports
+enum Sink {
+    Plain(BufWriter<fs::File>),
+    Gzip(GzEncoder<BufWriter<fs::File>>),
+}
+
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Sink::Plain(w) => w.write(buf),
+            Sink::Gzip(w) => w.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Sink::Plain(w) => w.flush(),
+            Sink::Gzip(w) => w.flush(),
+        }
+    }
+}
+
+impl Sink {
+    fn finish(self) -> io::Result<()> {
+        let mut out = match self {
+            Sink::Plain(w) => w,
+            Sink::Gzip(w) => w.finish()?,
+        };
+        out.flush()
+    }
+}
+
+enum Output {
+    Csv {
+        sink: Sink,
+        header_written: bool,
+    },
+    NdJson(Sink),
+    Parquet {
+        file: Option<fs::File>,
+        properties: WriterProperties,
+        writer: Option<ArrowWriter<fs::File>>,
+    },
+}
+
+/// The exported file while it is written, removed unless it is moved to its destination
+struct PendingFile {
+    path: PathBuf,
+    destination: PathBuf,
+}
+
+impl PendingFile {
+    fn create(destination: &Path) -> Result<(Self, fs::File), anyhow::Error> {
+        let name = destination
+            .file_name()
+            .ok_or_else(|| anyhow::anyhow!("Invalid export destination {:?}", destination))?;
+        let path = destination.with_file_name(format!(".{}.partial", name.to_string_lossy()));
+        let file = fs::File::create(&path)?;
+        let pending = Self {
+            path,
+            destination: destination.to_path_buf(),
+        };
+        Ok((pending, file))
+    }
+
+    fn persist(self) -> io::Result<()> {
+        fs::rename(&self.path, &self.destination)
+    }
+}
+
+impl Drop for PendingFile {
+    fn drop(&mut self) {
+        // Fails once the file was renamed to its destination
+        fs::remove_file(&self.path).ok();
+    }
+}
+
+/// Writes record batches into the exported file
+pub(crate) struct Exporter {
+    output: Output,
+    pending: PendingFile,
+    /// Schema of the exported columns
+    schema: SchemaRef,
+    partition_columns: Vec<String>,
+    filter: Option<RowFilter>,
+    rows: usize,
+}
+
+impl Exporter {
+    /// Creates the exported file for the table with the given metadata
+    pub fn create(
+        destination: &Path,
+        format: ExportFormat,
+        options: &ExportOptions,
+        metadata: &Metadata,
+    ) -> Result<Self, anyhow::Error> {
+        let schema = export_schema(metadata, options.columns.as_deref())?;
+        let (pending, file) = PendingFile::create(destination)?;
+        let output = match format {
+            ExportFormat::Csv => Output::Csv {
+                sink: Self::text_sink(file, format, options.compression)?,
+                header_written: false,
+            },
+            ExportFormat::NdJson => {
+                Output::NdJson(Self::text_sink(file, format, options.compression)?)
+            }
+            ExportFormat::Parquet => {
+                let compression = match options.compression {
+                    None => ParquetCompression::UNCOMPRESSED,
+                    Some(Compression::Gzip) => ParquetCompression::GZIP,
+                    Some(Compression::Snappy) => ParquetCompression::SNAPPY,
+                    Some(Compression::Zstd) => ParquetCompression::ZSTD,
+                };
+                Output::Parquet {
+                    file: Some(file),
+                    properties: WriterProperties::builder()
+                        .set_compression(compression)
+                        .build(),
+                    writer: None,
+                }
+            }
+        };
+        Ok(Self {
+            output,
+            pending,
+            schema,
+            partition_columns: metadata.partition_columns.clone(),
+            filter: options.filter.clone(),
+            rows: 0,
+        })
+    }
+
+    /// Columns to read from the data files, None to read all of them
+    pub fn file_columns(&self, columns: Option<&[String]>) -> Option<Vec<String>> {
+        columns.map(|columns| {
+            columns
+                .iter()
+                .filter(|c| !self.partition_columns.contains(c))
+                .cloned()
+                .collect()
+        })
+    }
+
+    /// Adds the partition columns to a batch read from `file` and casts its columns to the
+    /// exported schema
+    fn conform(&self, file: &File, batch: &RecordBatch) -> Result<RecordBatch, anyhow::Error> {
+        let rows = batch.num_rows();
+        let columns = self
+            .schema
+            .fields()
+            .iter()
+            .map(|field| {
+                if self.partition_columns.contains(field.name()) {
+                    let value = file.partition_values.get(field.name());
+                    return partition_column(value, field.data_type(), rows);
+                }
+                Ok(match batch.schema().index_of(field.name()) {
+                    Ok(i) if batch.column(i).data_type() == field.data_type() => {
+                        batch.column(i).clone()
+                    }
+                    Ok(i) => cast(batch.column(i), field.data_type())?,
+                    Err(_) => new_null_array(field.data_type(), rows),
+                })
+            })
+            .collect::<Result<Vec<_>, anyhow::Error>>()?;
+        Ok(RecordBatch::try_new(self.schema.clone(), columns)?)
+    }
+
+    fn text_sink(
+        file: fs::File,
+        format: ExportFormat,
+        compression: Option<Compression>,
+    ) -> Result<Sink, anyhow::Error> {
+        match compression {
+            None => Ok(Sink::Plain(BufWriter::new(file))),
+            Some(Compression::Gzip) => Ok(Sink::Gzip(GzEncoder::new(
+                BufWriter::new(file),
+                flate2::Compression::default(),
+            ))),
+            Some(compression) => Err(anyhow::anyhow!(
+                "{:?} compression is not supported for {:?} exports",
+                compression,
+                format
+            )),
+        }
+    }
+
+    /// Writes a batch read from `file`
+    pub fn write(&mut self, file: &File, batch: RecordBatch) -> Result<(), anyhow::Error> {
+        let batch = self.conform(file, &batch)?;
+        let batch = match &self.filter {
+            Some(filter) => filter_record_batch(&batch, &filter(&batch)?)?,
+            None => batch,
+        };
+        self.rows += batch.num_rows();
+        match &mut self.output {
+            Output::Csv {
+                sink,
+                header_written,
+            } => {
+                let mut writer = arrow::csv::WriterBuilder::new()
+                    .has_headers(!*header_written)
+                    .build(sink);
+                writer.write(&batch)?;
+                *header_written = true;
+            }
+            Output::NdJson(sink) => {
+                let mut writer = arrow::json::LineDelimitedWriter::new(sink);
+                writer.write_batches(&[batch])?;
+                writer.finish()?;
+            }
+            Output::Parquet {
+                file,
+                properties,
+                writer,
+            } => {
+                if writer.is_none() {
+                    *writer = Some(ArrowWriter::try_new(
+                        file.take().unwrap(),
+                        self.schema.clone(),
+                        Some(properties.clone()),
+                    )?);
+                }
+                writer.as_mut().unwrap().write(&batch)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Completes the exported file and returns the number of exported rows
+    pub fn finish(self) -> Result<usize, anyhow::Error> {
+        match self.output {
+            Output::Csv { sink, .. } | Output::NdJson(sink) => sink.finish()?,
+            Output::Parquet {
+                file,
+                properties,
+                writer,
+            } => {
+                let mut writer = match (writer, file) {
+                    (Some(writer), _) => writer,
+                    (None, Some(file)) => {
+                        ArrowWriter::try_new(file, self.schema, Some(properties))?
+                    }
+                    (None, None) => unreachable!(),
+                };
+                writer.close()?;
+            }
+        }
+        self.pending.persist()?;
+        Ok(self.rows)
+    }
+}
+
+/// Arrow schema of the table, restricted to the selected columns
+fn export_schema(
+    metadata: &Metadata,
+    columns: Option<&[String]>,
+) -> Result<SchemaRef, anyhow::Error> {
+    let schema: Value = serde_json::from_str(&metadata.schema_string)?;
+    let fields = struct_fields(&schema)?;
+    let fields = match columns {
+        Some(columns) => columns
+            .iter()
+            .map(|name| {
+                fields
+                    .iter()
+                    .find(|f| f.name() == name)
+                    .cloned()
+                    .ok_or_else(|| anyhow::anyhow!("Column {} not found in the table schema", name))
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        None => fields,
+    };
+    Ok(Arc::new(ArrowSchema::new(fields)))
+}
+
+fn struct_fields(struct_type: &Value) -> Result<Vec<Field>, anyhow::Error> {
+    struct_type["fields"]
+        .as_array()
+        .ok_or_else(|| anyhow::anyhow!("Invalid struct type {}", struct_type))?
+        .iter()
+        .map(|field| {
+            Ok(Field::new(
+                field["name"].as_str().unwrap_or_default(),
+                arrow_type(&field["type"])?,
+                field["nullable"].as_bool().unwrap_or(true),
+            ))
+        })
+        .collect()
+}
+
+/// Arrow type of a type of the Delta table schema
+fn arrow_type(data_type: &Value) -> Result<DataType, anyhow::Error> {
+    let primitive = match data_type.as_str() {
+        Some(primitive) => primitive,
+        None => {
+            return match data_type["type"].as_str() {
+                Some("struct") => Ok(DataType::Struct(struct_fields(data_type)?)),
+                Some("array") => Ok(DataType::List(Box::new(Field::new(
+                    "element",
+                    arrow_type(&data_type["elementType"])?,
+                    data_type["containsNull"].as_bool().unwrap_or(true),
+                )))),
+                Some("map") => {
+                    let entries = vec![
+                        Field::new("key", arrow_type(&data_type["keyType"])?, false),
+                        Field::new(
+                            "value",
+                            arrow_type(&data_type["valueType"])?,
+                            data_type["valueContainsNull"].as_bool().unwrap_or(true),
+                        ),
+                    ];
+                    Ok(DataType::Map(
+                        Box::new(Field::new("entries", DataType::Struct(entries), false)),
+                        false,
+                    ))
+                }
+                _ => Err(anyhow::anyhow!("Unsupported type {}", data_type)),
+            };
+        }
+    };
+    Ok(match primitive {
+        "string" => DataType::Utf8,
+        "long" => DataType::Int64,
+        "integer" => DataType::Int32,
+        "short" => DataType::Int16,
+        "byte" => DataType::Int8,
+        "float" => DataType::Float32,
+        "double" => DataType::Float64,
+        "boolean" => DataType::Boolean,
+        "binary" => DataType::Binary,
+        "date" => DataType::Date32,
+        "timestamp" => DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".to_string())),
+        "timestamp_ntz" => DataType::Timestamp(TimeUnit::Microsecond, None),
+        decimal => {
+            let (precision, scale) = decimal
+                .strip_prefix("decimal(")
+                .and_then(|d| d.strip_suffix(')'))
+                .and_then(|d| d.split_once(','))
+                .ok_or_else(|| anyhow::anyhow!("Unsupported type {}", decimal))?;
+            DataType::Decimal(precision.trim().parse()?, scale.trim().parse()?)
+        }
+    })
+}
+
+/// Column of `rows` copies of a partition value, which the protocol sends as a string
+fn partition_column(
+    value: Option<&Value>,
+    data_type: &DataType,
+    rows: usize,
+) -> Result<ArrayRef, anyhow::Error> {
+    let value = value.and_then(Value::as_str);
+    match data_type {
+        DataType::Boolean => {
+            let value = value.map(|v| v.eq_ignore_ascii_case("true"));
+            return Ok(Arc::new(BooleanArray::from(vec![value; rows])));
+        }
+        DataType::Decimal(precision, scale) => {
+            let value = value
+                .map(|v| parse_decimal(v, *precision, *scale))
+                .transpose()?;
+            let mut builder = DecimalBuilder::new(rows, *precision, *scale);
+            for _ in 0..rows {
+                match value {
+                    Some(value) => builder.append_value(value)?,
+                    None => builder.append_null()?,
+                }
+            }
+            return Ok(Arc::new(builder.finish()));
+        }
+        _ => {}
+    }
+    let strings: ArrayRef = Arc::new(StringArray::from(vec![value; rows]));
+    // Strings are only cast to nanosecond timestamps
+    let strings = match data_type {
+        DataType::Timestamp(_, _) => {
+            cast(&strings, &DataType::Timestamp(TimeUnit::Nanosecond, None))?
+        }
+        _ => strings,
+    };
+    Ok(cast(&strings, data_type)?)
+}
+
+/// Unscaled value of a decimal written as a string, e.g. 12345 for "123.45" with the scale 2
+fn parse_decimal(value: &str, precision: usize, scale: usize) -> Result<i128, anyhow::Error> {
+    let invalid = || anyhow::anyhow!("Invalid decimal({},{}) value {}", precision, scale, value);
+    let (negative, digits) = match value.trim().strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, value.trim().trim_start_matches('+')),
+    };
+    let (integer, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    // Digits beyond the scale can only be zeros
+    let (fraction, dropped) = fraction.split_at(fraction.len().min(scale));
+    if integer.is_empty() && fraction.is_empty() || dropped.bytes().any(|b| b != b'0') {
+        return Err(invalid());
+    }
+    let padding = std::iter::repeat_n('0', scale - fraction.len());
+    let mut unscaled: i128 = 0;
+    for c in integer.chars().chain(fraction.chars()).chain(padding) {
+        let digit = c.to_digit(10).ok_or_else(invalid)?;
+        unscaled = unscaled
+            .checked_mul(10)
+            .and_then(|v| v.checked_add(digit as i128))
+            .ok_or_else(invalid)?;
+    }
+    Ok(if negative { -unscaled } else { unscaled })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Array, DecimalArray};
+    use serde_json::json;
+
+    #[test]
+    fn decimal_partition_values() {
+        let value = json!("12345678901234567890.123456789012345678");
+        let column = partition_column(Some(&value), &DataType::Decimal(38, 18), 2).unwrap();
+        let column = column.as_any().downcast_ref::<DecimalArray>().unwrap();
+        assert_eq!(column.len(), 2);
+        assert_eq!(
+            column.value(1),
+            12_345_678_901_234_567_890_123_456_789_012_345_678
+        );
+        assert_eq!(parse_decimal("-1.5", 5, 2).unwrap(), -150);
+        assert_eq!(parse_decimal("7.100", 5, 2).unwrap(), 710);
+        assert!(parse_decimal("1.234", 5, 2).is_err());
+        assert!(parse_decimal("1e3", 5, 2).is_err());
+        assert!(partition_column(Some(&json!("12345")), &DataType::Decimal(5, 2), 1).is_err());
+        let column = partition_column(None, &DataType::Decimal(5, 2), 1).unwrap();
+        assert!(column.is_null(0));
+    }
+}
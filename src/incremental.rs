@@ -0,0 +1,395 @@
+//! Incremental reads of the changes of shared tables, in bounded micro-batches.
+//!
+//! Like the streaming source of the Spark connector,
+//! [Client::read_incremental][crate::Client::read_incremental] lists the files added and
+//! removed since a starting version with the `startingVersion` and `endingVersion` options of
+//! the query API, up to the version of the table when the read started. The actions are
+//! returned in [MicroBatch]es of at most [IncrementalOptions::max_files_per_batch] files and
+//! [IncrementalOptions::max_bytes_per_batch] bytes, which may end in the middle of a version.
+//!
+//! With a [CheckpointStore], the end of a batch is saved when the next batch is requested and
+//! when the read completes, so every action is delivered at least once and a restarted read
+//! resumes after the last processed batch, from
+//! [IncrementalOptions::starting_version] otherwise.
+
+use crate::checkpoint::{Checkpoint, CheckpointStore};
+use crate::protocol::{File, FileChange, Table, TableChanges, TableFiles, TableMetadata};
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Number of files of a batch if [IncrementalOptions::max_files_per_batch] is not set, the
+/// default of `maxFilesPerTrigger` in the Spark connector
+const DEFAULT_MAX_FILES_PER_BATCH: usize = 1000;
+
+/// Options of [Client::read_incremental][crate::Client::read_incremental]
+#[derive(Clone, Default)]
+pub struct IncrementalOptions {
+    /// First version to read if there is no checkpoint, 0 by default
+    pub starting_version: i64,
+    /// Maximum number of file actions of a batch, 1000 by default
+    pub max_files_per_batch: Option<usize>,
+    /// Maximum total size of the files of a batch. A batch always contains at least one file
+    pub max_bytes_per_batch: Option<u64>,
+    /// Store of the end of the last processed batch, to resume after a restart
+    pub checkpoint_store: Option<Arc<dyn CheckpointStore>>,
+    /// Key of the checkpoints, the fully qualified table name by default
+    pub checkpoint_key: Option<String>,
+}
+
+impl fmt::Debug for IncrementalOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IncrementalOptions")
+            .field("starting_version", &self.starting_version)
+            .field("max_files_per_batch", &self.max_files_per_batch)
+            .field("max_bytes_per_batch", &self.max_bytes_per_batch)
+            .field(
+                "checkpoint_store",
+                &self.checkpoint_store.as_ref().map(|_| "<checkpoint store>"),
+            )
+            .field("checkpoint_key", &self.checkpoint_key)
+            .finish()
+    }
+}
+
+/// File actions of consecutive table versions
+#[derive(Debug, Clone)]
+pub struct MicroBatch {
+    /// Position before the batch
+    pub start: Checkpoint,
+    /// Position after the batch, saved to the checkpoint store once the batch was processed
+    pub end: Checkpoint,
+    /// Metadata of the table when the actions were listed
+    pub metadata: TableMetadata,
+    /// Actions ordered by version
+    pub changes: Vec<FileChange>,
+}
+
+impl MicroBatch {
+    /// Files added by the batch
+    pub fn added(&self) -> impl Iterator<Item = &File> {
+        self.changes.iter().filter_map(|c| match c {
+            FileChange::Add(file) => Some(file),
+            FileChange::Remove(_) => None,
+        })
+    }
+
+    /// Files removed by the batch
+    pub fn removed(&self) -> impl Iterator<Item = &File> {
+        self.changes.iter().filter_map(|c| match c {
+            FileChange::Remove(file) => Some(file),
+            FileChange::Add(_) => None,
+        })
+    }
+
+    /// The added files as a listing, to read their data
+    pub(crate) fn added_files(&self) -> TableFiles {
+        TableFiles {
+            metadata: self.metadata.clone(),
+            files: self.added().cloned().collect(),
+            version: Some(self.end.version),
+        }
+    }
+}
+
+/// State of an incremental read, independent of the client listing the changes
+pub(crate) struct IncrementalReader {
+    pub table: Table,
+    pub options: IncrementalOptions,
+    key: String,
+    /// Whether the checkpoint was loaded
+    started: bool,
+    /// End of the last returned batch
+    position: Checkpoint,
+    /// Version of the table when the read started
+    ending_version: Option<i64>,
+    /// Listed actions after the position, with their index within their version
+    buffer: VecDeque<(FileChange, usize)>,
+    /// Last version of the listing in the buffer
+    buffered_version: i64,
+    metadata: Option<TableMetadata>,
+    /// End of the last returned batch, saved when the next batch is requested
+    unsaved: Option<Checkpoint>,
+}
+
+impl IncrementalReader {
+    pub fn new(table: &Table, options: IncrementalOptions) -> Self {
+        let key = options
+            .checkpoint_key
+            .clone()
+            .unwrap_or_else(|| table.fully_qualified_name());
+        let position = Checkpoint::version(options.starting_version - 1);
+        Self {
+            table: table.clone(),
+            options,
+            key,
+            started: false,
+            position,
+            ending_version: None,
+            buffer: VecDeque::new(),
+            buffered_version: position.version,
+            metadata: None,
+            unsaved: None,
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn checkpoint_store(&self) -> Option<Arc<dyn CheckpointStore>> {
+        self.options.checkpoint_store.clone()
+    }
+
+    /// Whether the checkpoint has to be loaded before the first listing
+    pub fn needs_checkpoint(&self) -> bool {
+        !self.started && self.options.checkpoint_store.is_some()
+    }
+
+    pub fn start(&mut self, checkpoint: Option<Checkpoint>) {
+        self.started = true;
+        if let Some(checkpoint) = checkpoint {
+            self.position = checkpoint;
+            self.buffered_version = checkpoint.version;
+        }
+    }
+
+    /// The end of the last returned batch, which has been processed once the next batch is
+    /// requested
+    pub fn take_unsaved(&mut self) -> Option<Checkpoint> {
+        self.unsaved.take()
+    }
+
+    /// Restores the checkpoint to save after the checkpoint store failed
+    pub fn save_failed(&mut self, checkpoint: Checkpoint) {
+        self.unsaved = Some(checkpoint);
+    }
+
+    /// Whether the version of the table has to be queried before listing the changes
+    pub fn needs_ending_version(&self) -> bool {
+        self.ending_version.is_none()
+    }
+
+    pub fn set_ending_version(&mut self, version: i64) {
+        self.started = true;
+        self.ending_version = Some(version);
+    }
+
+    /// The range of versions to list, None if the buffered actions can be used or the read
+    /// is complete. Buffered actions are listed again once their URLs are about to expire
+    pub fn changes_to_list(&mut self, expiry_margin: Duration) -> Option<(i64, i64)> {
+        if self
+            .buffer
+            .front()
+            .is_some_and(|(c, _)| c.file().url_expires_within(expiry_margin))
+        {
+            self.buffer.clear();
+            self.buffered_version = self.position.version;
+        }
+        let ending_version = self.ending_version?;
+        if !self.buffer.is_empty() || self.buffered_version >= ending_version {
+            return None;
+        }
+        Some((self.position.version + 1, ending_version))
+    }
+
+    /// Buffers the actions listed for the versions from the position to `ending_version`,
+    /// skipping the ones which were already returned. Fails if an action has no version
+    pub fn listed(
+        &mut self,
+        changes: TableChanges,
+        ending_version: i64,
+    ) -> Result<(), anyhow::Error> {
+        if let Some(change) = changes.changes.iter().find(|c| c.version().is_none()) {
+            return Err(anyhow::anyhow!(
+                "Change of file {} has no version",
+                change.file().id
+            ));
+        }
+        let mut version = -1;
+        let mut index = 0;
+        for change in changes.changes {
+            let change_version = change.version().unwrap_or_default();
+            if change_version != version {
+                version = change_version;
+                index = 0;
+            }
+            if version <= self.position.version
+                || (version == self.position.version + 1 && index < self.position.index)
+            {
+                index += 1;
+                continue;
+            }
+            self.buffer.push_back((change, index));
+            index += 1;
+        }
+        self.buffered_version = ending_version;
+        self.metadata = Some(changes.metadata);
+        Ok(())
+    }
+
+    /// The next batch of buffered actions, None once the ending version was reached
+    pub fn next_batch(&mut self) -> Option<MicroBatch> {
+        let start = self.position;
+        if self.buffer.is_empty() {
+            if self.position.version < self.buffered_version {
+                // The remaining versions have no file actions
+                self.position = Checkpoint::version(self.buffered_version);
+                if self.options.checkpoint_store.is_some() {
+                    self.unsaved = Some(self.position);
+                }
+            }
+            return None;
+        }
+        let max_files = self
+            .options
+            .max_files_per_batch
+            .unwrap_or(DEFAULT_MAX_FILES_PER_BATCH)
+            .max(1);
+        let max_bytes = self.options.max_bytes_per_batch.unwrap_or(u64::MAX);
+        let mut changes = Vec::new();
+        let mut bytes = 0u64;
+        while let Some((change, _)) = self.buffer.front() {
+            let size = change.file().size.max(0) as u64;
+            if !changes.is_empty()
+                && (changes.len() >= max_files || bytes.saturating_add(size) > max_bytes)
+            {
+                break;
+            }
+            bytes = bytes.saturating_add(size);
+            changes.push(self.buffer.pop_front().unwrap().0);
+        }
+        self.position = match self.buffer.front() {
+            Some((change, index)) => Checkpoint {
+                version: change.version().unwrap_or_default() - 1,
+                index: *index,
+            },
+            None => Checkpoint::version(self.buffered_version),
+        };
+        if self.options.checkpoint_store.is_some() {
+            self.unsaved = Some(self.position);
+        }
+        Some(MicroBatch {
+            start,
+            end: self.position,
+            metadata: self.metadata.clone().unwrap(),
+            changes,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Map;
+
+    fn table() -> Table {
+        Table {
+            name: "table".to_string(),
+            schema: "schema".to_string(),
+            share: "share".to_string(),
+            share_id: None,
+            id: None,
+        }
+    }
+
+    fn changes(actions: &[(i64, &str)]) -> TableChanges {
+        TableChanges {
+            metadata: serde_json::from_value(serde_json::json!({
+                "protocol": { "minReaderVersion": 1 },
+                "metadata": {
+                    "id": "id",
+                    "format": { "provider": "parquet" },
+                    "schemaString": "{}",
+                    "configuration": {},
+                    "partitionColumns": []
+                }
+            }))
+            .unwrap(),
+            changes: actions
+                .iter()
+                .map(|(version, id)| {
+                    FileChange::Add(File {
+                        id: id.to_string(),
+                        url: format!("https://example.com/{}", id),
+                        partition_values: Map::new(),
+                        size: 10,
+                        stats: None,
+                        version: Some(*version),
+                        timestamp: None,
+                        expiration_timestamp: None,
+                        deletion_vector: None,
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    fn ids(batch: &MicroBatch) -> Vec<&str> {
+        batch.added().map(|f| f.id.as_str()).collect()
+    }
+
+    #[test]
+    fn bounded_batches() {
+        let options = IncrementalOptions {
+            starting_version: 1,
+            max_files_per_batch: Some(2),
+            max_bytes_per_batch: Some(25),
+            ..Default::default()
+        };
+        let mut reader = IncrementalReader::new(&table(), options);
+        reader.set_ending_version(4);
+        assert_eq!(reader.changes_to_list(Duration::ZERO), Some((1, 4)));
+        reader
+            .listed(changes(&[(1, "a"), (2, "b"), (2, "c"), (2, "d")]), 4)
+            .unwrap();
+
+        let batch = reader.next_batch().unwrap();
+        assert_eq!(ids(&batch), ["a", "b"]);
+        assert_eq!(batch.start, Checkpoint::version(0));
+        assert_eq!(
+            batch.end,
+            Checkpoint {
+                version: 1,
+                index: 1
+            }
+        );
+        let batch = reader.next_batch().unwrap();
+        assert_eq!(ids(&batch), ["c", "d"]);
+        assert_eq!(batch.end, Checkpoint::version(4));
+        assert_eq!(reader.changes_to_list(Duration::ZERO), None);
+        assert!(reader.next_batch().is_none());
+    }
+
+    #[test]
+    fn resumes_within_version() {
+        let options = IncrementalOptions {
+            max_files_per_batch: Some(1),
+            ..Default::default()
+        };
+        let mut reader = IncrementalReader::new(&table(), options);
+        reader.start(Some(Checkpoint {
+            version: 1,
+            index: 1,
+        }));
+        reader.set_ending_version(3);
+        assert_eq!(reader.changes_to_list(Duration::ZERO), Some((2, 3)));
+        reader.listed(changes(&[(2, "b"), (2, "c")]), 3).unwrap();
+        let batch = reader.next_batch().unwrap();
+        assert_eq!(ids(&batch), ["c"]);
+        assert_eq!(batch.end, Checkpoint::version(3));
+    }
+
+    #[test]
+    fn rejects_changes_without_version() {
+        let mut reader = IncrementalReader::new(&table(), IncrementalOptions::default());
+        reader.set_ending_version(2);
+        let mut listed = changes(&[(1, "a"), (2, "b")]);
+        if let FileChange::Add(file) = &mut listed.changes[1] {
+            file.version = None;
+        }
+        assert!(reader.listed(listed, 2).is_err());
+        assert!(reader.next_batch().is_none());
+    }
+}
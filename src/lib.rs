@@ -20,9 +20,24 @@
 //! The following [Cargo features][cargo-features] can be enabled:
 //!
 //! - **blocking**: provides the [blocking][] client.
+//! - **object-store**: provides a [cache store][cache] backed by the `object_store` crate, to
+//!   share the cached table files between processes through a bucket.
+//! - **server**: provides a [server][] for local tables, to test clients end-to-end offline.
+//! - **testing**: provides a [mock server][testing] with tables built from parquet files, to
+//!   unit-test code using the clients.
+//! - **tracing**: wraps every protocol request and file download in a `tracing` span, with
+//!   the table, HTTP status, size, duration and number of retries. The [metrics][] are
+//!   reported with or without the feature.
+//! - **cli**: builds the `delta-sharing` command line tool, which lists the shares, schemas,
+//!   tables and files of a profile, shows table metadata and versions, prints the first rows
+//!   of a table and downloads or mirrors its files. Its exit code tells protocol failures apart.
 //!
 //! [blocking]: ./blocking/index.html
+//! [cache]: ./cache/struct.ObjectCacheStore.html
 //! [client]: ./struct.Client.html
+//! [metrics]: ./metrics/index.html
+//! [server]: ./server/index.html
+//! [testing]: ./testing/index.html
 //! [cargo-features]: https://doc.rust-lang.org/stable/cargo/reference/manifest.html#the-features-section
 //!
 //! # Quick start example
@@ -31,10 +46,10 @@
 //! [profile files](https://github.com/delta-io/delta-sharing/blob/main/PROTOCOL.md#profile-file-format)
 //!  (which are JSON files containing settings to access a Delta Sharing Server). There are several ways to get started:
 //! - Download the profile file to access an open, example Delta Sharing Server hosted by Databricks
-//!     [here](https://databricks-datasets-oregon.s3-us-west-2.amazonaws.com/delta-sharing/share/open-datasets.share).
+//!   [here](https://databricks-datasets-oregon.s3-us-west-2.amazonaws.com/delta-sharing/share/open-datasets.share).
 //! - Start your own [Delta Sharing Server](https://github.com/delta-io/delta-sharing#delta-sharing-reference-server)
-//!     and create your own profile file following [profile file format](https://github.com/delta-io/delta-sharing/blob/main/PROTOCOL.md#profile-file-format)
-//!     to connect to this server.
+//!   and create your own profile file following [profile file format](https://github.com/delta-io/delta-sharing/blob/main/PROTOCOL.md#profile-file-format)
+//!   to connect to this server.
 //! - Download a profile file from your own Delta Sharing data provider (if you have any).
 //!
 //! When you have your Delta Sharing provider information, replace `"<your Delta Share endpoinit URL>"`
@@ -50,7 +65,7 @@
 //!      endpoint: "<your Delta Share endpoinit URL>".to_string(),
 //!      bearer_token: "<your Delta Share access token>".to_string(),
 //!  };
-//!  let mut app = Client::new(config, None).await.unwrap();
+//!  let app = Client::new(config, None).await.unwrap();
 //!  let shares = app.list_shares().await.unwrap();
 //!  if shares.len() == 0 {
 //!      println!("At least 1 Delta Share is required");
@@ -77,12 +92,36 @@
 #[macro_use]
 extern crate log;
 
+pub use self::builder::ClientBuilder;
 pub use self::client::Client;
+pub use self::error::Error;
+pub use self::utils::{SUPPORTED_READER_FEATURES, SUPPORTED_READER_VERSION};
 
+mod builder;
+pub mod cache;
+pub mod checkpoint;
 mod client;
+mod column_mapping;
+mod core;
+pub mod credentials;
+mod deletion_vector;
+mod error;
+pub mod export;
+pub mod incremental;
+pub mod metrics;
+pub mod mirror;
 pub mod protocol;
 mod reader;
+pub mod remote;
+mod session;
+mod telemetry;
+mod transport;
 mod utils;
+pub mod watch;
 
 #[cfg(feature = "blocking")]
 pub mod blocking;
+#[cfg(feature = "server")]
+pub mod server;
+#[cfg(feature = "testing")]
+pub mod testing;
@@ -0,0 +1,40 @@
+//! Hooks to collect metrics of the clients.
+//!
+//! Implement [Metrics] to feed counters and histograms into a metrics library and pass it to
+//! the client builder. All the methods do nothing by default, so only the events of interest
+//! have to be handled. The methods are called inline with the requests and should be cheap.
+
+use std::time::Duration;
+
+/// Receives the events of a client, see the [module documentation][self]
+#[allow(unused_variables)]
+pub trait Metrics: Send + Sync {
+    /// A request to the sharing server succeeded. `operation` names the protocol call,
+    /// e.g. `list_shares` or `query_table`
+    fn request(&self, operation: &str, duration: Duration) {}
+
+    /// A request to the sharing server failed
+    fn request_error(&self, operation: &str, duration: Duration) {}
+
+    /// A request was retried after the token was refreshed, or a file download after its URL
+    /// expired or its content was corrupt. `operation` is `download` for file downloads
+    fn retry(&self, operation: &str) {}
+
+    /// The files of the table were found in the local cache
+    fn cache_hit(&self, table: &str) {}
+
+    /// The files of the table had to be downloaded
+    fn cache_miss(&self, table: &str) {}
+
+    /// A data or deletion vector file of the table, or a range of a data file read directly
+    /// from its URL, was downloaded
+    fn download(&self, table: &str, bytes: u64, duration: Duration) {}
+
+    /// A download of a data or deletion vector file of the table failed
+    fn download_error(&self, table: &str, duration: Duration) {}
+}
+
+/// Used when no [Metrics] are configured
+pub(crate) struct NoMetrics;
+
+impl Metrics for NoMetrics {}
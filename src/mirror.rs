@@ -0,0 +1,395 @@
+//! Mirroring of shared tables into local Delta tables.
+//!
+//! The mirror is a regular Delta table: the data files of the shared table are downloaded
+//! into `<destination>/<partition directories>/<file id>.parquet` and every sync appends a
+//! JSON commit to `<destination>/_delta_log` which adds the new files and removes the files
+//! which are no longer part of the shared table. The version of the shared table is recorded
+//! in the commit info, so later syncs only download the files added since the last sync and
+//! do nothing if the shared table did not change. Removed files are kept on disk, so older
+//! versions of the mirror stay readable.
+
+use crate::protocol::{File, Table, TableFiles};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const DELTA_LOG_DIR: &str = "_delta_log";
+const OPERATION: &str = "DELTA SHARING MIRROR";
+/// Partition directory name of null partition values, as written by Spark
+const NULL_PARTITION: &str = "__HIVE_DEFAULT_PARTITION__";
+
+/// Result of [Client::mirror_table][crate::Client::mirror_table]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MirrorSummary {
+    /// Version of the shared table the mirror is in sync with
+    pub source_version: i64,
+    /// Latest version of the local Delta table
+    pub version: i64,
+    /// Number of data files downloaded and added by this sync
+    pub files_added: usize,
+    /// Number of data files removed by this sync
+    pub files_removed: usize,
+}
+
+/// State of a local mirror, replayed from its Delta log
+pub(crate) struct Mirror {
+    root: PathBuf,
+    table: String,
+    version: Option<i64>,
+    source_version: Option<i64>,
+    /// Paths of the files of the latest version, as written in the add actions
+    files: BTreeSet<String>,
+    protocol: Option<Value>,
+    metadata: Option<Value>,
+}
+
+impl Mirror {
+    /// Opens the mirror of the table at `root`, which is created by the first commit
+    pub fn open(root: &Path, table: &Table) -> Result<Self, anyhow::Error> {
+        let mut mirror = Self {
+            root: root.to_path_buf(),
+            table: table.fully_qualified_name(),
+            version: None,
+            source_version: None,
+            files: BTreeSet::new(),
+            protocol: None,
+            metadata: None,
+        };
+        let log_dir = root.join(DELTA_LOG_DIR);
+        if !log_dir.is_dir() {
+            return Ok(mirror);
+        }
+        // Commits are named after their version padded to 20 digits, other files are ignored
+        let mut commits = Vec::new();
+        for entry in fs::read_dir(&log_dir)? {
+            let path = entry?.path();
+            let version = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_suffix(".json"))
+                .filter(|v| v.len() == 20 && v.bytes().all(|b| b.is_ascii_digit()))
+                .and_then(|v| v.parse::<i64>().ok());
+            if let Some(version) = version {
+                commits.push((version, path));
+            }
+        }
+        commits.sort_unstable();
+        for (version, path) in commits {
+            let commit = fs::File::open(path)?;
+            for line in BufReader::new(commit).lines() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let action: Value = serde_json::from_str(&line)?;
+                mirror.apply(&action)?;
+            }
+            mirror.version = Some(version);
+        }
+        Ok(mirror)
+    }
+
+    fn apply(&mut self, action: &Value) -> Result<(), anyhow::Error> {
+        if let Some(commit_info) = action.get("commitInfo") {
+            let parameters = &commit_info["operationParameters"];
+            if commit_info["operation"] != OPERATION {
+                return Err(anyhow::anyhow!(
+                    "{} is not a mirror of a shared table",
+                    self.root.display()
+                ));
+            }
+            if parameters["table"] != self.table.as_str() {
+                return Err(anyhow::anyhow!(
+                    "{} is a mirror of table {}, not {}",
+                    self.root.display(),
+                    parameters["table"].as_str().unwrap_or_default(),
+                    self.table
+                ));
+            }
+            self.source_version = parameters["sourceVersion"]
+                .as_str()
+                .and_then(|v| v.parse().ok());
+        } else if let Some(protocol) = action.get("protocol") {
+            self.protocol = Some(protocol.clone());
+        } else if let Some(metadata) = action.get("metaData") {
+            self.metadata = Some(metadata.clone());
+        } else if let Some(path) = action.get("add").and_then(|a| a["path"].as_str()) {
+            self.files.insert(path.to_string());
+        } else if let Some(path) = action.get("remove").and_then(|a| a["path"].as_str()) {
+            self.files.remove(path);
+        }
+        Ok(())
+    }
+
+    /// The summary of a sync if the mirror is already at the version of the shared table
+    pub fn up_to_date(&self, source_version: i64) -> Option<MirrorSummary> {
+        match (self.version, self.source_version) {
+            (Some(version), Some(mirrored)) if mirrored == source_version => Some(MirrorSummary {
+                source_version,
+                version,
+                files_added: 0,
+                files_removed: 0,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Indexes and local paths of the listed files which are not part of the mirror yet
+    pub fn missing_files(
+        &self,
+        table_files: &TableFiles,
+    ) -> Result<Vec<(usize, PathBuf)>, anyhow::Error> {
+        let partition_columns = &table_files.metadata.metadata.partition_columns;
+        let mut missing = Vec::new();
+        for (i, file) in table_files.files.iter().enumerate() {
+            if file.deletion_vector.is_some() {
+                return Err(anyhow::anyhow!(
+                    "Mirroring tables with deletion vectors is not supported"
+                ));
+            }
+            let segments = Self::file_segments(partition_columns, file);
+            if self.files.contains(&Self::add_path(&segments)) {
+                continue;
+            }
+            let path = segments.iter().fold(self.root.clone(), |p, s| p.join(s));
+            missing.push((i, path));
+        }
+        Ok(missing)
+    }
+
+    /// Commits the listed files as the next version of the mirror. The missing files must
+    /// have been downloaded
+    pub fn commit(
+        &self,
+        table_files: &TableFiles,
+        source_version: i64,
+    ) -> Result<MirrorSummary, anyhow::Error> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as i64;
+        let source = &table_files.metadata;
+        let mut actions = vec![json!({ "commitInfo": {
+            "timestamp": now,
+            "operation": OPERATION,
+            "operationParameters": {
+                "table": self.table,
+                "sourceVersion": source_version.to_string(),
+            },
+            "isBlindAppend": false,
+        } })];
+
+        let reader_version = source.protocol.min_reader_version;
+        let mut protocol = json!({
+            "minReaderVersion": reader_version,
+            "minWriterVersion": source
+                .protocol
+                .min_writer_version
+                .unwrap_or(if reader_version > 1 { 5 } else { 2 }),
+        });
+        if let Some(features) = &source.protocol.reader_features {
+            protocol["readerFeatures"] = json!(features);
+        }
+        if let Some(features) = &source.protocol.writer_features {
+            protocol["writerFeatures"] = json!(features);
+        }
+        if self.protocol.as_ref() != Some(&protocol) {
+            actions.push(json!({ "protocol": protocol }));
+        }
+
+        let m = &source.metadata;
+        let mut metadata = json!({
+            "id": m.id,
+            "format": {
+                "provider": m.format.provider,
+                "options": m.format.options.clone().unwrap_or_default(),
+            },
+            "schemaString": m.schema_string,
+            "partitionColumns": m.partition_columns,
+            "configuration": m.configuration,
+        });
+        if let Some(name) = &m.name {
+            metadata["name"] = json!(name);
+        }
+        if let Some(description) = &m.description {
+            metadata["description"] = json!(description);
+        }
+        let changed = match &self.metadata {
+            Some(current) => {
+                let mut current = current.clone();
+                if let Some(current) = current.as_object_mut() {
+                    current.remove("createdTime");
+                }
+                current != metadata
+            }
+            None => true,
+        };
+        if changed {
+            metadata["createdTime"] = json!(now);
+            actions.push(json!({ "metaData": metadata }));
+        }
+
+        let mut listed = BTreeSet::new();
+        let mut files_added = 0;
+        for file in &table_files.files {
+            let path = Self::add_path(&Self::file_segments(&m.partition_columns, file));
+            if !self.files.contains(&path) && !listed.contains(&path) {
+                actions.push(json!({ "add": {
+                    "path": path,
+                    "partitionValues": Self::partition_values(file),
+                    "size": file.size,
+                    "modificationTime": file.timestamp.unwrap_or(now),
+                    "dataChange": true,
+                    "stats": file.stats,
+                } }));
+                files_added += 1;
+            }
+            listed.insert(path);
+        }
+        let mut files_removed = 0;
+        for path in self.files.difference(&listed) {
+            actions.push(json!({ "remove": {
+                "path": path,
+                "deletionTimestamp": now,
+                "dataChange": true,
+            } }));
+            files_removed += 1;
+        }
+
+        let version = self.version.map_or(0, |v| v + 1);
+        let log_dir = self.root.join(DELTA_LOG_DIR);
+        fs::create_dir_all(&log_dir)?;
+        let commit_path = log_dir.join(format!("{:020}.json", version));
+        let mut commit = match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&commit_path)
+        {
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                return Err(anyhow::anyhow!(
+                    "Version {} of {} was committed concurrently",
+                    version,
+                    self.root.display()
+                ))
+            }
+            res => res?,
+        };
+        for action in &actions {
+            writeln!(commit, "{}", action)?;
+        }
+        commit.sync_all()?;
+        info!(
+            "--> Mirrored version {} of {} as version {} of {}",
+            source_version,
+            self.table,
+            version,
+            self.root.display()
+        );
+        Ok(MirrorSummary {
+            source_version,
+            version,
+            files_added,
+            files_removed,
+        })
+    }
+
+    /// Partition values of the file as strings, as required by the add actions
+    fn partition_values(file: &File) -> Map<String, Value> {
+        file.partition_values
+            .iter()
+            .map(|(k, v)| {
+                let value = match v {
+                    Value::Null => Value::Null,
+                    Value::String(s) => Value::String(s.clone()),
+                    v => Value::String(v.to_string()),
+                };
+                (k.clone(), value)
+            })
+            .collect()
+    }
+
+    /// Hive-style partition directories and the file name of a data file
+    fn file_segments(partition_columns: &[String], file: &File) -> Vec<String> {
+        let mut segments = partition_columns
+            .iter()
+            .map(|column| {
+                let value = match file.partition_values.get(column) {
+                    None | Some(Value::Null) => NULL_PARTITION.to_string(),
+                    Some(Value::String(s)) => escape_partition(s),
+                    Some(v) => escape_partition(&v.to_string()),
+                };
+                format!("{}={}", escape_partition(column), value)
+            })
+            .collect::<Vec<_>>();
+        segments.push(format!("{}.parquet", escape_partition(&file.id)));
+        segments
+    }
+
+    /// Relative URI of a data file in the add and remove actions
+    fn add_path(segments: &[String]) -> String {
+        segments
+            .iter()
+            .map(|s| encode_uri_segment(s))
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+}
+
+/// Escapes the characters which are not allowed in partition directory names, like Hive
+fn escape_partition(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if c < ' ' || "\"#%'*/:=?\\\x7f{[]^".contains(c) {
+            escaped.push_str(&format!("%{:02X}", c as u32));
+        } else {
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+fn encode_uri_segment(segment: &str) -> String {
+    let mut encoded = String::with_capacity(segment.len());
+    for b in segment.bytes() {
+        if b.is_ascii_alphanumeric() || b"-_.~=".contains(&b) {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", b));
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn partitioned_file_paths() {
+        let file: File = serde_json::from_value(json!({
+            "id": "file-1",
+            "url": "https://example.com/file-1.parquet",
+            "partitionValues": { "date": "2022-01-01 10:00", "country": null },
+            "size": 10,
+        }))
+        .unwrap();
+        let columns = vec!["date".to_string(), "country".to_string()];
+        let segments = Mirror::file_segments(&columns, &file);
+        assert_eq!(
+            segments,
+            [
+                "date=2022-01-01 10%3A00",
+                "country=__HIVE_DEFAULT_PARTITION__",
+                "file-1.parquet"
+            ]
+        );
+        assert_eq!(
+            Mirror::add_path(&segments),
+            "date=2022-01-01%2010%253A00/country=__HIVE_DEFAULT_PARTITION__/file-1.parquet"
+        );
+    }
+}
@@ -1,10 +1,14 @@
 //! Delta Sharing Protocol message types
 
+use crate::error::Error;
+use crate::utils::{SUPPORTED_READER_FEATURES, SUPPORTED_READER_VERSION};
 use serde::{Deserialize, Serialize};
 use serde_json::value::Value;
 use serde_json::Map;
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ProviderConfig {
     pub share_credentials_version: i32,
@@ -12,22 +16,42 @@ pub struct ProviderConfig {
     pub bearer_token: String,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+impl fmt::Debug for ProviderConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProviderConfig")
+            .field("share_credentials_version", &self.share_credentials_version)
+            .field("endpoint", &self.endpoint)
+            .field("bearer_token", &"<redacted>")
+            .finish()
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Serialize)]
 pub struct Share {
     pub name: String,
+    /// Immutable id of the share, if the server provides it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Debug, Clone, Serialize)]
 pub struct Schema {
     pub name: String,
     pub share: String,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct Table {
     pub name: String,
     pub share: String,
     pub schema: String,
+    /// Immutable id of the share of the table, if the server provides it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub share_id: Option<String>,
+    /// Immutable id of the table, if the server provides it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
 }
 
 impl Table {
@@ -40,6 +64,56 @@ impl Table {
 #[serde(rename_all = "camelCase")]
 pub struct Protocol {
     pub min_reader_version: i32,
+    /// Only present in the `delta` response format
+    pub min_writer_version: Option<i32>,
+    /// Table features required to read the table, only present in the `delta` response format
+    pub reader_features: Option<Vec<String>>,
+    /// Table features required to write the table, only present in the `delta` response format
+    pub writer_features: Option<Vec<String>>,
+}
+
+impl Protocol {
+    /// Checks that this client can read a table with this protocol
+    pub fn check_supported(&self) -> Result<(), Error> {
+        if self.min_reader_version > SUPPORTED_READER_VERSION {
+            return Err(Error::UnsupportedReaderVersion {
+                required: self.min_reader_version,
+                supported: SUPPORTED_READER_VERSION,
+            });
+        }
+        let unsupported = self
+            .reader_features
+            .iter()
+            .flatten()
+            .filter(|f| !SUPPORTED_READER_FEATURES.contains(&f.as_str()))
+            .cloned()
+            .collect::<Vec<_>>();
+        if !unsupported.is_empty() {
+            return Err(Error::UnsupportedReaderFeatures(unsupported));
+        }
+        Ok(())
+    }
+}
+
+/// Format of the table metadata and files returned by the sharing server
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResponseFormat {
+    /// The original Delta Sharing format, with files described by `file` actions
+    #[default]
+    Parquet,
+    /// Native Delta Lake actions wrapped in `deltaSingleAction`, required for tables
+    /// using advanced Delta features
+    Delta,
+}
+
+impl ResponseFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ResponseFormat::Parquet => "parquet",
+            ResponseFormat::Delta => "delta",
+        }
+    }
 }
 
 #[derive(Deserialize, Debug, Clone, PartialEq, Serialize)]
@@ -58,6 +132,12 @@ pub struct Metadata {
     pub schema_string: String,
     pub configuration: Map<String, Value>,
     pub partition_columns: Vec<String>,
+    /// Table version the metadata belongs to, returned when querying changes or a starting version
+    pub version: Option<i64>,
+    /// Size of the table in bytes, if known by the server
+    pub size: Option<i64>,
+    /// Number of files in the table, if known by the server
+    pub num_files: Option<i64>,
 }
 
 #[derive(Deserialize, Debug, Clone, PartialEq, Serialize)]
@@ -72,12 +152,85 @@ pub struct File {
     pub id: String,
     pub url: String,
     pub partition_values: Map<String, Value>,
-    pub size: i32,
+    pub size: i64,
     pub stats: Option<String>,
+    /// Table version of the file, returned when querying changes or a starting version
+    pub version: Option<i64>,
+    /// Commit time (milliseconds since the epoch) of the table version of the file
+    pub timestamp: Option<i64>,
+    /// Time (milliseconds since the epoch) when the presigned `url` expires
+    pub expiration_timestamp: Option<i64>,
+    /// Rows of the file which have been deleted, only present in the `delta` response format
+    pub deletion_vector: Option<DeletionVectorDescriptor>,
+}
+
+/// Location of a deletion vector marking rows of a data file as deleted
+#[derive(Deserialize, Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeletionVectorDescriptor {
+    /// `i` for a deletion vector stored inline, `p` for an absolute path or URL
+    /// and `u` for a path relative to the table root
+    pub storage_type: String,
+    /// Z85 encoded deletion vector, or the path of the file it is stored in
+    pub path_or_inline_dv: String,
+    /// Position of the deletion vector in the file it is stored in
+    pub offset: Option<i32>,
+    /// Size of the serialized deletion vector in bytes
+    pub size_in_bytes: i32,
+    /// Number of rows marked as deleted
+    pub cardinality: i64,
+}
+
+impl File {
+    /// Returns true if the presigned `url` has expired or expires within the given margin
+    pub fn url_expires_within(&self, margin: Duration) -> bool {
+        match self.expiration_timestamp {
+            Some(expiration) => {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_millis() as i64;
+                expiration <= now + margin.as_millis() as i64
+            }
+            None => false,
+        }
+    }
 }
 
 #[derive(Deserialize, Debug, Clone, PartialEq, Serialize)]
 pub struct TableFiles {
     pub metadata: TableMetadata,
     pub files: Vec<File>,
+    /// Table version the files were listed for, as reported by the server
+    pub version: Option<i64>,
+}
+
+/// A file added or removed by a version of the table
+#[derive(Deserialize, Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileChange {
+    Add(File),
+    Remove(File),
+}
+
+impl FileChange {
+    pub fn file(&self) -> &File {
+        match self {
+            FileChange::Add(file) | FileChange::Remove(file) => file,
+        }
+    }
+
+    /// Table version which added or removed the file
+    pub fn version(&self) -> Option<i64> {
+        self.file().version
+    }
+}
+
+/// Files added and removed by a range of table versions, see
+/// [Client::list_table_changes][crate::Client::list_table_changes]
+#[derive(Deserialize, Debug, Clone, PartialEq, Serialize)]
+pub struct TableChanges {
+    pub metadata: TableMetadata,
+    /// Changes ordered by version
+    pub changes: Vec<FileChange>,
 }
@@ -1,12 +1,79 @@
+use crate::column_mapping::ColumnMapping;
+use bytes::Bytes;
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use parquet::file::serialized_reader::SliceableCursor;
 use polars::prelude::Result as PolarResult;
 use polars::prelude::*;
+use roaring::RoaringTreemap;
+use std::fs;
+use std::io::Cursor;
 use std::path::PathBuf;
+use std::sync::Arc;
 
-pub fn load_parquet_files_as_dataframe(parquet_root_dir_path: &PathBuf) -> PolarResult<LazyFrame> {
-    let search_pattern = parquet_root_dir_path
-        .join("*.parquet")
-        .display()
-        .to_string();
-    let res = LazyFrame::scan_parquet(search_pattern.into(), Default::default());
-    res
+/// Loads the given parquet files, dropping the rows marked as deleted by their deletion vectors
+/// and renaming the columns to their logical names
+pub fn load_parquet_files(
+    files: &[(PathBuf, Option<RoaringTreemap>)],
+    column_mapping: &ColumnMapping,
+) -> PolarResult<LazyFrame> {
+    let mut frames: Vec<LazyFrame> = Vec::new();
+    for (path, deleted_rows) in files {
+        let mut frame = LazyFrame::scan_parquet(path.display().to_string(), Default::default())?;
+        if let Some(deleted_rows) = deleted_rows {
+            let df = frame.collect()?;
+            let mask: BooleanChunked = (0..df.height() as u64)
+                .map(|i| !deleted_rows.contains(i))
+                .collect();
+            frame = df.filter(&mask)?.lazy();
+        }
+        if *column_mapping != ColumnMapping::None {
+            let reader = SerializedFileReader::new(fs::File::open(path)?)
+                .map_err(|e| PolarsError::ComputeError(e.to_string().into()))?;
+            let renames = column_mapping.renames(
+                reader
+                    .metadata()
+                    .file_metadata()
+                    .schema_descr()
+                    .root_schema()
+                    .get_fields(),
+            );
+            frame = frame.rename(renames.keys(), renames.values());
+        }
+        frames.push(frame);
+    }
+    concat(&frames, false)
+}
+
+/// Loads parquet files from their content, like [load_parquet_files]
+pub fn load_parquet_buffers(
+    files: &[(Bytes, Option<RoaringTreemap>)],
+    column_mapping: &ColumnMapping,
+) -> PolarResult<LazyFrame> {
+    let mut frames: Vec<LazyFrame> = Vec::new();
+    for (content, deleted_rows) in files {
+        let mut df = ParquetReader::new(Cursor::new(content.clone())).finish()?;
+        if let Some(deleted_rows) = deleted_rows {
+            let mask: BooleanChunked = (0..df.height() as u64)
+                .map(|i| !deleted_rows.contains(i))
+                .collect();
+            df = df.filter(&mask)?;
+        }
+        let mut frame = df.lazy();
+        if *column_mapping != ColumnMapping::None {
+            let reader =
+                SerializedFileReader::new(SliceableCursor::new(Arc::new(content.to_vec())))
+                    .map_err(|e| PolarsError::ComputeError(e.to_string().into()))?;
+            let renames = column_mapping.renames(
+                reader
+                    .metadata()
+                    .file_metadata()
+                    .schema_descr()
+                    .root_schema()
+                    .get_fields(),
+            );
+            frame = frame.rename(renames.keys(), renames.values());
+        }
+        frames.push(frame);
+    }
+    concat(&frames, false)
 }
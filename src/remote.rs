@@ -0,0 +1,405 @@
+//! Cacheless reading of shared table data.
+//!
+//! Instead of downloading whole data files into `data_root`, the readers in this module
+//! use HTTP range requests against the presigned [File] URLs, so only the parquet footer
+//! and the required row groups and columns are transferred.
+
+use crate::column_mapping::ColumnMapping;
+use crate::metrics::Metrics;
+use crate::protocol::{File, TableFiles};
+use crate::telemetry::Span;
+use crate::utils::{redact_error, redact_url};
+use arrow::array::BooleanArray;
+use arrow::compute::filter_record_batch;
+use arrow::datatypes::{Field, Schema as ArrowSchema};
+use arrow::error::ArrowError;
+use arrow::ipc::writer::FileWriter;
+use arrow::record_batch::RecordBatch;
+use bytes::Bytes;
+use futures::future::BoxFuture;
+use futures::{ready, FutureExt, TryStreamExt};
+use parquet::arrow::ParquetRecordBatchStreamBuilder;
+use polars::prelude::{DataFrame, IpcReader, SerReader};
+use reqwest::{header, StatusCode};
+use roaring::RoaringTreemap;
+use std::collections::HashMap;
+use std::io::{self, Cursor, SeekFrom};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncSeek, ReadBuf};
+
+/// Smallest number of bytes requested per range request. Ranges which would end at the end
+/// of the file are extended backwards instead, so the parquet footer and the metadata before
+/// it are normally fetched in a single round trip
+const MIN_RANGE_SIZE: u64 = 64 * 1024;
+
+/// Options for reading table data directly from the presigned file URLs
+#[derive(Debug, Clone, Default)]
+pub struct ReadOptions {
+    /// Names of the columns to read. All the columns are read if None
+    pub columns: Option<Vec<String>>,
+    /// Maximum number of rows to read. Row groups and files beyond the limit are not fetched
+    pub limit: Option<usize>,
+}
+
+/// Table and [Metrics] the range requests of a reader are reported to as downloads
+#[derive(Clone)]
+pub(crate) struct DownloadMetrics {
+    pub table: String,
+    pub metrics: Arc<dyn Metrics>,
+}
+
+/// A reader over a remote file which fetches the requested bytes with HTTP range requests.
+///
+/// Implements [AsyncRead] and [AsyncSeek] so it can be used with the parquet async reader.
+pub struct HttpRangeReader {
+    http_client: reqwest::Client,
+    url: String,
+    size: u64,
+    download_metrics: Option<DownloadMetrics>,
+    position: u64,
+    buffer_start: u64,
+    buffer: Bytes,
+    pending: Option<(u64, BoxFuture<'static, io::Result<Bytes>>)>,
+}
+
+impl HttpRangeReader {
+    /// Constructs a new reader
+    /// # Arguments
+    ///
+    /// * `http_client` - HTTP client used for range requests. It should not send the sharing server credentials
+    /// * `url` - URL of the remote file
+    /// * `size` - Size of the remote file in bytes
+    pub fn new(http_client: reqwest::Client, url: String, size: u64) -> Self {
+        Self {
+            http_client,
+            url,
+            size,
+            download_metrics: None,
+            position: 0,
+            buffer_start: 0,
+            buffer: Bytes::new(),
+            pending: None,
+        }
+    }
+
+    /// Reports each range request as a download of the table, in a download span
+    pub(crate) fn with_metrics(mut self, download_metrics: DownloadMetrics) -> Self {
+        self.download_metrics = Some(download_metrics);
+        self
+    }
+
+    async fn fetch_range(
+        http_client: reqwest::Client,
+        url: String,
+        download_metrics: Option<DownloadMetrics>,
+        start: u64,
+        end: u64,
+    ) -> io::Result<Bytes> {
+        debug!(
+            "--> HTTP GET range {}-{} of {}",
+            start,
+            end - 1,
+            redact_url(&url)
+        );
+        let request = async {
+            let resp = http_client
+                .get(&url)
+                .header(header::RANGE, format!("bytes={}-{}", start, end - 1))
+                .send()
+                .await?
+                .error_for_status()?;
+            let status = resp.status();
+            Ok((status, resp.bytes().await?))
+        };
+        let result = match download_metrics {
+            Some(download_metrics) => {
+                let span = Span::download(&download_metrics.table, &url, download_metrics.metrics);
+                let result = span.instrument(request).await;
+                span.finish_download(
+                    result
+                        .as_ref()
+                        .map(|(_, content)| content.len() as u64)
+                        .map_err(|e: &reqwest::Error| e.status()),
+                );
+                result
+            }
+            None => request.await,
+        };
+        let (status, content) = result.map_err(|e| io::Error::other(redact_error(e)))?;
+        if status == StatusCode::PARTIAL_CONTENT {
+            return Ok(content);
+        }
+        // The server ignored the range and returned the whole file
+        if content.len() < end as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("Expected at least {} bytes, got {}", end, content.len()),
+            ));
+        }
+        Ok(content.slice(start as usize..end as usize))
+    }
+}
+
+impl AsyncRead for HttpRangeReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        loop {
+            if let Some((start, fut)) = self.pending.as_mut() {
+                let start = *start;
+                let content = ready!(fut.poll_unpin(cx));
+                self.pending = None;
+                self.buffer_start = start;
+                self.buffer = content?;
+                // A short or empty response would otherwise request the same range forever
+                if start + self.buffer.len() as u64 <= self.position {
+                    return Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        format!(
+                            "Response for the range starting at {} ends before {}",
+                            start, self.position
+                        ),
+                    )));
+                }
+            }
+            if self.position >= self.size || buf.remaining() == 0 {
+                return Poll::Ready(Ok(()));
+            }
+            let buffer_end = self.buffer_start + self.buffer.len() as u64;
+            if self.position >= self.buffer_start && self.position < buffer_end {
+                let offset = (self.position - self.buffer_start) as usize;
+                let len = buf.remaining().min(self.buffer.len() - offset);
+                buf.put_slice(&self.buffer[offset..offset + len]);
+                self.position += len as u64;
+                return Poll::Ready(Ok(()));
+            }
+            let end = (self.position + MIN_RANGE_SIZE.max(buf.remaining() as u64)).min(self.size);
+            let start = self.position.min(end.saturating_sub(MIN_RANGE_SIZE));
+            let fut = Self::fetch_range(
+                self.http_client.clone(),
+                self.url.clone(),
+                self.download_metrics.clone(),
+                start,
+                end,
+            );
+            self.pending = Some((start, fut.boxed()));
+        }
+    }
+}
+
+impl AsyncSeek for HttpRangeReader {
+    fn start_seek(mut self: Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+        let position = match position {
+            SeekFrom::Start(p) => p as i64,
+            SeekFrom::End(p) => self.size as i64 + p,
+            SeekFrom::Current(p) => self.position as i64 + p,
+        };
+        if position < 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Invalid seek to a negative position",
+            ));
+        }
+        self.pending = None;
+        self.position = position as u64;
+        Ok(())
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        Poll::Ready(Ok(self.position))
+    }
+}
+
+/// Reads the record batches of a single data file using HTTP range requests and passes them
+/// to `visit` one at a time. Returns the number of rows visited
+/// # Arguments
+///
+/// * `http_client` - HTTP client used for range requests
+/// * `download_metrics` - Receives the range requests as downloads
+/// * `file` - The data file to read
+/// * `options` - Column projection and row limit, see [ReadOptions]
+/// * `deleted_rows` - Indexes of the rows to drop, as marked by the file deletion vector
+/// * `column_mapping` - Resolves the logical names of the columns, see [ColumnMapping]
+/// * `visit` - Receives the record batches with the file they were read from
+async fn visit_file_batches(
+    http_client: &reqwest::Client,
+    download_metrics: &DownloadMetrics,
+    file: &File,
+    options: &ReadOptions,
+    deleted_rows: Option<&RoaringTreemap>,
+    column_mapping: &ColumnMapping,
+    visit: &mut (dyn FnMut(&File, RecordBatch) -> Result<(), anyhow::Error> + Send),
+) -> Result<usize, anyhow::Error> {
+    let limit = options.limit.unwrap_or(usize::MAX);
+    if limit == 0 {
+        return Ok(0);
+    }
+    let reader = HttpRangeReader::new(http_client.clone(), file.url.clone(), file.size as u64)
+        .with_metrics(download_metrics.clone());
+    let mut builder = ParquetRecordBatchStreamBuilder::new(reader).await?;
+    let schema_descr = builder.metadata().file_metadata().schema_descr_ptr();
+    let renames = column_mapping.renames(schema_descr.root_schema().get_fields());
+    let logical_name = |physical: &str| {
+        renames
+            .get(physical)
+            .cloned()
+            .unwrap_or_else(|| physical.to_string())
+    };
+
+    if let Some(columns) = &options.columns {
+        let mut projection = Vec::new();
+        for name in columns {
+            let leaves = (0..schema_descr.num_columns())
+                .filter(|i| logical_name(&schema_descr.column(*i).path().parts()[0]) == *name)
+                .collect::<Vec<_>>();
+            if leaves.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "Column {} not found in file {}",
+                    name,
+                    file.id
+                ));
+            }
+            projection.extend(leaves);
+        }
+        projection.sort_unstable();
+        builder = builder.with_projection(projection);
+    }
+    // With deleted rows the number of rows left in a row group is only known after reading it
+    if let (Some(limit), None) = (options.limit, deleted_rows) {
+        let mut rows = 0;
+        let mut row_groups = Vec::new();
+        for (i, row_group) in builder.metadata().row_groups().iter().enumerate() {
+            if rows >= limit {
+                break;
+            }
+            rows += row_group.num_rows() as usize;
+            row_groups.push(i);
+        }
+        builder = builder.with_row_groups(row_groups);
+    }
+
+    let mut stream = builder.build()?;
+    let mut offset = 0;
+    let mut rows = 0;
+    while let Some(mut batch) = stream.try_next().await? {
+        if let Some(deleted_rows) = deleted_rows {
+            let mask = (0..batch.num_rows() as u64)
+                .map(|i| Some(!deleted_rows.contains(offset + i)))
+                .collect::<BooleanArray>();
+            offset += batch.num_rows() as u64;
+            batch = filter_record_batch(&batch, &mask)?;
+        }
+        if !renames.is_empty() {
+            batch = rename_columns(&batch, &renames)?;
+        }
+        batch = batch.slice(0, batch.num_rows().min(limit - rows));
+        rows += batch.num_rows();
+        visit(file, batch)?;
+        if rows >= limit {
+            break;
+        }
+    }
+    Ok(rows)
+}
+
+/// Reads the record batches of the listed table files using HTTP range requests and passes
+/// them to `visit` one at a time, stopping once the limit of `options` is reached
+/// # Arguments
+///
+/// * `http_client` - HTTP client used for range requests
+/// * `download_metrics` - Receives the range requests as downloads
+/// * `table_files` - The listed table files
+/// * `options` - Column projection and row limit, see [ReadOptions]
+/// * `deleted_rows` - Rows marked as deleted by the deletion vector of each file, in listing order
+/// * `visit` - Receives the record batches with the file they were read from
+pub(crate) async fn visit_table_batches(
+    http_client: &reqwest::Client,
+    download_metrics: &DownloadMetrics,
+    table_files: &TableFiles,
+    options: &ReadOptions,
+    deleted_rows: &[Option<RoaringTreemap>],
+    visit: &mut (dyn FnMut(&File, RecordBatch) -> Result<(), anyhow::Error> + Send),
+) -> Result<(), anyhow::Error> {
+    let column_mapping = ColumnMapping::from_metadata(&table_files.metadata.metadata)?;
+    let mut rows = 0;
+    for (file, deleted_rows) in table_files.files.iter().zip(deleted_rows) {
+        let mut file_options = options.clone();
+        if let Some(limit) = options.limit {
+            if rows >= limit {
+                break;
+            }
+            file_options.limit = Some(limit - rows);
+        }
+        rows += visit_file_batches(
+            http_client,
+            download_metrics,
+            file,
+            &file_options,
+            deleted_rows.as_ref(),
+            &column_mapping,
+            visit,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Reads the record batches of the listed table files using HTTP range requests,
+/// stopping once the limit of `options` is reached. See [visit_table_batches]
+pub(crate) async fn read_table_batches(
+    http_client: &reqwest::Client,
+    download_metrics: &DownloadMetrics,
+    table_files: &TableFiles,
+    options: &ReadOptions,
+    deleted_rows: &[Option<RoaringTreemap>],
+) -> Result<Vec<RecordBatch>, anyhow::Error> {
+    let mut batches = Vec::new();
+    visit_table_batches(
+        http_client,
+        download_metrics,
+        table_files,
+        options,
+        deleted_rows,
+        &mut |_, batch| {
+            batches.push(batch);
+            Ok(())
+        },
+    )
+    .await?;
+    Ok(batches)
+}
+
+fn rename_columns(
+    batch: &RecordBatch,
+    renames: &HashMap<String, String>,
+) -> Result<RecordBatch, ArrowError> {
+    let fields = batch
+        .schema()
+        .fields()
+        .iter()
+        .map(|f| match renames.get(f.name()) {
+            Some(name) => Field::new(name, f.data_type().clone(), f.is_nullable()),
+            None => f.clone(),
+        })
+        .collect::<Vec<_>>();
+    RecordBatch::try_new(Arc::new(ArrowSchema::new(fields)), batch.columns().to_vec())
+}
+
+/// Converts Arrow record batches into a polars [DataFrame]
+pub fn batches_to_dataframe(batches: &[RecordBatch]) -> Result<DataFrame, anyhow::Error> {
+    let schema = match batches.first() {
+        Some(batch) => batch.schema(),
+        None => return Ok(DataFrame::default()),
+    };
+    let mut writer = FileWriter::try_new(Vec::new(), &schema)?;
+    for batch in batches {
+        writer.write(batch)?;
+    }
+    writer.finish()?;
+    let buffer = writer.into_inner()?;
+    Ok(IpcReader::new(Cursor::new(buffer)).finish()?)
+}
@@ -0,0 +1,886 @@
+//! A Delta Sharing server for local tables, available with the `server` feature.
+//!
+//! [SharingServer] serves the directories `<root>/<share>/<schema>/<table>` over the
+//! [Delta Sharing protocol](https://github.com/delta-io/delta-sharing/blob/main/PROTOCOL.md),
+//! so clients can be tested end-to-end without a remote server. A table is either a Delta
+//! table, whose JSON commits in `_delta_log` are replayed (checkpoints are not supported), or
+//! a directory of parquet files served as an unpartitioned table with the single version 0.
+//!
+//! Listings are sent in the `parquet` or `delta` response format requested with the
+//! `delta-sharing-capabilities` header, `parquet` by default. Like the reference server, tables
+//! with deletion vectors are only served in the `delta` format.
+//!
+//! The data files are served by the same process, through URLs signed with HMAC-SHA256 like
+//! the presigned URLs of cloud storage, and support HTTP range requests. The signing key is
+//! random unless set with [SharingServerBuilder::signing_key].
+//!
+//! ```no_run
+//! use delta_sharing::server::SharingServer;
+//! use delta_sharing::Client;
+//!
+//! # async fn run() -> Result<(), anyhow::Error> {
+//! let server = SharingServer::builder("/srv/shares")
+//!     .bearer_token("secret")
+//!     .start()
+//!     .await?;
+//! let client = Client::new(server.profile(), None).await?;
+//! let shares = client.list_shares().await?;
+//! server.shutdown().await;
+//! # Ok(())
+//! # }
+//! ```
+
+pub(crate) mod table;
+
+use crate::protocol::{ProviderConfig, ResponseFormat};
+use crate::utils::{CAPABILITIES_HEADER, TABLE_VERSION_HEADER};
+use hmac::{Hmac, Mac};
+use hyper::header::{self, HeaderMap, HeaderValue};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::io::SeekFrom;
+use std::net::SocketAddr;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use table::{DataFile, FileChange, LocalTable, Snapshot};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use url::Url;
+
+/// How long the URLs of the data files are valid
+const URL_EXPIRATION: Duration = Duration::from_secs(3600);
+
+/// Failure of a request, returned as the error response of the protocol
+#[derive(Debug)]
+pub(crate) enum ServerError {
+    BadRequest(String),
+    Unauthorized,
+    NotFound(String),
+    Internal(String),
+}
+
+impl From<std::io::Error> for ServerError {
+    fn from(e: std::io::Error) -> Self {
+        match e.kind() {
+            std::io::ErrorKind::NotFound => ServerError::NotFound(e.to_string()),
+            _ => ServerError::Internal(e.to_string()),
+        }
+    }
+}
+
+impl ServerError {
+    fn into_response(self) -> Response<Body> {
+        let (status, code, message) = match self {
+            ServerError::BadRequest(m) => (StatusCode::BAD_REQUEST, "INVALID_PARAMETER_VALUE", m),
+            ServerError::Unauthorized => (
+                StatusCode::UNAUTHORIZED,
+                "UNAUTHENTICATED",
+                "The bearer token is missing or invalid".to_string(),
+            ),
+            ServerError::NotFound(m) => (StatusCode::NOT_FOUND, "RESOURCE_DOES_NOT_EXIST", m),
+            ServerError::Internal(m) => (StatusCode::INTERNAL_SERVER_ERROR, "INTERNAL_ERROR", m),
+        };
+        let body = json!({ "errorCode": code, "message": message });
+        Response::builder()
+            .status(status)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+}
+
+type ServerResult = Result<Response<Body>, ServerError>;
+
+/// Builder of a [SharingServer]
+#[derive(Debug)]
+pub struct SharingServerBuilder {
+    root: PathBuf,
+    bearer_tokens: Vec<String>,
+    max_page_size: Option<usize>,
+    signing_key: Option<Vec<u8>>,
+    addr: SocketAddr,
+}
+
+impl SharingServerBuilder {
+    /// Accepts requests with this bearer token. Any request is accepted if no token is given
+    pub fn bearer_token(mut self, token: impl Into<String>) -> Self {
+        self.bearer_tokens.push(token.into());
+        self
+    }
+
+    /// Limits the number of items of the list responses, even if the client asks for more
+    pub fn max_page_size(mut self, max_page_size: usize) -> Self {
+        self.max_page_size = Some(max_page_size);
+        self
+    }
+
+    /// Key of the HMAC-SHA256 signatures of the data file URLs. A random key is used by
+    /// default, so the URLs handed out become invalid when the server restarts
+    pub fn signing_key(mut self, key: impl Into<Vec<u8>>) -> Self {
+        self.signing_key = Some(key.into());
+        self
+    }
+
+    /// Address to listen on, a free port of the loopback interface by default
+    pub fn bind(mut self, addr: SocketAddr) -> Self {
+        self.addr = addr;
+        self
+    }
+
+    /// Starts serving the tables in the background of the current tokio runtime
+    pub async fn start(self) -> Result<SharingServer, anyhow::Error> {
+        let token = self.bearer_tokens.first().cloned().unwrap_or_default();
+        let state = Arc::new(State {
+            root: self.root,
+            bearer_tokens: self.bearer_tokens,
+            max_page_size: self.max_page_size,
+            signing_key: self.signing_key.unwrap_or_else(|| {
+                let mut key = vec![0; 32];
+                OsRng.fill_bytes(&mut key);
+                key
+            }),
+        });
+        let make_service = make_service_fn(move |_| {
+            let state = state.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let state = state.clone();
+                    async move { Ok::<_, Infallible>(state.handle(request).await) }
+                }))
+            }
+        });
+        let server = hyper::Server::try_bind(&self.addr)?.serve(make_service);
+        let addr = server.local_addr();
+        let (shutdown, signal) = oneshot::channel::<()>();
+        let task = tokio::spawn(async move {
+            let server = server.with_graceful_shutdown(async {
+                signal.await.ok();
+            });
+            if let Err(e) = server.await {
+                error!("--> Sharing server failed: {}", e);
+            }
+        });
+        info!("--> Sharing server listening on {}", addr);
+        Ok(SharingServer {
+            addr,
+            token,
+            shutdown: Some(shutdown),
+            task: Some(task),
+        })
+    }
+}
+
+/// A running Delta Sharing server, see the [module documentation][self].
+///
+/// The server stops when [shutdown][SharingServer::shutdown] is called or when it is dropped.
+#[derive(Debug)]
+pub struct SharingServer {
+    addr: SocketAddr,
+    token: String,
+    shutdown: Option<oneshot::Sender<()>>,
+    task: Option<JoinHandle<()>>,
+}
+
+impl SharingServer {
+    /// Creates a builder of a server for the tables in `root`
+    pub fn builder(root: impl Into<PathBuf>) -> SharingServerBuilder {
+        SharingServerBuilder {
+            root: root.into(),
+            bearer_tokens: Vec::new(),
+            max_page_size: None,
+            signing_key: None,
+            addr: SocketAddr::from(([127, 0, 0, 1], 0)),
+        }
+    }
+
+    /// Address the server listens on
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Endpoint of the sharing server, ending with a slash
+    pub fn endpoint(&self) -> String {
+        format!("http://{}/", self.addr)
+    }
+
+    /// Profile to access the server, with the first configured bearer token
+    pub fn profile(&self) -> ProviderConfig {
+        ProviderConfig {
+            share_credentials_version: 1,
+            endpoint: self.endpoint(),
+            bearer_token: self.token.clone(),
+        }
+    }
+
+    /// Stops the server after the pending requests completed
+    pub async fn shutdown(mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            shutdown.send(()).ok();
+        }
+        if let Some(task) = self.task.take() {
+            task.await.ok();
+        }
+    }
+}
+
+impl Drop for SharingServer {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            shutdown.send(()).ok();
+        }
+    }
+}
+
+struct State {
+    root: PathBuf,
+    bearer_tokens: Vec<String>,
+    max_page_size: Option<usize>,
+    /// Key of the signatures of the data file URLs
+    signing_key: Vec<u8>,
+}
+
+impl State {
+    async fn handle(&self, request: Request<Body>) -> Response<Body> {
+        debug!(
+            "--> Sharing server request {} {}",
+            request.method(),
+            request.uri().path()
+        );
+        self.route(request)
+            .await
+            .unwrap_or_else(ServerError::into_response)
+    }
+
+    async fn route(&self, request: Request<Body>) -> ServerResult {
+        let segments = request
+            .uri()
+            .path()
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .map(|s| {
+                percent_encoding::percent_decode_str(s)
+                    .decode_utf8_lossy()
+                    .to_string()
+            })
+            .collect::<Vec<_>>();
+        let segments = segments.iter().map(|s| s.as_str()).collect::<Vec<_>>();
+        let query = request
+            .uri()
+            .query()
+            .map(|q| {
+                url::form_urlencoded::parse(q.as_bytes())
+                    .into_owned()
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        if let ["files", share, schema, table, path @ ..] = &segments[..] {
+            if request.method() != Method::GET {
+                return Err(ServerError::NotFound("Not found".to_string()));
+            }
+            let path = path.join("/");
+            return self
+                .file(&request, &query, share, schema, table, &path)
+                .await;
+        }
+
+        self.authorize(&request)?;
+        let method = request.method().clone();
+        match (&method, &segments[..]) {
+            (&Method::GET, ["shares"]) => self.page(
+                &query,
+                self.list(&self.root)?,
+                |name| json!({ "name": name, "id": table::id(name) }),
+            ),
+            (&Method::GET, ["shares", share]) => {
+                self.share_dir(share)?;
+                json_response(json!({ "share": { "name": share, "id": table::id(share) } }))
+            }
+            (&Method::GET, ["shares", share, "schemas"]) => {
+                let schemas = self.list(&self.share_dir(share)?)?;
+                self.page(
+                    &query,
+                    schemas,
+                    |name| json!({ "name": name, "share": share }),
+                )
+            }
+            (&Method::GET, ["shares", share, "all-tables"]) => {
+                let share_dir = self.share_dir(share)?;
+                let mut tables = Vec::new();
+                for schema in self.list(&share_dir)? {
+                    for table in self.list(&share_dir.join(&schema))? {
+                        tables.push((schema.clone(), table));
+                    }
+                }
+                self.page(&query, tables, |(schema, name)| {
+                    table_json(share, schema, name)
+                })
+            }
+            (&Method::GET, ["shares", share, "schemas", schema, "tables"]) => {
+                let schema_dir = self.share_dir(share)?.join(schema);
+                if !schema_dir.is_dir() {
+                    return Err(ServerError::NotFound(format!(
+                        "Schema {} not found",
+                        schema
+                    )));
+                }
+                let tables = self.list(&schema_dir)?;
+                self.page(&query, tables, |name| table_json(share, schema, name))
+            }
+            (&Method::HEAD, ["shares", share, "schemas", schema, "tables", table]) => {
+                let version = self.table(share, schema, table)?.version(None)?;
+                version_response(Body::empty(), version)
+            }
+            (&Method::GET, ["shares", share, "schemas", schema, "tables", table, "version"]) => {
+                let starting_timestamp = query
+                    .get("startingTimestamp")
+                    .map(|t| parse_timestamp(t))
+                    .transpose()?;
+                let version = self
+                    .table(share, schema, table)?
+                    .version(starting_timestamp)?;
+                version_response(Body::empty(), version)
+            }
+            (
+                &Method::GET,
+                ["shares", share, "schemas", schema, "tables", table_name, "metadata"],
+            ) => {
+                let table = self.table(share, schema, table_name)?;
+                let version = match (query.get("version"), query.get("timestamp")) {
+                    (Some(_), Some(_)) => {
+                        return Err(ServerError::BadRequest(
+                            "Only one of version and timestamp can be given".to_string(),
+                        ))
+                    }
+                    (Some(version), None) => Some(parse_version(version)?),
+                    (None, Some(timestamp)) => Some(table.version_at(parse_timestamp(timestamp)?)?),
+                    (None, None) => None,
+                };
+                let snapshot = table.snapshot(version)?;
+                let format = response_format(request.headers(), &snapshot, table_name)?;
+                let body = format!(
+                    "{}\n{}\n",
+                    protocol_line(format, &snapshot),
+                    metadata_line(format, &snapshot, None)
+                );
+                format_response(Body::from(body), snapshot.version, format)
+            }
+            (&Method::POST, ["shares", share, "schemas", schema, "tables", table, "query"]) => {
+                let (share, schema, table) =
+                    (share.to_string(), schema.to_string(), table.to_string());
+                self.query(request, &share, &schema, &table).await
+            }
+            _ => Err(ServerError::NotFound(format!(
+                "{} {} not found",
+                method,
+                segments.join("/")
+            ))),
+        }
+    }
+
+    fn authorize(&self, request: &Request<Body>) -> Result<(), ServerError> {
+        if self.bearer_tokens.is_empty() {
+            return Ok(());
+        }
+        let token = request
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "));
+        match token {
+            Some(token) if self.bearer_tokens.iter().any(|t| t == token) => Ok(()),
+            _ => Err(ServerError::Unauthorized),
+        }
+    }
+
+    /// Sorted names of the directories in `dir`, skipping hidden directories
+    fn list(&self, dir: &Path) -> Result<Vec<String>, ServerError> {
+        let mut names = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if entry.file_type()?.is_dir() && !name.starts_with('.') && !name.starts_with('_') {
+                names.push(name);
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    fn share_dir(&self, share: &str) -> Result<PathBuf, ServerError> {
+        let dir = self.root.join(checked_name(share)?);
+        if dir.is_dir() {
+            Ok(dir)
+        } else {
+            Err(ServerError::NotFound(format!("Share {} not found", share)))
+        }
+    }
+
+    fn table(&self, share: &str, schema: &str, table: &str) -> Result<LocalTable, ServerError> {
+        let dir = self
+            .share_dir(share)?
+            .join(checked_name(schema)?)
+            .join(checked_name(table)?);
+        if dir.is_dir() {
+            Ok(LocalTable::new(dir))
+        } else {
+            Err(ServerError::NotFound(format!(
+                "Table {}.{}.{} not found",
+                share, schema, table
+            )))
+        }
+    }
+
+    /// Lists a page of the items, starting at the offset in the `pageToken`
+    fn page<T>(
+        &self,
+        query: &HashMap<String, String>,
+        items: Vec<T>,
+        to_json: impl Fn(&T) -> Value,
+    ) -> ServerResult {
+        let start = match query.get("pageToken").filter(|t| !t.is_empty()) {
+            Some(token) => token
+                .parse::<usize>()
+                .map_err(|_| ServerError::BadRequest(format!("Invalid page token {}", token)))?,
+            None => 0,
+        };
+        let mut size = match query.get("maxResults") {
+            Some(max) => max
+                .parse::<usize>()
+                .map_err(|_| ServerError::BadRequest(format!("Invalid maxResults {}", max)))?,
+            None => usize::MAX,
+        };
+        if let Some(max_page_size) = self.max_page_size {
+            size = size.min(max_page_size);
+        }
+        let end = start.saturating_add(size).min(items.len());
+        let page = items
+            .get(start..end)
+            .unwrap_or_default()
+            .iter()
+            .map(to_json)
+            .collect::<Vec<_>>();
+        let mut body = json!({ "items": page });
+        if end < items.len() {
+            body["nextPageToken"] = json!(end.to_string());
+        }
+        json_response(body)
+    }
+
+    async fn query(
+        &self,
+        request: Request<Body>,
+        share: &str,
+        schema: &str,
+        table_name: &str,
+    ) -> ServerResult {
+        let host = request
+            .headers()
+            .get(header::HOST)
+            .and_then(|h| h.to_str().ok())
+            .unwrap_or("localhost")
+            .to_string();
+        let headers = request.headers().clone();
+        let body = hyper::body::to_bytes(request.into_body())
+            .await
+            .map_err(|e| ServerError::BadRequest(e.to_string()))?;
+        let body: Value = if body.is_empty() {
+            json!({})
+        } else {
+            serde_json::from_slice(&body).map_err(|e| ServerError::BadRequest(e.to_string()))?
+        };
+        let table = self.table(share, schema, table_name)?;
+        let expiration = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .saturating_add(URL_EXPIRATION)
+            .as_millis() as i64;
+        let base = Url::parse(&format!("http://{}/", host))
+            .map_err(|e| ServerError::BadRequest(e.to_string()))?;
+        let file_url = |path: &str| {
+            let mut url = base.clone();
+            url.path_segments_mut()
+                .unwrap()
+                .extend(["files", share, schema, table_name])
+                .extend(path.split('/'));
+            let signature = self.signature(url.path(), expiration);
+            url.query_pairs_mut()
+                .append_pair("expires", &expiration.to_string())
+                .append_pair("signature", &signature);
+            url.to_string()
+        };
+        // A file line, with the version and commit timestamp of the change for change listings
+        let file_line = |format: ResponseFormat,
+                         file: &DataFile,
+                         change: Option<&FileChange>|
+         -> Result<String, ServerError> {
+            let url = file_url(&file.path);
+            let removed = change.is_some_and(|c| !c.added);
+            if format == ResponseFormat::Parquet {
+                let action = match change {
+                    Some(_) if removed => "remove",
+                    Some(_) => "add",
+                    None => "file",
+                };
+                let mut json = json!({
+                    "id": table::id(&file.path),
+                    "url": url,
+                    "partitionValues": file.partition_values,
+                    "size": file.size,
+                    "stats": file.stats,
+                    "expirationTimestamp": expiration,
+                });
+                if let Some(change) = change {
+                    json["version"] = json!(change.version);
+                    json["timestamp"] = json!(change.timestamp);
+                }
+                return Ok(json!({ action: json }).to_string());
+            }
+
+            let mut delta_action = json!({
+                "path": url,
+                "partitionValues": file.partition_values,
+                "size": file.size,
+                "dataChange": true,
+            });
+            match change {
+                Some(change) if removed => {
+                    delta_action["deletionTimestamp"] = json!(change.timestamp)
+                }
+                _ => {
+                    delta_action["modificationTime"] = json!(file.modification_time);
+                    delta_action["stats"] = json!(file.stats);
+                }
+            }
+            if let Some(descriptor) = &file.deletion_vector {
+                let mut descriptor = descriptor.clone();
+                if let Some(path) = table::deletion_vector_path(&descriptor)? {
+                    descriptor["storageType"] = json!("p");
+                    descriptor["pathOrInlineDv"] = json!(file_url(&path));
+                }
+                delta_action["deletionVector"] = descriptor;
+            }
+            let action = if removed { "remove" } else { "add" };
+            let mut json = json!({
+                "id": table::id(&file.path),
+                "expirationTimestamp": expiration,
+                "deltaSingleAction": { action: delta_action },
+            });
+            if let Some(change) = change {
+                json["version"] = json!(change.version);
+                json["timestamp"] = json!(change.timestamp);
+            }
+            Ok(json!({ "file": json }).to_string())
+        };
+
+        if let Some(starting_version) = body.get("startingVersion") {
+            let starting_version = version_option(starting_version)?;
+            let ending_version = body.get("endingVersion").map(version_option).transpose()?;
+            let (snapshot, changes) = table.changes(starting_version, ending_version)?;
+            let format = response_format(&headers, &snapshot, table_name)?;
+            if format == ResponseFormat::Parquet
+                && changes.iter().any(|c| c.file.deletion_vector.is_some())
+            {
+                return Err(deletion_vectors_error(table_name));
+            }
+            let mut lines = vec![
+                protocol_line(format, &snapshot),
+                metadata_line(format, &snapshot, Some(snapshot.version)),
+            ];
+            for change in &changes {
+                lines.push(file_line(format, &change.file, Some(change))?);
+            }
+            lines.push(String::new());
+            return format_response(Body::from(lines.join("\n")), starting_version, format);
+        }
+
+        let version = match (body.get("version"), body.get("timestamp")) {
+            (Some(_), Some(_)) => {
+                return Err(ServerError::BadRequest(
+                    "Only one of version and timestamp can be given".to_string(),
+                ))
+            }
+            (Some(version), None) => Some(version_option(version)?),
+            (None, Some(timestamp)) => {
+                let timestamp = timestamp.as_str().unwrap_or_default();
+                Some(table.version_at(parse_timestamp(timestamp)?)?)
+            }
+            (None, None) => None,
+        };
+        let snapshot = table.snapshot(version)?;
+        let format = response_format(&headers, &snapshot, table_name)?;
+        let mut lines = vec![
+            protocol_line(format, &snapshot),
+            metadata_line(format, &snapshot, None),
+        ];
+        for file in &snapshot.files {
+            lines.push(file_line(format, file, None)?);
+        }
+        lines.push(String::new());
+        format_response(Body::from(lines.join("\n")), snapshot.version, format)
+    }
+
+    fn mac(&self, path: &str, expiration: i64) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.signing_key)
+            .expect("HMAC accepts keys of any length");
+        mac.update(format!("{}:{}", path, expiration).as_bytes());
+        mac
+    }
+
+    /// Hex encoded signature of the URL path of a data file
+    fn signature(&self, path: &str, expiration: i64) -> String {
+        self.mac(path, expiration)
+            .finalize()
+            .into_bytes()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    /// Checks the signature of a data file URL in constant time
+    fn verify_signature(&self, path: &str, expiration: i64, signature: &str) -> bool {
+        let signature = (0..signature.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(signature.get(i..i + 2)?, 16).ok())
+            .collect::<Option<Vec<_>>>();
+        match signature {
+            Some(signature) => self.mac(path, expiration).verify_slice(&signature).is_ok(),
+            None => false,
+        }
+    }
+
+    /// Serves a data file, or the requested range of it
+    async fn file(
+        &self,
+        request: &Request<Body>,
+        query: &HashMap<String, String>,
+        share: &str,
+        schema: &str,
+        table: &str,
+        path: &str,
+    ) -> ServerResult {
+        let expiration = query
+            .get("expires")
+            .and_then(|e| e.parse::<i64>().ok())
+            .ok_or_else(|| ServerError::BadRequest("Missing expiration".to_string()))?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as i64;
+        let signature = query.get("signature").map_or("", |s| s.as_str());
+        if !self.verify_signature(request.uri().path(), expiration, signature) {
+            return Err(ServerError::BadRequest("Invalid signature".to_string()));
+        }
+        if expiration < now {
+            return Ok(Response::builder()
+                .status(StatusCode::FORBIDDEN)
+                .body(Body::from("Request has expired"))
+                .unwrap());
+        }
+
+        let table = self.table(share, schema, table)?;
+        let relative = Path::new(path);
+        if relative
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_)))
+        {
+            return Err(ServerError::NotFound(format!("File {} not found", path)));
+        }
+        let mut file = tokio::fs::File::open(table.root().join(relative)).await?;
+        let len = file.metadata().await?.len() as usize;
+
+        let range = request
+            .headers()
+            .get(header::RANGE)
+            .and_then(|r| r.to_str().ok())
+            .map(|r| parse_range(r, len));
+        match range {
+            None => {
+                let mut data = Vec::with_capacity(len);
+                file.read_to_end(&mut data).await?;
+                Ok(Response::builder()
+                    .header(header::CONTENT_LENGTH, data.len())
+                    .header(header::ACCEPT_RANGES, "bytes")
+                    .body(Body::from(data))
+                    .unwrap())
+            }
+            Some(Some((start, end))) => {
+                // Only the requested range is read, as clients fetch the footers and column
+                // chunks of large files
+                let mut data = vec![0; end + 1 - start];
+                file.seek(SeekFrom::Start(start as u64)).await?;
+                file.read_exact(&mut data).await?;
+                Ok(Response::builder()
+                    .status(StatusCode::PARTIAL_CONTENT)
+                    .header(
+                        header::CONTENT_RANGE,
+                        format!("bytes {}-{}/{}", start, end, len),
+                    )
+                    .header(header::CONTENT_LENGTH, data.len())
+                    .body(Body::from(data))
+                    .unwrap())
+            }
+            Some(None) => Ok(Response::builder()
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(header::CONTENT_RANGE, format!("bytes */{}", len))
+                .body(Body::empty())
+                .unwrap()),
+        }
+    }
+}
+
+/// Rejects names which would escape the directory of the tables
+fn checked_name(name: &str) -> Result<&str, ServerError> {
+    if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\']) {
+        Err(ServerError::NotFound(format!("{} not found", name)))
+    } else {
+        Ok(name)
+    }
+}
+
+/// Inclusive byte range of a `Range` header, None if it can not be satisfied
+fn parse_range(range: &str, len: usize) -> Option<(usize, usize)> {
+    let (start, end) = range.strip_prefix("bytes=")?.split_once('-')?;
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let suffix = suffix.parse::<usize>().ok()?.min(len);
+            (len - suffix, len.checked_sub(1)?)
+        }
+        (start, "") => (start.parse().ok()?, len.checked_sub(1)?),
+        (start, end) => (
+            start.parse().ok()?,
+            end.parse::<usize>().ok()?.min(len.checked_sub(1)?),
+        ),
+    };
+    (start <= end && end < len).then_some((start, end))
+}
+
+fn parse_version(version: &str) -> Result<i64, ServerError> {
+    version
+        .parse()
+        .map_err(|_| ServerError::BadRequest(format!("Invalid version {}", version)))
+}
+
+/// Milliseconds since the epoch of a timestamp in ISO 8601 format
+fn parse_timestamp(timestamp: &str) -> Result<i64, ServerError> {
+    chrono::DateTime::parse_from_rfc3339(timestamp)
+        .map(|t| t.timestamp_millis())
+        .map_err(|_| ServerError::BadRequest(format!("Invalid timestamp {}", timestamp)))
+}
+
+fn version_option(value: &Value) -> Result<i64, ServerError> {
+    value
+        .as_i64()
+        .ok_or_else(|| ServerError::BadRequest(format!("Invalid version {}", value)))
+}
+
+/// Listing entry of a table, with ids derived from its names
+fn table_json(share: &str, schema: &str, name: &str) -> Value {
+    json!({
+        "name": name,
+        "schema": schema,
+        "share": share,
+        "shareId": table::id(share),
+        "id": table::id(&format!("{}.{}.{}", share, schema, name)),
+    })
+}
+
+fn json_response(body: Value) -> ServerResult {
+    Ok(Response::builder()
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap())
+}
+
+/// Format of the listings of a table: the first format of the `responseformat` capability of
+/// the request, or `delta` for tables with deletion vectors
+fn response_format(
+    headers: &HeaderMap,
+    snapshot: &Snapshot,
+    table_name: &str,
+) -> Result<ResponseFormat, ServerError> {
+    let capabilities = headers
+        .get(CAPABILITIES_HEADER)
+        .and_then(|c| c.to_str().ok())
+        .unwrap_or_default();
+    let mut formats = capabilities
+        .split(';')
+        .filter_map(|c| c.split_once('='))
+        .filter(|(name, _)| name.trim().eq_ignore_ascii_case("responseformat"))
+        .flat_map(|(_, formats)| formats.split(','))
+        .filter_map(|f| match f.trim().to_lowercase().as_str() {
+            "parquet" => Some(ResponseFormat::Parquet),
+            "delta" => Some(ResponseFormat::Delta),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    if formats.is_empty() {
+        formats.push(ResponseFormat::Parquet);
+    }
+    if snapshot.has_deletion_vectors() {
+        return match formats.contains(&ResponseFormat::Delta) {
+            true => Ok(ResponseFormat::Delta),
+            false => Err(deletion_vectors_error(table_name)),
+        };
+    }
+    Ok(formats[0])
+}
+
+fn deletion_vectors_error(table_name: &str) -> ServerError {
+    ServerError::BadRequest(format!(
+        "Table {} has deletion vectors, which are only supported with responseformat=delta",
+        table_name
+    ))
+}
+
+fn protocol_line(format: ResponseFormat, snapshot: &Snapshot) -> String {
+    match format {
+        ResponseFormat::Parquet => {
+            json!({ "protocol": { "minReaderVersion": snapshot.protocol["minReaderVersion"] } })
+        }
+        ResponseFormat::Delta => json!({ "protocol": { "deltaProtocol": snapshot.protocol } }),
+    }
+    .to_string()
+}
+
+/// The metadata line, with the table version for change listings
+fn metadata_line(format: ResponseFormat, snapshot: &Snapshot, version: Option<i64>) -> String {
+    let mut metadata = match format {
+        ResponseFormat::Parquet => snapshot.metadata.clone(),
+        ResponseFormat::Delta => json!({ "deltaMetadata": snapshot.metadata }),
+    };
+    if let Some(version) = version {
+        metadata["version"] = json!(version);
+    }
+    json!({ "metaData": metadata }).to_string()
+}
+
+/// Response of a metadata or query request, with the format of the listing
+fn format_response(body: Body, version: i64, format: ResponseFormat) -> ServerResult {
+    let mut response = version_response(body, version)?;
+    response.headers_mut().insert(
+        CAPABILITIES_HEADER,
+        HeaderValue::from_str(&format!("responseformat={}", format.as_str())).unwrap(),
+    );
+    Ok(response)
+}
+
+fn version_response(body: Body, version: i64) -> ServerResult {
+    let mut response = Response::builder()
+        .header(header::CONTENT_TYPE, "application/x-ndjson")
+        .body(body)
+        .unwrap();
+    response.headers_mut().insert(
+        TABLE_VERSION_HEADER,
+        HeaderValue::from_str(&version.to_string()).unwrap(),
+    );
+    Ok(response)
+}
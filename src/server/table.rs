@@ -0,0 +1,483 @@
+//! Snapshots of the tables served by the local server
+
+use super::ServerError;
+use crate::deletion_vector;
+use arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+use parquet::arrow::parquet_to_arrow_schema;
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use percent_encoding::percent_decode_str;
+use serde_json::{json, Map, Value};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+const DELTA_LOG_DIR: &str = "_delta_log";
+
+/// A data file of a table snapshot
+pub(crate) struct DataFile {
+    /// Path relative to the table directory
+    pub path: String,
+    pub partition_values: Map<String, Value>,
+    pub size: i64,
+    /// Last modification of the file in milliseconds since the epoch
+    pub modification_time: i64,
+    pub stats: Option<String>,
+    /// Descriptor of the deletion vector of the file, see [deletion_vector_path]
+    pub deletion_vector: Option<Value>,
+}
+
+/// A file added or removed by a version of the table
+pub(crate) struct FileChange {
+    pub version: i64,
+    /// Commit timestamp of the version in milliseconds since the epoch
+    pub timestamp: i64,
+    pub added: bool,
+    pub file: DataFile,
+}
+
+/// The state of a table at a version
+pub(crate) struct Snapshot {
+    pub version: i64,
+    /// The Delta protocol action
+    pub protocol: Value,
+    pub metadata: Value,
+    pub files: Vec<DataFile>,
+}
+
+impl Snapshot {
+    /// Whether the table may have deletion vectors, which can only be sent in the `delta`
+    /// response format
+    pub fn has_deletion_vectors(&self) -> bool {
+        let features = self.protocol["readerFeatures"].as_array();
+        features.is_some_and(|f| f.contains(&json!("deletionVectors")))
+            || self.files.iter().any(|f| f.deletion_vector.is_some())
+    }
+}
+
+/// A Delta table, or a directory of parquet files served as a table with a single version
+pub(crate) struct LocalTable {
+    root: PathBuf,
+}
+
+impl LocalTable {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn is_delta(&self) -> bool {
+        self.root.join(DELTA_LOG_DIR).is_dir()
+    }
+
+    /// Versions of the table with their commit timestamps in milliseconds since the epoch
+    pub fn versions(&self) -> Result<Vec<(i64, i64)>, ServerError> {
+        if !self.is_delta() {
+            return Ok(vec![(0, modified_millis(&self.root)?)]);
+        }
+        let mut versions = Vec::new();
+        for entry in fs::read_dir(self.root.join(DELTA_LOG_DIR))? {
+            let path = entry?.path();
+            // Commits are named after their version padded to 20 digits
+            let version = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_suffix(".json"))
+                .filter(|v| v.len() == 20 && v.bytes().all(|b| b.is_ascii_digit()))
+                .and_then(|v| v.parse::<i64>().ok());
+            let version = match version {
+                Some(version) => version,
+                None => continue,
+            };
+            let timestamp = self
+                .commit_timestamp(&path)?
+                .map_or_else(|| modified_millis(&path), Ok)?;
+            versions.push((version, timestamp));
+        }
+        versions.sort_unstable();
+        if versions.is_empty() {
+            return Err(ServerError::NotFound(format!(
+                "Table {} has no commits",
+                self.root.display()
+            )));
+        }
+        Ok(versions)
+    }
+
+    fn commit_timestamp(&self, commit: &Path) -> Result<Option<i64>, ServerError> {
+        for action in read_actions(commit)? {
+            if let Some(timestamp) = action
+                .get("commitInfo")
+                .and_then(|c| c.get("timestamp"))
+                .and_then(|t| t.as_i64())
+            {
+                return Ok(Some(timestamp));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Latest version of the table, or the earliest version committed at or after the timestamp
+    pub fn version(&self, starting_timestamp: Option<i64>) -> Result<i64, ServerError> {
+        let versions = self.versions()?;
+        match starting_timestamp {
+            None => Ok(versions.last().unwrap().0),
+            Some(timestamp) => versions
+                .iter()
+                .find(|(_, committed)| *committed >= timestamp)
+                .map(|(version, _)| *version)
+                .ok_or_else(|| {
+                    ServerError::BadRequest(
+                        "The timestamp is after the latest version of the table".to_string(),
+                    )
+                }),
+        }
+    }
+
+    /// Version of the table which was current at the timestamp
+    pub fn version_at(&self, timestamp: i64) -> Result<i64, ServerError> {
+        self.versions()?
+            .iter()
+            .rev()
+            .find(|(_, committed)| *committed <= timestamp)
+            .map(|(version, _)| *version)
+            .ok_or_else(|| {
+                ServerError::BadRequest(
+                    "The timestamp is before the first version of the table".to_string(),
+                )
+            })
+    }
+
+    /// The table at the given version, or at its latest version
+    pub fn snapshot(&self, version: Option<i64>) -> Result<Snapshot, ServerError> {
+        if self.is_delta() {
+            self.delta_snapshot(version)
+        } else {
+            match version {
+                None | Some(0) => self.directory_snapshot(),
+                Some(version) => Err(ServerError::NotFound(format!(
+                    "Version {} of the table does not exist",
+                    version
+                ))),
+            }
+        }
+    }
+
+    fn delta_snapshot(&self, version: Option<i64>) -> Result<Snapshot, ServerError> {
+        let versions = self.versions()?;
+        let version = version.unwrap_or(versions.last().unwrap().0);
+        if !versions.iter().any(|(v, _)| *v == version) {
+            return Err(ServerError::NotFound(format!(
+                "Version {} of the table does not exist",
+                version
+            )));
+        }
+        let mut protocol = None;
+        let mut metadata = None;
+        let mut files = BTreeMap::new();
+        for (v, _) in versions.iter().filter(|(v, _)| *v <= version) {
+            let commit = self
+                .root
+                .join(DELTA_LOG_DIR)
+                .join(format!("{:020}.json", v));
+            for action in read_actions(&commit)? {
+                if let Some(p) = action.get("protocol") {
+                    protocol = Some(p.clone());
+                } else if let Some(m) = action.get("metaData") {
+                    metadata = Some(json!({
+                        "id": m["id"],
+                        "name": m.get("name").unwrap_or(&Value::Null),
+                        "description": m.get("description").unwrap_or(&Value::Null),
+                        "format": m["format"],
+                        "schemaString": m["schemaString"],
+                        "partitionColumns": m["partitionColumns"],
+                        "configuration": m.get("configuration").cloned().unwrap_or_else(|| json!({})),
+                    }));
+                } else if let Some(add) = action.get("add") {
+                    let path = add["path"].as_str().unwrap_or_default().to_string();
+                    files.insert(path, data_file(add));
+                } else if let Some(remove) = action.get("remove") {
+                    files.remove(remove["path"].as_str().unwrap_or_default());
+                }
+            }
+        }
+        let (protocol, metadata) = match (protocol, metadata) {
+            (Some(protocol), Some(metadata)) => (protocol, metadata),
+            _ => {
+                return Err(ServerError::Internal(format!(
+                    "Table {} has no protocol or metadata",
+                    self.root.display()
+                )))
+            }
+        };
+        Ok(Snapshot {
+            version,
+            protocol,
+            metadata,
+            files: files.into_values().collect(),
+        })
+    }
+
+    /// The files added and removed by the versions from `starting_version` to `ending_version`,
+    /// or to the latest version, with the snapshot of the last of these versions
+    pub fn changes(
+        &self,
+        starting_version: i64,
+        ending_version: Option<i64>,
+    ) -> Result<(Snapshot, Vec<FileChange>), ServerError> {
+        let versions = self.versions()?;
+        let ending_version = ending_version.unwrap_or(versions.last().unwrap().0);
+        if starting_version < 0 || starting_version > ending_version {
+            return Err(ServerError::BadRequest(format!(
+                "Invalid version range {}..{}",
+                starting_version, ending_version
+            )));
+        }
+        let mut snapshot = self.snapshot(Some(ending_version))?;
+        let mut changes = Vec::new();
+        if !self.is_delta() {
+            let timestamp = versions[0].1;
+            changes.extend(snapshot.files.drain(..).map(|file| FileChange {
+                version: 0,
+                timestamp,
+                added: true,
+                file,
+            }));
+            return Ok((snapshot, changes));
+        }
+        for (version, timestamp) in versions
+            .iter()
+            .filter(|(v, _)| (starting_version..=ending_version).contains(v))
+        {
+            let commit = self
+                .root
+                .join(DELTA_LOG_DIR)
+                .join(format!("{:020}.json", version));
+            for action in read_actions(&commit)? {
+                let (added, action) = match (action.get("add"), action.get("remove")) {
+                    (Some(add), _) => (true, add),
+                    (None, Some(remove)) => (false, remove),
+                    (None, None) => continue,
+                };
+                changes.push(FileChange {
+                    version: *version,
+                    timestamp: *timestamp,
+                    added,
+                    file: data_file(action),
+                });
+            }
+        }
+        Ok((snapshot, changes))
+    }
+
+    fn directory_snapshot(&self) -> Result<Snapshot, ServerError> {
+        let mut paths = Vec::new();
+        list_parquet_files(&self.root, &mut paths)?;
+        paths.sort();
+        let mut files = Vec::new();
+        let mut schema = None;
+        for path in paths {
+            let reader = SerializedFileReader::new(fs::File::open(&path)?)
+                .map_err(|e| ServerError::Internal(e.to_string()))?;
+            let file_metadata = reader.metadata().file_metadata();
+            if schema.is_none() {
+                let arrow_schema = parquet_to_arrow_schema(
+                    file_metadata.schema_descr(),
+                    file_metadata.key_value_metadata(),
+                )
+                .map_err(|e| ServerError::Internal(e.to_string()))?;
+                schema = Some(schema_string(&arrow_schema));
+            }
+            files.push(DataFile {
+                path: path
+                    .strip_prefix(&self.root)
+                    .unwrap()
+                    .to_string_lossy()
+                    .replace('\\', "/"),
+                partition_values: Map::new(),
+                size: fs::metadata(&path)?.len() as i64,
+                modification_time: modified_millis(&path)?,
+                stats: Some(json!({ "numRecords": file_metadata.num_rows() }).to_string()),
+                deletion_vector: None,
+            });
+        }
+        let schema = schema.unwrap_or_else(|| schema_string(&Schema::empty()));
+        Ok(Snapshot {
+            version: 0,
+            protocol: json!({ "minReaderVersion": 1, "minWriterVersion": 2 }),
+            metadata: json!({
+                "id": id(&self.root.to_string_lossy()),
+                "format": { "provider": "parquet" },
+                "schemaString": schema,
+                "partitionColumns": [],
+                "configuration": {},
+            }),
+            files,
+        })
+    }
+}
+
+/// The data file of an add or remove action
+fn data_file(action: &Value) -> DataFile {
+    let path = action["path"].as_str().unwrap_or_default();
+    DataFile {
+        path: percent_decode_str(path).decode_utf8_lossy().to_string(),
+        partition_values: action["partitionValues"]
+            .as_object()
+            .cloned()
+            .unwrap_or_default(),
+        size: action["size"].as_i64().unwrap_or_default(),
+        modification_time: action["modificationTime"].as_i64().unwrap_or_default(),
+        stats: action["stats"].as_str().map(|s| s.to_string()),
+        deletion_vector: action
+            .get("deletionVector")
+            .filter(|dv| !dv.is_null())
+            .cloned(),
+    }
+}
+
+/// Path relative to the table directory of a deletion vector stored next to the table, None
+/// if it is stored inline. Deletion vectors at absolute paths can not be served
+pub(crate) fn deletion_vector_path(descriptor: &Value) -> Result<Option<String>, ServerError> {
+    let path_or_inline_dv = descriptor["pathOrInlineDv"].as_str().unwrap_or_default();
+    match descriptor["storageType"].as_str() {
+        Some("i") => Ok(None),
+        Some("u") if path_or_inline_dv.len() >= 20 => {
+            let (prefix, encoded_uuid) = path_or_inline_dv.split_at(path_or_inline_dv.len() - 20);
+            let uuid = deletion_vector::z85_decode(encoded_uuid)
+                .map_err(|e| ServerError::Internal(e.to_string()))?
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<String>();
+            let file = format!(
+                "deletion_vector_{}-{}-{}-{}-{}.bin",
+                &uuid[..8],
+                &uuid[8..12],
+                &uuid[12..16],
+                &uuid[16..20],
+                &uuid[20..]
+            );
+            Ok(Some(if prefix.is_empty() {
+                file
+            } else {
+                format!("{}/{}", prefix, file)
+            }))
+        }
+        storage_type => Err(ServerError::Internal(format!(
+            "Deletion vectors with storage type {} are not supported",
+            storage_type.unwrap_or_default()
+        ))),
+    }
+}
+
+/// A stable identifier derived from the value
+pub(crate) fn id(value: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+fn read_actions(path: &Path) -> Result<Vec<Value>, ServerError> {
+    let mut actions = Vec::new();
+    for line in BufReader::new(fs::File::open(path)?).lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            actions.push(
+                serde_json::from_str(&line).map_err(|e| ServerError::Internal(e.to_string()))?,
+            );
+        }
+    }
+    Ok(actions)
+}
+
+fn modified_millis(path: &Path) -> Result<i64, ServerError> {
+    let modified = fs::metadata(path)?.modified()?;
+    Ok(modified
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64)
+}
+
+fn list_parquet_files(dir: &Path, paths: &mut Vec<PathBuf>) -> Result<(), ServerError> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = path.file_name().unwrap().to_string_lossy();
+        if name.starts_with('.') || name.starts_with('_') {
+            continue;
+        }
+        if path.is_dir() {
+            list_parquet_files(&path, paths)?;
+        } else if name.ends_with(".parquet") {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Delta schema string of an Arrow schema
+pub(crate) fn schema_string(schema: &Schema) -> String {
+    struct_type(schema.fields()).to_string()
+}
+
+/// Delta schema of a struct with the given Arrow fields
+fn struct_type(fields: &[Field]) -> Value {
+    json!({
+        "type": "struct",
+        "fields": fields
+            .iter()
+            .map(|f| json!({
+                "name": f.name(),
+                "type": delta_type(f.data_type()),
+                "nullable": f.is_nullable(),
+                "metadata": {},
+            }))
+            .collect::<Vec<_>>(),
+    })
+}
+
+/// Delta type of an Arrow data type
+fn delta_type(data_type: &DataType) -> Value {
+    match data_type {
+        DataType::Boolean => json!("boolean"),
+        DataType::Int8 | DataType::UInt8 => json!("byte"),
+        DataType::Int16 | DataType::UInt16 => json!("short"),
+        DataType::Int32 | DataType::UInt32 => json!("integer"),
+        DataType::Int64 | DataType::UInt64 => json!("long"),
+        DataType::Float16 | DataType::Float32 => json!("float"),
+        DataType::Float64 => json!("double"),
+        DataType::Utf8 | DataType::LargeUtf8 => json!("string"),
+        DataType::Binary | DataType::LargeBinary | DataType::FixedSizeBinary(_) => {
+            json!("binary")
+        }
+        DataType::Date32 | DataType::Date64 => json!("date"),
+        DataType::Timestamp(TimeUnit::Second | TimeUnit::Millisecond, _)
+        | DataType::Timestamp(TimeUnit::Microsecond | TimeUnit::Nanosecond, _) => {
+            json!("timestamp")
+        }
+        DataType::Decimal(precision, scale) => json!(format!("decimal({},{})", precision, scale)),
+        DataType::List(field) | DataType::LargeList(field) | DataType::FixedSizeList(field, _) => {
+            json!({
+                "type": "array",
+                "elementType": delta_type(field.data_type()),
+                "containsNull": field.is_nullable(),
+            })
+        }
+        DataType::Struct(fields) => struct_type(fields),
+        DataType::Map(field, _) => match field.data_type() {
+            DataType::Struct(entries) if entries.len() == 2 => json!({
+                "type": "map",
+                "keyType": delta_type(entries[0].data_type()),
+                "valueType": delta_type(entries[1].data_type()),
+                "valueContainsNull": entries[1].is_nullable(),
+            }),
+            _ => json!("binary"),
+        },
+        _ => json!("string"),
+    }
+}
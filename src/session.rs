@@ -0,0 +1,667 @@
+//! Protocol calls, downloads and caching shared by both clients.
+//!
+//! A [Session] borrows the state of a client for the duration of a call. The async
+//! [Client][crate::Client] awaits its methods, the [blocking Client][crate::blocking::Client]
+//! runs them on its runtime, and both only differ in their [Transport].
+
+use crate::cache::{CacheStore, CacheVerification};
+use crate::core::{Core, Request, Response, SyncedFiles};
+use crate::error::Error;
+use crate::export::{ExportFormat, ExportOptions, Exporter};
+use crate::incremental::{IncrementalReader, MicroBatch};
+use crate::mirror::{Mirror, MirrorSummary};
+use crate::protocol::*;
+use crate::remote::{self, ReadOptions};
+use crate::telemetry::Span;
+use crate::transport::Transport;
+use crate::utils::*;
+use crate::watch::{VersionEvent, Watcher};
+use arrow::record_batch::RecordBatch;
+use bytes::Bytes;
+use polars::prelude::{DataFrame, LazyFrame};
+use reqwest::header::{HeaderName, HeaderValue};
+use reqwest::StatusCode;
+use roaring::RoaringTreemap;
+use serde::de::DeserializeOwned;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The state of a client used by a call
+pub(crate) struct Session<'a, T> {
+    pub core: &'a Core,
+    pub transport: &'a T,
+    /// Client of the range requests against the presigned file URLs
+    pub range_client: &'a reqwest::Client,
+    pub data_root: &'a str,
+    pub response_format: ResponseFormat,
+    pub best_effort_read: bool,
+}
+
+impl<T: Transport> Session<'_, T> {
+    async fn execute(&self, mut request: Request) -> Result<Response, Error> {
+        request.headers.insert(
+            HeaderName::from_static(CAPABILITIES_HEADER),
+            HeaderValue::from_str(&capabilities(self.response_format))
+                .expect("Capabilities are a valid header value"),
+        );
+        let span = self.core.request_span(&request);
+        let result = span
+            .instrument(self.execute_authorized(&request, &span))
+            .await;
+        span.finish_request(&result);
+        result
+    }
+
+    /// Executes the request with the token of the credential provider. If the token is
+    /// rejected, the request is retried once with a refreshed token
+    async fn execute_authorized(&self, request: &Request, span: &Span) -> Result<Response, Error> {
+        let credentials = self.core.credentials();
+        let token = credentials.token().await.map_err(Error::Credentials)?;
+        match self.send(request, &token).await {
+            Err(Error::Http(e)) if e.status() == Some(StatusCode::UNAUTHORIZED) => {
+                debug!("--> Token rejected, refreshing the credentials");
+                span.retried();
+                match credentials
+                    .refresh(&token)
+                    .await
+                    .map_err(Error::Credentials)?
+                {
+                    Some(token) => self.send(request, &token).await,
+                    None => Err(e.into()),
+                }
+            }
+            res => res,
+        }
+    }
+
+    async fn send(&self, request: &Request, token: &str) -> Result<Response, Error> {
+        debug!("--> HTTP {} to: {}", &request.method, &request.url);
+        let response = self.transport.send(request, token).await?;
+        self.core.log_response(&response);
+        Ok(response)
+    }
+
+    /// Fetches a data or deletion vector file of the table from its presigned URL
+    async fn fetch(&self, table: &Table, url: &str) -> Result<Bytes, reqwest::Error> {
+        let span = self.core.download_span(table, url);
+        let result = span.instrument(self.get(url)).await;
+        span.finish_download(
+            result
+                .as_ref()
+                .map(|c| c.len() as u64)
+                .map_err(|e| e.status()),
+        );
+        result
+    }
+
+    async fn get(&self, url: &str) -> Result<Bytes, reqwest::Error> {
+        self.transport.get(url).await.map_err(redact_error)
+    }
+
+    /// Requests the pages of a listing until the server returns no next page token
+    async fn list_pages<I: DeserializeOwned>(
+        &self,
+        request: impl Fn(Option<&str>) -> Request,
+    ) -> Result<Vec<I>, anyhow::Error> {
+        let mut items = Vec::new();
+        let mut page_token = None;
+        loop {
+            let resp = self.execute(request(page_token.as_deref())).await?;
+            let page = Core::parse::<ListResponse<I>>(&resp)?;
+            items.extend(page.items);
+            match page.next_page_token.filter(|t| !t.is_empty()) {
+                Some(token) => page_token = Some(token),
+                None => return Ok(items),
+            }
+        }
+    }
+
+    pub async fn list_shares(&self) -> Result<Vec<Share>, anyhow::Error> {
+        self.list_pages(|token| self.core.list_shares_request(token))
+            .await
+    }
+
+    pub async fn get_share(&self, name: &str) -> Result<Share, anyhow::Error> {
+        let resp = self.execute(self.core.get_share_request(name)).await?;
+        Ok(Core::parse::<GetShareResponse>(&resp)?.share)
+    }
+
+    pub async fn list_schemas(&self, share: &Share) -> Result<Vec<Schema>, anyhow::Error> {
+        self.list_pages(|token| self.core.list_schemas_request(share, token))
+            .await
+    }
+
+    pub async fn list_tables(&self, schema: &Schema) -> Result<Vec<Table>, anyhow::Error> {
+        self.list_pages(|token| self.core.list_tables_request(schema, token))
+            .await
+    }
+
+    pub async fn list_all_tables(&self, share: &Share) -> Result<Vec<Table>, anyhow::Error> {
+        self.list_pages(|token| self.core.list_all_tables_request(share, token))
+            .await
+    }
+
+    pub async fn get_table_metadata_at(
+        &self,
+        table: &Table,
+        version: Option<i64>,
+        timestamp: Option<&str>,
+    ) -> Result<(i64, TableMetadata), anyhow::Error> {
+        let request = self
+            .core
+            .table_metadata_request(table, version, timestamp)?;
+        let resp = self.execute(request).await?;
+        Ok((
+            parse_table_version(&resp.headers)?,
+            Core::parse_table_metadata(&resp)?,
+        ))
+    }
+
+    pub async fn get_table_metadata(&self, table: &Table) -> Result<TableMetadata, anyhow::Error> {
+        let request = self.core.table_metadata_request(table, None, None)?;
+        Core::parse_table_metadata(&self.execute(request).await?)
+    }
+
+    pub async fn table_version(
+        &self,
+        table: &Table,
+        timestamp: Option<&str>,
+    ) -> Result<i64, Error> {
+        let request = self.core.table_version_request(table, timestamp);
+        // Servers without the version endpoint answer 405, or 404 without an error body. A
+        // missing table is reported as Error::NotFound and not retried
+        let resp = match self.execute(request).await {
+            Err(Error::Http(e))
+                if timestamp.is_none()
+                    && matches!(
+                        e.status(),
+                        Some(StatusCode::NOT_FOUND | StatusCode::METHOD_NOT_ALLOWED)
+                    ) =>
+            {
+                debug!("--> Version endpoint is not available, falling back to HEAD");
+                self.execute(self.core.table_head_request(table)).await?
+            }
+            res => res?,
+        };
+        parse_table_version(&resp.headers)
+    }
+
+    pub async fn list_table_files(
+        &self,
+        table: &Table,
+        predicate_hints: Option<Vec<String>>,
+        limit_hint: Option<i32>,
+        version: Option<i64>,
+    ) -> Result<TableFiles, anyhow::Error> {
+        let request =
+            self.core
+                .list_table_files_request(table, predicate_hints, limit_hint, version);
+        Core::parse_table_files(&self.execute(request).await?)
+    }
+
+    pub async fn list_table_changes(
+        &self,
+        table: &Table,
+        starting_version: i64,
+        ending_version: Option<i64>,
+    ) -> Result<TableChanges, anyhow::Error> {
+        let request = self
+            .core
+            .list_table_changes_request(table, starting_version, ending_version);
+        Core::parse_table_changes(&self.execute(request).await?)
+    }
+
+    /// Downloads the i-th listed file, refreshing the presigned URLs if they are about to
+    /// expire or access to the file is denied. The download and its retries are measured by
+    /// a single span
+    async fn fetch_file(
+        &self,
+        table: &Table,
+        table_files: &mut TableFiles,
+        i: usize,
+    ) -> Result<Bytes, anyhow::Error> {
+        let span = self.core.download_span(table, &table_files.files[i].url);
+        let result = span
+            .instrument(self.fetch_file_with_retries(table, table_files, i, &span))
+            .await;
+        span.finish_download(match &result {
+            Ok(content) => Ok(content.len() as u64),
+            Err(e) => Err(e.downcast_ref::<reqwest::Error>().and_then(|e| e.status())),
+        });
+        result
+    }
+
+    async fn fetch_file_with_retries(
+        &self,
+        table: &Table,
+        table_files: &mut TableFiles,
+        i: usize,
+        span: &Span,
+    ) -> Result<Bytes, anyhow::Error> {
+        if table_files.files[i].url_expires_within(URL_EXPIRY_MARGIN) {
+            info!(
+                "--> URL of file {} is about to expire",
+                &table_files.files[i].id
+            );
+            self.refresh_file_urls(table, table_files).await?;
+        }
+        let file = &table_files.files[i];
+        debug!("--> Download {}", redact_url(&file.url));
+        let content = match self.get(&file.url).await {
+            Err(e) if e.status() == Some(StatusCode::FORBIDDEN) => {
+                info!("--> Access to file {} denied, refreshing its URL", &file.id);
+                span.retried();
+                self.refresh_file_urls(table, table_files).await?;
+                self.get(&table_files.files[i].url).await?
+            }
+            res => res?,
+        };
+        let file = &table_files.files[i];
+        if let Err(e) = Core::verify_download(file, &content) {
+            warn!("--> {}, downloading it again", e);
+            span.retried();
+            let content = self.get(&file.url).await?;
+            Core::verify_download(file, &content)?;
+            return Ok(content);
+        }
+        Ok(content)
+    }
+
+    /// Downloads the i-th listed file to `dst_path`, see [Session::fetch_file]
+    async fn download_file(
+        &self,
+        table: &Table,
+        table_files: &mut TableFiles,
+        i: usize,
+        dst_path: &Path,
+    ) -> Result<(), anyhow::Error> {
+        let content = self.fetch_file(table, table_files, i).await?;
+        fs::write(dst_path, content)?;
+        Ok(())
+    }
+
+    /// Re-issues the file listing for the same table version and replaces the presigned URLs
+    async fn refresh_file_urls(
+        &self,
+        table: &Table,
+        table_files: &mut TableFiles,
+    ) -> Result<(), anyhow::Error> {
+        let refreshed = self
+            .list_table_files(table, None, None, table_files.version)
+            .await?;
+        Core::apply_refreshed_urls(table, table_files, refreshed)
+    }
+
+    pub async fn get_files(&self, table: &Table) -> Result<Vec<PathBuf>, anyhow::Error> {
+        let lock = self.core.table_lock(table);
+        let _guard = lock.lock().await;
+        let store = self.core.cache_store(self.data_root);
+        let synced = self.sync_files(table, store.as_ref(), false).await?;
+        match synced.buffers {
+            Some(buffers) => Core::write_files(self.data_root, table, &synced.table_files, buffers),
+            None => Ok(synced
+                .table_files
+                .files
+                .iter()
+                .filter_map(|file| store.local_path(&Core::file_key(table, file)))
+                .collect()),
+        }
+    }
+
+    pub async fn verify_cache(&self, table: &Table) -> Result<CacheVerification, anyhow::Error> {
+        let lock = self.core.table_lock(table);
+        let _guard = lock.lock().await;
+        let store = self.core.cache_store(self.data_root);
+        let synced = self.sync_files(table, store.as_ref(), true).await?;
+        Ok(CacheVerification {
+            files: synced.table_files.files.len(),
+            repaired: synced.downloaded,
+        })
+    }
+
+    /// Lists the table files and downloads the ones which are not in the cache store or do
+    /// not match the checksums of its manifest. The cached files are only verified the first
+    /// time they are read by this process, or always with `verify`. The caller must hold the
+    /// table lock
+    async fn sync_files(
+        &self,
+        table: &Table,
+        store: &dyn CacheStore,
+        verify: bool,
+    ) -> Result<SyncedFiles, anyhow::Error> {
+        let mut table_files = self.list_table_files(table, None, None, None).await?;
+        Core::check_protocol(&table_files.metadata.protocol, self.best_effort_read)?;
+        let key = table.fully_qualified_name();
+        let local = store.local_path(&Core::table_key(table)).is_some();
+        let mut checksums = self
+            .core
+            .cached_checksums(table, &table_files)
+            .filter(|_| !verify);
+        if local && checksums.is_some() {
+            self.core.metrics().cache_hit(&key);
+            return Ok(SyncedFiles {
+                table_files,
+                buffers: None,
+                downloaded: Vec::new(),
+            });
+        }
+        if checksums.is_none() {
+            checksums = match store.get(&Core::manifest_key(table)).await? {
+                Some(manifest) => Core::parse_manifest(&manifest, &table_files),
+                None => None,
+            };
+        }
+        let outdated = checksums.is_none();
+        if outdated {
+            info!("--> Downloading data files of {}", key);
+            store.delete_dir(&Core::table_key(table)).await?;
+        }
+        let checksums = checksums.unwrap_or_default();
+        let mut verified = BTreeMap::new();
+        let mut buffers = Vec::new();
+        let mut downloaded = Vec::new();
+        for i in 0..table_files.files.len() {
+            let file = &table_files.files[i];
+            let file_key = Core::file_key(table, file);
+            let cached = match checksums.get(&file.id) {
+                Some(checksum) => store
+                    .get(&file_key)
+                    .await?
+                    .filter(|content| Core::is_intact(file, content, checksum)),
+                None => None,
+            };
+            let content = match cached {
+                Some(content) => content,
+                None => {
+                    if !outdated {
+                        warn!("--> Cached file {} is missing or corrupt", &file_key);
+                    }
+                    let content = self.fetch_file(table, &mut table_files, i).await?;
+                    store.put(&file_key, content.clone()).await?;
+                    downloaded.push(table_files.files[i].id.clone());
+                    content
+                }
+            };
+            verified.insert(table_files.files[i].id.clone(), Core::checksum(&content));
+            if !local {
+                buffers.push(content);
+            }
+        }
+        if outdated || !downloaded.is_empty() {
+            self.core.metrics().cache_miss(&key);
+            store
+                .put(
+                    &Core::manifest_key(table),
+                    Core::manifest(&table_files, &verified)?,
+                )
+                .await?;
+        } else {
+            self.core.metrics().cache_hit(&key);
+        }
+        self.core.record_cached(table, &table_files, verified);
+        Ok(SyncedFiles {
+            table_files,
+            buffers: (!local).then_some(buffers),
+            downloaded,
+        })
+    }
+
+    pub async fn get_dataframe(&self, table: &Table) -> Result<LazyFrame, anyhow::Error> {
+        let lock = self.core.table_lock(table);
+        let _guard = lock.lock().await;
+        let store = self.core.cache_store(self.data_root);
+        let SyncedFiles {
+            table_files,
+            buffers,
+            ..
+        } = self.sync_files(table, store.as_ref(), false).await?;
+        let deleted_rows = self
+            .load_deletion_vectors(table, &table_files.files)
+            .await?;
+        match (buffers, store.local_path(&Core::table_key(table))) {
+            (Some(buffers), _) => {
+                Core::load_dataframe_from_buffers(&table_files, buffers, deleted_rows)
+            }
+            (None, Some(table_path)) => {
+                Core::load_dataframe(&table_path, &table_files, deleted_rows)
+            }
+            (None, None) => unreachable!("sync_files returns the content of non-local files"),
+        }
+    }
+
+    async fn load_deletion_vectors(
+        &self,
+        table: &Table,
+        files: &[File],
+    ) -> Result<Vec<Option<RoaringTreemap>>, anyhow::Error> {
+        let mut deleted_rows = Vec::with_capacity(files.len());
+        for file in files {
+            let descriptor = match &file.deletion_vector {
+                Some(descriptor) => descriptor,
+                None => {
+                    deleted_rows.push(None);
+                    continue;
+                }
+            };
+            let content = match Core::deletion_vector_url(descriptor)? {
+                Some(url) => {
+                    debug!("--> Download deletion vector {}", redact_url(url));
+                    Some(self.fetch(table, url).await?)
+                }
+                None => None,
+            };
+            deleted_rows.push(Some(Core::decode_deletion_vector(
+                descriptor,
+                content.as_deref(),
+            )?));
+        }
+        Ok(deleted_rows)
+    }
+
+    pub async fn read_record_batches(
+        &self,
+        table: &Table,
+        options: &ReadOptions,
+    ) -> Result<Vec<RecordBatch>, anyhow::Error> {
+        let limit_hint = options.limit.map(|l| l as i32);
+        let table_files = self.list_table_files(table, None, limit_hint, None).await?;
+        self.read_table_files(table, &table_files, options).await
+    }
+
+    pub async fn read_dataframe(
+        &self,
+        table: &Table,
+        options: &ReadOptions,
+    ) -> Result<DataFrame, anyhow::Error> {
+        let batches = self.read_record_batches(table, options).await?;
+        remote::batches_to_dataframe(&batches)
+    }
+
+    pub async fn read_micro_batch(
+        &self,
+        table: &Table,
+        batch: &MicroBatch,
+        options: &ReadOptions,
+    ) -> Result<Vec<RecordBatch>, anyhow::Error> {
+        self.read_table_files(table, &batch.added_files(), options)
+            .await
+    }
+
+    /// Reads the listed files directly from their presigned URLs
+    async fn read_table_files(
+        &self,
+        table: &Table,
+        table_files: &TableFiles,
+        options: &ReadOptions,
+    ) -> Result<Vec<RecordBatch>, anyhow::Error> {
+        Core::check_protocol(&table_files.metadata.protocol, self.best_effort_read)?;
+        let deleted_rows = self
+            .load_deletion_vectors(table, &table_files.files)
+            .await?;
+        remote::read_table_batches(
+            self.range_client,
+            &self.core.download_metrics(table),
+            table_files,
+            options,
+            &deleted_rows,
+        )
+        .await
+    }
+
+    pub async fn export_table(
+        &self,
+        table: &Table,
+        format: ExportFormat,
+        destination: &Path,
+        options: &ExportOptions,
+    ) -> Result<usize, anyhow::Error> {
+        let table_files = self
+            .list_table_files(table, options.predicate_hints.clone(), None, None)
+            .await?;
+        Core::check_protocol(&table_files.metadata.protocol, self.best_effort_read)?;
+        let deleted_rows = self
+            .load_deletion_vectors(table, &table_files.files)
+            .await?;
+        let mut exporter =
+            Exporter::create(destination, format, options, &table_files.metadata.metadata)?;
+        // The partition columns are not stored in the data files
+        let read_options = ReadOptions {
+            columns: exporter.file_columns(options.columns.as_deref()),
+            limit: None,
+        };
+        remote::visit_table_batches(
+            self.range_client,
+            &self.core.download_metrics(table),
+            &table_files,
+            &read_options,
+            &deleted_rows,
+            &mut |file, batch| exporter.write(file, batch),
+        )
+        .await?;
+        exporter.finish()
+    }
+
+    pub async fn mirror_table(
+        &self,
+        table: &Table,
+        destination: &Path,
+    ) -> Result<MirrorSummary, anyhow::Error> {
+        let mirror = Mirror::open(destination, table)?;
+        let version = self.table_version(table, None).await?;
+        if let Some(summary) = mirror.up_to_date(version) {
+            return Ok(summary);
+        }
+        let mut table_files = self
+            .list_table_files(table, None, None, Some(version))
+            .await?;
+        Core::check_protocol(&table_files.metadata.protocol, self.best_effort_read)?;
+        for (i, dst_path) in mirror.missing_files(&table_files)? {
+            fs::create_dir_all(dst_path.parent().unwrap())?;
+            self.download_file(table, &mut table_files, i, &dst_path)
+                .await?;
+        }
+        mirror.commit(&table_files, version)
+    }
+
+    async fn save_checkpoint(&self, reader: &mut IncrementalReader) -> Result<(), anyhow::Error> {
+        if let (Some(store), Some(checkpoint)) = (reader.checkpoint_store(), reader.take_unsaved())
+        {
+            if let Err(e) = store.save(reader.key(), checkpoint).await {
+                reader.save_failed(checkpoint);
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// The next batch of an incremental read, None once it is complete
+    pub async fn next_micro_batch(
+        &self,
+        reader: &mut IncrementalReader,
+    ) -> Result<Option<MicroBatch>, anyhow::Error> {
+        self.save_checkpoint(reader).await?;
+        if let Some(store) = reader
+            .checkpoint_store()
+            .filter(|_| reader.needs_checkpoint())
+        {
+            let checkpoint = store.load(reader.key()).await?;
+            reader.start(checkpoint);
+        }
+        if reader.needs_ending_version() {
+            let version = self.table_version(&reader.table, None).await?;
+            reader.set_ending_version(version);
+        }
+        if let Some((starting_version, ending_version)) = reader.changes_to_list(URL_EXPIRY_MARGIN)
+        {
+            let changes = self
+                .list_table_changes(&reader.table, starting_version, Some(ending_version))
+                .await?;
+            Core::check_protocol(&changes.metadata.protocol, self.best_effort_read)?;
+            reader.listed(changes, ending_version)?;
+        }
+        match reader.next_batch() {
+            Some(batch) => Ok(Some(batch)),
+            None => {
+                self.save_checkpoint(reader).await?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Waits for the next version of a watched table
+    pub async fn next_version_event(
+        &self,
+        watcher: &mut Watcher,
+    ) -> Result<VersionEvent, anyhow::Error> {
+        if let (Some(store), Some(checkpoint)) =
+            (watcher.checkpoint_store(), watcher.take_unsaved())
+        {
+            if let Err(e) = store.save(watcher.key(), checkpoint).await {
+                watcher.save_failed(checkpoint);
+                return Err(e);
+            }
+        }
+        loop {
+            if watcher.should_wait() {
+                tokio::time::sleep(watcher.delay()).await;
+            }
+            if let Some(store) = watcher
+                .checkpoint_store()
+                .filter(|_| watcher.needs_checkpoint())
+            {
+                match store.load(watcher.key()).await {
+                    Ok(checkpoint) => watcher.start(checkpoint),
+                    Err(e) => {
+                        watcher.failed();
+                        return Err(e);
+                    }
+                }
+            }
+            if let Some(version) = watcher.next_version() {
+                let changes = if watcher.options.include_files {
+                    match self
+                        .list_table_changes(&watcher.table, version, Some(version))
+                        .await
+                    {
+                        Ok(changes) => Some(changes),
+                        Err(e) => {
+                            watcher.failed();
+                            return Err(e);
+                        }
+                    }
+                } else {
+                    None
+                };
+                return Ok(watcher.emit(changes));
+            }
+            match self.table_version(&watcher.table, None).await {
+                Ok(version) => watcher.observed(version),
+                Err(e) => {
+                    watcher.failed();
+                    return Err(e.into());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,152 @@
+//! Tracing spans and [Metrics] events of the requests and file downloads.
+//!
+//! The spans are only created with the `tracing` feature, the metrics are reported either way.
+
+use crate::core::{Request, Response};
+use crate::error::Error;
+use crate::metrics::Metrics;
+#[cfg(feature = "tracing")]
+use crate::utils::{parse_table_version, redact_url};
+use reqwest::StatusCode;
+use std::future::Future;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+enum Kind {
+    Request(&'static str),
+    Download(String),
+}
+
+/// Measures a single protocol call or file download, including its retries
+pub(crate) struct Span {
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+    kind: Kind,
+    metrics: Arc<dyn Metrics>,
+    start: Instant,
+    retries: AtomicU32,
+}
+
+impl Span {
+    pub fn request(request: &Request, metrics: Arc<dyn Metrics>) -> Self {
+        Self {
+            #[cfg(feature = "tracing")]
+            span: tracing::info_span!(
+                "delta_sharing.request",
+                operation = request.operation,
+                table = request.table.as_deref().unwrap_or_default(),
+                method = %request.method,
+                status = tracing::field::Empty,
+                version = tracing::field::Empty,
+                bytes = tracing::field::Empty,
+                duration_ms = tracing::field::Empty,
+                retries = 0,
+            ),
+            kind: Kind::Request(request.operation),
+            metrics,
+            start: Instant::now(),
+            retries: AtomicU32::new(0),
+        }
+    }
+
+    pub fn download(table: &str, url: &str, metrics: Arc<dyn Metrics>) -> Self {
+        #[cfg(not(feature = "tracing"))]
+        let _ = url;
+        Self {
+            #[cfg(feature = "tracing")]
+            span: tracing::info_span!(
+                "delta_sharing.download",
+                table = table,
+                url = %redact_url(url),
+                status = tracing::field::Empty,
+                bytes = tracing::field::Empty,
+                duration_ms = tracing::field::Empty,
+                retries = 0,
+            ),
+            kind: Kind::Download(table.to_string()),
+            metrics,
+            start: Instant::now(),
+            retries: AtomicU32::new(0),
+        }
+    }
+
+    /// Runs the future within the span
+    pub async fn instrument<F: Future>(&self, future: F) -> F::Output {
+        #[cfg(feature = "tracing")]
+        {
+            use tracing::Instrument;
+            future.instrument(self.span.clone()).await
+        }
+        #[cfg(not(feature = "tracing"))]
+        future.await
+    }
+
+    /// Counts a retry of the request or download, reported as the `download` operation for
+    /// downloads
+    pub fn retried(&self) {
+        let _retries = self.retries.fetch_add(1, Ordering::Relaxed) + 1;
+        #[cfg(feature = "tracing")]
+        self.span.record("retries", _retries);
+        match &self.kind {
+            Kind::Request(operation) => self.metrics.retry(operation),
+            Kind::Download(_) => self.metrics.retry("download"),
+        }
+    }
+
+    pub fn finish_request(self, result: &Result<Response, Error>) {
+        let duration = self.start.elapsed();
+        #[cfg(feature = "tracing")]
+        {
+            self.span.record("duration_ms", duration.as_millis() as u64);
+            match result {
+                Ok(response) => {
+                    self.span.record("bytes", response.body.len() as u64);
+                    if let Ok(version) = parse_table_version(&response.headers) {
+                        self.span.record("version", version);
+                    }
+                }
+                Err(Error::Http(e)) => {
+                    if let Some(status) = e.status() {
+                        self.span.record("status", status.as_u16());
+                    }
+                }
+                Err(Error::NotFound(_)) => {
+                    self.span.record("status", 404);
+                }
+                Err(_) => {}
+            }
+        }
+        if let Kind::Request(operation) = &self.kind {
+            match result {
+                Ok(_) => self.metrics.request(operation, duration),
+                Err(_) => self.metrics.request_error(operation, duration),
+            }
+        }
+    }
+
+    /// Records the size of the downloaded content, or the HTTP status of the failed download
+    /// if there is one
+    pub fn finish_download(self, result: Result<u64, Option<StatusCode>>) {
+        let duration = self.start.elapsed();
+        #[cfg(feature = "tracing")]
+        {
+            self.span.record("duration_ms", duration.as_millis() as u64);
+            match result {
+                Ok(bytes) => {
+                    self.span.record("bytes", bytes);
+                }
+                Err(Some(status)) => {
+                    self.span.record("status", status.as_u16());
+                }
+                Err(None) => {}
+            }
+        }
+        if let Kind::Download(table) = &self.kind {
+            match result {
+                Ok(bytes) => self.metrics.download(table, bytes, duration),
+                Err(_) => self.metrics.download_error(table, duration),
+            }
+        }
+    }
+}
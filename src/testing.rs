@@ -0,0 +1,317 @@
+//! Helpers to test code using the clients against a local sharing server, available with the
+//! `testing` feature.
+//!
+//! [MockSharingServer] serves tables made of parquet files, with protocol-correct responses
+//! generated from the files, so no JSON responses have to be written by hand:
+//!
+//! ```no_run
+//! use arrow::array::Int64Array;
+//! use arrow::datatypes::{DataType, Field, Schema};
+//! use arrow::record_batch::RecordBatch;
+//! use delta_sharing::testing::{write_parquet, MockSharingServer};
+//! use delta_sharing::Client;
+//! use std::sync::Arc;
+//!
+//! # async fn run() -> Result<(), anyhow::Error> {
+//! let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Int64, false)]));
+//! let batch = RecordBatch::try_new(schema.clone(), vec![Arc::new(Int64Array::from(vec![1, 2]))])?;
+//! write_parquet("/tmp/ids.parquet", &[batch])?;
+//!
+//! let server = MockSharingServer::builder()
+//!     .with_share("empty_share")
+//!     .with_table("share.schema.ids", schema, ["/tmp/ids.parquet"])
+//!     .start()
+//!     .await?;
+//! let client = Client::new(server.profile(), None).await?;
+//! let df = client.get_dataframe(&server.table("share.schema.ids")?).await?.collect()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::protocol::{ProviderConfig, Table};
+use crate::server::table::{id, schema_string};
+use crate::server::SharingServer;
+use arrow::datatypes::SchemaRef;
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::{parquet_to_arrow_schema, ArrowWriter};
+use parquet::file::properties::WriterProperties;
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use serde_json::json;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{env, fs};
+
+/// Bearer token of the [MockSharingServer] profile, unless another one is configured
+pub const TEST_BEARER_TOKEN: &str = "test-token";
+
+/// Writes the record batches into a parquet file, e.g. to create the files of a mocked table
+pub fn write_parquet(path: impl AsRef<Path>, batches: &[RecordBatch]) -> Result<(), anyhow::Error> {
+    let first = batches
+        .first()
+        .ok_or_else(|| anyhow::anyhow!("At least one record batch is required"))?;
+    // The dictionary encoder of parquet 14 fails the debug assertions of recent compilers
+    let properties = WriterProperties::builder()
+        .set_dictionary_enabled(false)
+        .build();
+    let mut writer =
+        ArrowWriter::try_new(fs::File::create(path)?, first.schema(), Some(properties))?;
+    for batch in batches {
+        writer.write(batch)?;
+    }
+    writer.close()?;
+    Ok(())
+}
+
+/// Parses a table name in the `share.schema.table` format
+fn parse_table(name: &str) -> Result<Table, anyhow::Error> {
+    match name.split('.').collect::<Vec<_>>()[..] {
+        [share, schema, table] => Ok(Table {
+            share: checked_name(share)?.to_string(),
+            schema: checked_name(schema)?.to_string(),
+            name: checked_name(table)?.to_string(),
+            share_id: None,
+            id: None,
+        }),
+        _ => Err(anyhow::anyhow!(
+            "Invalid table {}, expected share.schema.table",
+            name
+        )),
+    }
+}
+
+struct MockTable {
+    name: String,
+    schema: SchemaRef,
+    files: Vec<PathBuf>,
+}
+
+/// Builder of a [MockSharingServer]
+pub struct MockSharingServerBuilder {
+    shares: BTreeSet<String>,
+    tables: Vec<MockTable>,
+    bearer_token: String,
+    max_page_size: Option<usize>,
+}
+
+impl MockSharingServerBuilder {
+    /// Adds a share, which is only needed for shares without any table
+    pub fn with_share(mut self, share: impl Into<String>) -> Self {
+        self.shares.insert(share.into());
+        self
+    }
+
+    /// Adds the table `share.schema.table` with the Arrow schema and the parquet files.
+    /// The table has a single version 0, the files are copied when the server starts, which
+    /// fails if the schema of a file does not match the schema of the table
+    pub fn with_table<P: AsRef<Path>>(
+        mut self,
+        table: impl Into<String>,
+        schema: SchemaRef,
+        parquet_files: impl IntoIterator<Item = P>,
+    ) -> Self {
+        self.tables.push(MockTable {
+            name: table.into(),
+            schema,
+            files: parquet_files
+                .into_iter()
+                .map(|p| p.as_ref().to_path_buf())
+                .collect(),
+        });
+        self
+    }
+
+    /// Bearer token required by the server, [TEST_BEARER_TOKEN] by default
+    pub fn bearer_token(mut self, token: impl Into<String>) -> Self {
+        self.bearer_token = token.into();
+        self
+    }
+
+    /// Limits the number of items of the list responses
+    pub fn max_page_size(mut self, max_page_size: usize) -> Self {
+        self.max_page_size = Some(max_page_size);
+        self
+    }
+
+    /// Writes the tables into a temporary directory and starts serving them in the
+    /// background of the current tokio runtime
+    pub async fn start(self) -> Result<MockSharingServer, anyhow::Error> {
+        let root = env::temp_dir().join(format!(
+            "delta-sharing-mock-{:032x}",
+            rand::random::<u128>()
+        ));
+        // Fails instead of sharing the directory of another server
+        fs::create_dir(&root)?;
+        let result = self.write_tables(&root);
+        if result.is_err() {
+            fs::remove_dir_all(&root).ok();
+        }
+        let tables = result?;
+        let mut builder = SharingServer::builder(&root).bearer_token(self.bearer_token);
+        if let Some(max_page_size) = self.max_page_size {
+            builder = builder.max_page_size(max_page_size);
+        }
+        match builder.start().await {
+            Ok(server) => Ok(MockSharingServer {
+                server: Some(server),
+                root,
+                tables,
+            }),
+            Err(e) => {
+                fs::remove_dir_all(&root).ok();
+                Err(e)
+            }
+        }
+    }
+
+    fn write_tables(&self, root: &Path) -> Result<Vec<Table>, anyhow::Error> {
+        for share in &self.shares {
+            fs::create_dir_all(root.join(checked_name(share)?))?;
+        }
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as i64;
+        let mut tables = Vec::new();
+        for mock in &self.tables {
+            let table = parse_table(&mock.name)?;
+            let dir = root
+                .join(&table.share)
+                .join(&table.schema)
+                .join(&table.name);
+            if dir.exists() {
+                return Err(anyhow::anyhow!("Table {} is added twice", mock.name));
+            }
+            fs::create_dir_all(dir.join("_delta_log"))?;
+            let mut actions = vec![
+                json!({ "commitInfo": { "timestamp": timestamp } }),
+                json!({ "protocol": { "minReaderVersion": 1, "minWriterVersion": 2 } }),
+                json!({ "metaData": {
+                    "id": id(&mock.name),
+                    "name": table.name,
+                    "format": { "provider": "parquet", "options": {} },
+                    "schemaString": schema_string(&mock.schema),
+                    "partitionColumns": [],
+                    "configuration": {},
+                    "createdTime": timestamp,
+                } }),
+            ];
+            for (i, file) in mock.files.iter().enumerate() {
+                let reader = SerializedFileReader::new(fs::File::open(file)?)?;
+                let file_metadata = reader.metadata().file_metadata();
+                let file_schema = parquet_to_arrow_schema(
+                    file_metadata.schema_descr(),
+                    file_metadata.key_value_metadata(),
+                )?;
+                let matches = file_schema.fields().len() == mock.schema.fields().len()
+                    && file_schema
+                        .fields()
+                        .iter()
+                        .zip(mock.schema.fields())
+                        .all(|(f, m)| {
+                            f.name() == m.name()
+                                && f.data_type() == m.data_type()
+                                && f.is_nullable() == m.is_nullable()
+                        });
+                if !matches {
+                    return Err(anyhow::anyhow!(
+                        "Schema of {} does not match the schema of table {}",
+                        file.display(),
+                        mock.name
+                    ));
+                }
+                let num_records = file_metadata.num_rows();
+                let name = format!("part-{:05}.parquet", i);
+                let size = fs::copy(file, dir.join(&name))
+                    .map_err(|e| anyhow::anyhow!("Failed to copy {}: {}", file.display(), e))?;
+                actions.push(json!({ "add": {
+                    "path": name,
+                    "partitionValues": {},
+                    "size": size,
+                    "modificationTime": timestamp,
+                    "dataChange": true,
+                    "stats": json!({ "numRecords": num_records }).to_string(),
+                } }));
+            }
+            let commit = actions
+                .iter()
+                .map(|a| a.to_string())
+                .collect::<Vec<_>>()
+                .join("\n");
+            fs::write(
+                dir.join("_delta_log").join(format!("{:020}.json", 0)),
+                commit,
+            )?;
+            tables.push(table);
+        }
+        Ok(tables)
+    }
+}
+
+/// Rejects names which are not a single directory name
+fn checked_name(name: &str) -> Result<&str, anyhow::Error> {
+    if name.is_empty() || name.starts_with('_') || name.contains(['.', '/', '\\']) {
+        Err(anyhow::anyhow!("Invalid name {}", name))
+    } else {
+        Ok(name)
+    }
+}
+
+/// A sharing server with mocked tables, see the [module documentation][self].
+///
+/// The server stops and its temporary directory is removed when it is dropped.
+pub struct MockSharingServer {
+    server: Option<SharingServer>,
+    root: PathBuf,
+    tables: Vec<Table>,
+}
+
+impl MockSharingServer {
+    pub fn builder() -> MockSharingServerBuilder {
+        MockSharingServerBuilder {
+            shares: BTreeSet::new(),
+            tables: Vec::new(),
+            bearer_token: TEST_BEARER_TOKEN.to_string(),
+            max_page_size: None,
+        }
+    }
+
+    /// Endpoint of the sharing server, ending with a slash
+    pub fn endpoint(&self) -> String {
+        self.server.as_ref().unwrap().endpoint()
+    }
+
+    /// Profile to access the server
+    pub fn profile(&self) -> ProviderConfig {
+        self.server.as_ref().unwrap().profile()
+    }
+
+    /// A table added with [MockSharingServerBuilder::with_table], given as `share.schema.table`
+    pub fn table(&self, name: &str) -> Result<Table, anyhow::Error> {
+        let table = parse_table(name)?;
+        self.tables
+            .iter()
+            .find(|t| t.share == table.share && t.schema == table.schema && t.name == table.name)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("Table {} is not mocked", name))
+    }
+
+    /// Directory with the files of the mocked tables
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Stops the server after the pending requests completed
+    pub async fn shutdown(mut self) {
+        if let Some(server) = self.server.take() {
+            server.shutdown().await;
+        }
+    }
+}
+
+impl Drop for MockSharingServer {
+    fn drop(&mut self) {
+        self.server.take();
+        fs::remove_dir_all(&self.root).ok();
+    }
+}
@@ -0,0 +1,97 @@
+//! HTTP transports of the clients.
+//!
+//! [Session][crate::session::Session] runs the protocol calls, downloads and caching of both
+//! clients and only reaches the network through a [Transport]: the async
+//! [Client][crate::Client] uses a [reqwest::Client], the
+//! [blocking Client][crate::blocking::Client] a [reqwest::blocking::Client] whose calls run on
+//! the blocking thread pool of its runtime.
+
+use crate::core::{Core, Request, Response};
+use crate::error::Error;
+use async_trait::async_trait;
+use bytes::Bytes;
+
+/// Executes the HTTP requests of a client
+#[async_trait]
+pub(crate) trait Transport: Send + Sync {
+    /// Sends a request to the sharing server with the bearer token
+    async fn send(&self, request: &Request, token: &str) -> Result<Response, Error>;
+
+    /// Fetches the content of a presigned file URL, without the sharing server credentials
+    async fn get(&self, url: &str) -> Result<Bytes, reqwest::Error>;
+}
+
+#[async_trait]
+impl Transport for reqwest::Client {
+    async fn send(&self, request: &Request, token: &str) -> Result<Response, Error> {
+        let mut builder = self
+            .request(request.method.clone(), request.url.clone())
+            .headers(request.headers.clone())
+            .bearer_auth(token);
+        if let Some(body) = &request.body {
+            builder = builder.json(body);
+        }
+        let resp = builder.send().await?;
+        if let Some(e) = resp.error_for_status_ref().err() {
+            return Err(Core::status_error(
+                e,
+                &resp.text().await.unwrap_or_default(),
+            ));
+        }
+        let headers = resp.headers().clone();
+        let body = resp.text().await?;
+        Ok(Response { headers, body })
+    }
+
+    async fn get(&self, url: &str) -> Result<Bytes, reqwest::Error> {
+        self.get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await
+    }
+}
+
+#[cfg(feature = "blocking")]
+#[async_trait]
+impl Transport for reqwest::blocking::Client {
+    async fn send(&self, request: &Request, token: &str) -> Result<Response, Error> {
+        let client = self.clone();
+        let request = request.clone();
+        let token = token.to_string();
+        spawn_blocking(move || {
+            let mut builder = client
+                .request(request.method, request.url)
+                .headers(request.headers)
+                .bearer_auth(token);
+            if let Some(body) = &request.body {
+                builder = builder.json(body);
+            }
+            let resp = builder.send()?;
+            if let Some(e) = resp.error_for_status_ref().err() {
+                return Err(Core::status_error(e, &resp.text().unwrap_or_default()));
+            }
+            let headers = resp.headers().clone();
+            let body = resp.text()?;
+            Ok(Response { headers, body })
+        })
+        .await
+    }
+
+    async fn get(&self, url: &str) -> Result<Bytes, reqwest::Error> {
+        let client = self.clone();
+        let url = url.to_string();
+        spawn_blocking(move || client.get(url).send()?.error_for_status()?.bytes()).await
+    }
+}
+
+/// Runs a blocking request on the blocking thread pool, as the blocking reqwest client must not
+/// be used within the runtime driving the session
+#[cfg(feature = "blocking")]
+async fn spawn_blocking<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    match tokio::task::spawn_blocking(f).await {
+        Ok(result) => result,
+        Err(e) => std::panic::resume_unwind(e.into_panic()),
+    }
+}
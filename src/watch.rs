@@ -0,0 +1,228 @@
+//! Polling of shared tables for new versions.
+//!
+//! [Client::watch][crate::Client::watch] polls the version of a table and emits a
+//! [VersionEvent] for every version published since the last processed one, optionally with
+//! the files added and removed by the version. Failed polls are retried with an exponential backoff and reported
+//! as errors, the watch goes on until the stream is dropped.
+//!
+//! With a [CheckpointStore], the version of an event is saved when the next event is requested,
+//! so every version is delivered at least once and a restarted watch resumes after the last
+//! processed version. Without a checkpoint, the first event is the current version of the table.
+
+use crate::checkpoint::{Checkpoint, CheckpointStore};
+use crate::protocol::{Table, TableChanges};
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A version of a watched table
+#[derive(Debug, Clone)]
+pub struct VersionEvent {
+    /// The new version
+    pub version: i64,
+    /// The version of the previous event or checkpoint, None for the first event of a watch
+    /// without checkpoint
+    pub previous_version: Option<i64>,
+    /// Files added and removed by the version and its change data files, only listed if
+    /// [WatchOptions::include_files] is set
+    pub changes: Option<TableChanges>,
+}
+
+/// Options of [Client::watch_with_options][crate::Client::watch_with_options]
+#[derive(Clone, Default)]
+pub struct WatchOptions {
+    /// List the changes of every new version, see [VersionEvent::changes]
+    pub include_files: bool,
+    /// Store of the last processed version, to resume after a restart
+    pub checkpoint_store: Option<Arc<dyn CheckpointStore>>,
+    /// Key of the checkpoints, the fully qualified table name by default
+    pub checkpoint_key: Option<String>,
+    /// Maximum delay between failed polls, 32 times the polling interval by default
+    pub max_backoff: Option<Duration>,
+}
+
+impl fmt::Debug for WatchOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WatchOptions")
+            .field("include_files", &self.include_files)
+            .field(
+                "checkpoint_store",
+                &self.checkpoint_store.as_ref().map(|_| "<checkpoint store>"),
+            )
+            .field("checkpoint_key", &self.checkpoint_key)
+            .field("max_backoff", &self.max_backoff)
+            .finish()
+    }
+}
+
+/// State of a watch, independent of the client polling the table
+pub(crate) struct Watcher {
+    pub table: Table,
+    pub options: WatchOptions,
+    key: String,
+    interval: Duration,
+    /// Whether the checkpoint was loaded
+    started: bool,
+    last: Option<i64>,
+    pending: VecDeque<i64>,
+    /// Version of the last returned event, saved when the next event is requested
+    unsaved: Option<i64>,
+    /// Whether the version was polled at least once
+    polled: bool,
+    failures: u32,
+}
+
+impl Watcher {
+    pub fn new(table: &Table, interval: Duration, options: WatchOptions) -> Self {
+        let key = options
+            .checkpoint_key
+            .clone()
+            .unwrap_or_else(|| table.fully_qualified_name());
+        Self {
+            table: table.clone(),
+            options,
+            key,
+            interval,
+            started: false,
+            last: None,
+            pending: VecDeque::new(),
+            unsaved: None,
+            polled: false,
+            failures: 0,
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn checkpoint_store(&self) -> Option<Arc<dyn CheckpointStore>> {
+        self.options.checkpoint_store.clone()
+    }
+
+    /// Whether the checkpoint has to be loaded before the first poll
+    pub fn needs_checkpoint(&self) -> bool {
+        !self.started && self.options.checkpoint_store.is_some()
+    }
+
+    pub fn start(&mut self, checkpoint: Option<Checkpoint>) {
+        self.started = true;
+        self.last = checkpoint.map(|c| c.version);
+    }
+
+    /// The checkpoint of the last returned event, which has been processed once the next
+    /// event is requested
+    pub fn take_unsaved(&mut self) -> Option<Checkpoint> {
+        self.unsaved.take().map(Checkpoint::version)
+    }
+
+    /// Restores the checkpoint to save after the checkpoint store failed
+    pub fn save_failed(&mut self, checkpoint: Checkpoint) {
+        self.unsaved = Some(checkpoint.version);
+        self.failed();
+    }
+
+    /// Whether to wait before the next request, because the last poll found no new version
+    /// or a request failed
+    pub fn should_wait(&self) -> bool {
+        self.polled && (self.pending.is_empty() || self.failures > 0)
+    }
+
+    /// Records the current version of the table
+    pub fn observed(&mut self, current: i64) {
+        self.failures = 0;
+        self.started = true;
+        self.polled = true;
+        let next = match self.pending.back().copied().or(self.last) {
+            Some(last) => last + 1,
+            None => current,
+        };
+        self.pending.extend(next..=current);
+    }
+
+    pub fn failed(&mut self) {
+        self.polled = true;
+        self.failures = self.failures.saturating_add(1);
+    }
+
+    /// The next version to emit, if any
+    pub fn next_version(&self) -> Option<i64> {
+        self.pending.front().copied()
+    }
+
+    /// Builds the event of the next version and marks it as emitted
+    pub fn emit(&mut self, changes: Option<TableChanges>) -> VersionEvent {
+        let version = self.pending.pop_front().unwrap();
+        self.failures = 0;
+        let event = VersionEvent {
+            version,
+            previous_version: self.last,
+            changes,
+        };
+        self.last = Some(version);
+        if self.options.checkpoint_store.is_some() {
+            self.unsaved = Some(version);
+        }
+        event
+    }
+
+    /// Delay before the next poll, growing exponentially after failed polls
+    pub fn delay(&self) -> Duration {
+        let max_backoff = self.options.max_backoff.unwrap_or(self.interval * 32);
+        if self.failures == 0 {
+            return self.interval;
+        }
+        let factor = 2u32.saturating_pow(self.failures.min(16));
+        self.interval.saturating_mul(factor).min(max_backoff)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table() -> Table {
+        Table {
+            name: "table".to_string(),
+            schema: "schema".to_string(),
+            share: "share".to_string(),
+            share_id: None,
+            id: None,
+        }
+    }
+
+    #[test]
+    fn emits_every_new_version() {
+        let mut watcher = Watcher::new(&table(), Duration::from_secs(1), WatchOptions::default());
+        watcher.observed(3);
+        let event = watcher.emit(None);
+        assert_eq!((event.version, event.previous_version), (3, None));
+        assert_eq!(watcher.next_version(), None);
+        watcher.observed(3);
+        assert_eq!(watcher.next_version(), None);
+        assert!(watcher.should_wait());
+        watcher.observed(5);
+        assert!(!watcher.should_wait());
+        assert_eq!(watcher.emit(None).version, 4);
+        let event = watcher.emit(None);
+        assert_eq!((event.version, event.previous_version), (5, Some(4)));
+    }
+
+    #[test]
+    fn backoff() {
+        let options = WatchOptions {
+            max_backoff: Some(Duration::from_secs(5)),
+            ..Default::default()
+        };
+        let mut watcher = Watcher::new(&table(), Duration::from_secs(1), options);
+        assert_eq!(watcher.delay(), Duration::from_secs(1));
+        watcher.failed();
+        assert_eq!(watcher.delay(), Duration::from_secs(2));
+        watcher.failed();
+        watcher.failed();
+        assert_eq!(watcher.delay(), Duration::from_secs(5));
+        watcher.observed(0);
+        assert_eq!(watcher.delay(), Duration::from_secs(1));
+    }
+}
@@ -1,10 +1,13 @@
 mod common;
 
 use delta_sharing::blocking::Client;
+use delta_sharing::credentials::EnvToken;
 use delta_sharing::protocol::*;
+use std::env;
 use std::path::Path;
+use std::sync::Arc;
 use uuid::Uuid;
-use wiremock::matchers::{method, path, MethodExactMatcher};
+use wiremock::matchers::{header, method, path, MethodExactMatcher};
 use wiremock::{Mock, MockServer, ResponseTemplate};
 
 pub struct BlockingTestApp {
@@ -22,8 +25,7 @@ fn create_blocking_test_app() -> BlockingTestApp {
         bearer_token: Uuid::new_v4().to_string(),
     };
     let client = Client::new(config, None).unwrap();
-    let test_app = BlockingTestApp { client, server };
-    test_app
+    BlockingTestApp { client, server }
 }
 
 fn create_blocking_mocked_test_app(
@@ -64,6 +66,8 @@ fn get_dataframe() {
         name: "table_1".to_string(),
         share: "share_1".to_string(),
         schema: "schema_1".to_string(),
+        share_id: None,
+        id: None,
     };
 
     let app = create_blocking_test_app();
@@ -129,3 +133,137 @@ fn get_dataframe() {
         "Row value mismatch"
     );
 }
+
+#[test]
+fn env_token_is_read_per_request() {
+    let server = tokio_test::block_on(MockServer::start());
+    let var = format!("DELTA_SHARING_TOKEN_{}", Uuid::new_v4().simple());
+    let config = ProviderConfig {
+        share_credentials_version: 1,
+        endpoint: server.uri(),
+        bearer_token: "unused".to_string(),
+    };
+    let client = Client::builder(config)
+        .credential_provider(Arc::new(EnvToken::new(&var)))
+        .build()
+        .unwrap();
+    for token in ["first", "second"] {
+        tokio_test::block_on(
+            Mock::given(path("/shares"))
+                .and(header(
+                    "authorization",
+                    format!("Bearer {}", token).as_str(),
+                ))
+                .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"items": []}"#))
+                .expect(1)
+                .mount(&server),
+        );
+    }
+
+    env::set_var(&var, "first");
+    assert!(client.list_shares().unwrap().is_empty());
+    env::set_var(&var, "second");
+    assert!(client.list_shares().unwrap().is_empty());
+    env::remove_var(&var);
+}
+
+#[test]
+fn get_share() {
+    let body = r#"{"share": { "name": "share_1", "id": "1" }}"#;
+    let app = create_blocking_mocked_test_app(body, "/shares/share_1", method("GET"));
+    let share = app.client.get_share("share_1").unwrap();
+
+    assert_eq!(share.name, "share_1", "Share name mismatch");
+}
+
+#[test]
+fn get_table_version_falls_back_to_head() {
+    let table = Table {
+        name: "table_1".to_string(),
+        share: "share_1".to_string(),
+        schema: "schema_1".to_string(),
+        share_id: None,
+        id: None,
+    };
+    let url = format!(
+        "shares/{}/schemas/{}/tables/{}",
+        table.share, table.schema, table.name
+    );
+
+    let app = create_blocking_test_app();
+    let m = Mock::given(path(format!("{}/version", url)))
+        .and(method("GET"))
+        .respond_with(ResponseTemplate::new(404))
+        .expect(1)
+        .mount(&app.server);
+    tokio_test::block_on(m);
+    let m = Mock::given(path(url))
+        .and(method("HEAD"))
+        .respond_with(ResponseTemplate::new(200).insert_header("delta-table-version", "3"))
+        .expect(1)
+        .mount(&app.server);
+    tokio_test::block_on(m);
+    let version = app.client.get_table_version(&table).unwrap();
+
+    assert_eq!(version, 3, "Table version mismatch");
+}
+
+#[test]
+fn read_dataframe() {
+    use delta_sharing::remote::ReadOptions;
+
+    let table = Table {
+        name: "table_1".to_string(),
+        share: "share_1".to_string(),
+        schema: "schema_1".to_string(),
+        share_id: None,
+        id: None,
+    };
+
+    let app = create_blocking_test_app();
+    let parquet_local_path =
+        std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test/test.parquet");
+    let file_content = std::fs::read(parquet_local_path).unwrap();
+
+    let list_files_url = format!(
+        "shares/{}/schemas/{}/tables/{}/query",
+        table.share, table.schema, table.name
+    );
+    let mut file: File =
+        serde_json::from_str(common::TEST_FILE_RESPONSE).expect("Invalid file info");
+    let file_url_path = "/shares/test.parquet";
+    file.url = format!("{}{}", &app.server.uri(), &file_url_path);
+    file.size = file_content.len() as i64;
+    let list_files_body = &format!(
+        r#"{{ "protocol": {} }}
+           {{ "metaData": {} }}
+           {{ "file": {} }}"#,
+        common::TEST_PROTOCOL_RESPONSE,
+        common::TEST_METADATA_RESPONSE,
+        serde_json::to_string(&file).unwrap()
+    );
+    let m = Mock::given(path(list_files_url))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(list_files_body))
+        .expect(1)
+        .mount(&app.server);
+    tokio_test::block_on(m);
+    // The server ignores the range requests and always returns the whole file
+    let m = Mock::given(path(file_url_path))
+        .and(method("GET"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(file_content))
+        .mount(&app.server);
+    tokio_test::block_on(m);
+
+    let options = ReadOptions {
+        columns: Some(vec!["name".to_string()]),
+        limit: Some(2),
+    };
+    let df = app.client.read_dataframe(&table, &options).unwrap();
+    assert_eq!(df.shape(), (2, 1), "Dataframe shape mismatch");
+    assert_eq!(
+        df.get_row(1).0[0],
+        polars::datatypes::AnyValue::Utf8("Two"),
+        "Row value mismatch"
+    );
+}
@@ -0,0 +1,82 @@
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+use wiremock::matchers::{header, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+mod common;
+
+async fn run(server: &MockServer, args: &[&str]) -> Output {
+    let location = common::get_random_location(Path::new(env!("CARGO_TARGET_TMPDIR")));
+    fs::create_dir_all(&location).unwrap();
+    let profile = location.join("profile.share");
+    fs::write(
+        &profile,
+        format!(
+            r#"{{"shareCredentialsVersion": 1, "endpoint": "{}", "bearerToken": "token"}}"#,
+            server.uri()
+        ),
+    )
+    .unwrap();
+    let args = args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+    tokio::task::spawn_blocking(move || {
+        Command::new(env!("CARGO_BIN_EXE_delta-sharing"))
+            .arg("--profile")
+            .arg(profile)
+            .args(args)
+            .output()
+            .unwrap()
+    })
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn list_shares() {
+    let server = MockServer::start().await;
+    Mock::given(path("/shares"))
+        .and(method("GET"))
+        .and(header("authorization", "Bearer token"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(
+            r#"{"items": [ { "name": "share_1", "id": "1" }, { "name": "share_2", "id": "2" } ]}"#,
+        ))
+        .expect(2)
+        .mount(&server)
+        .await;
+
+    let output = run(&server, &["shares"]).await;
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "share\nshare_1\nshare_2\n"
+    );
+
+    let output = run(&server, &["--output", "json", "shares"]).await;
+    assert!(output.status.success());
+    let shares: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(shares[1]["name"], "share_2");
+}
+
+#[tokio::test]
+async fn exit_codes() {
+    let server = MockServer::start().await;
+    Mock::given(path(
+        "/shares/missing/schemas/schema_1/tables/table_1/version",
+    ))
+    .respond_with(ResponseTemplate::new(404))
+    .mount(&server)
+    .await;
+    Mock::given(path(
+        "/shares/denied/schemas/schema_1/tables/table_1/version",
+    ))
+    .respond_with(ResponseTemplate::new(403))
+    .mount(&server)
+    .await;
+
+    let output = run(&server, &["version", "missing.schema_1.table_1"]).await;
+    assert_eq!(output.status.code(), Some(4));
+    let output = run(&server, &["version", "denied.schema_1.table_1"]).await;
+    assert_eq!(output.status.code(), Some(3));
+    let output = run(&server, &["version", "not_a_table"]).await;
+    assert_eq!(output.status.code(), Some(1));
+}
@@ -1,10 +1,18 @@
 mod common;
 
 use common::create_mocked_test_app;
+use delta_sharing::credentials::*;
+use delta_sharing::metrics::Metrics;
 use delta_sharing::protocol::*;
+use delta_sharing::Client;
+use reqwest::header::{HeaderMap, HeaderValue};
 use std::path::Path;
-use wiremock::matchers::{method, path};
-use wiremock::{Mock, ResponseTemplate};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use std::{env, fs};
+use wiremock::matchers::{body_string_contains, header, method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
 
 // #[cfg(not(feature = "blocking"))]
 #[tokio::test]
@@ -23,10 +31,262 @@ async fn list_shares() {
     );
 }
 
+#[tokio::test]
+async fn client_builder() {
+    let server = MockServer::start().await;
+    let config = ProviderConfig {
+        share_credentials_version: 1,
+        endpoint: server.uri(),
+        bearer_token: "token".to_string(),
+    };
+    let mut headers = HeaderMap::new();
+    headers.insert("x-custom", HeaderValue::from_static("value"));
+    let client = Client::builder(config)
+        .connect_timeout(Duration::from_secs(5))
+        .timeout(Duration::from_millis(500))
+        .default_headers(headers)
+        .user_agent_suffix("test-app/1.0")
+        .build()
+        .unwrap();
+
+    Mock::given(path("/shares"))
+        .and(method("GET"))
+        .and(header("authorization", "Bearer token"))
+        .and(header("x-custom", "value"))
+        .and(|req: &wiremock::Request| {
+            req.headers
+                .get(&"user-agent".into())
+                .map(|v| v.as_str().ends_with(" test-app/1.0"))
+                .unwrap_or(false)
+        })
+        .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"items": []}"#))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(path("/shares/share_1"))
+        .and(method("GET"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(2)))
+        .mount(&server)
+        .await;
+
+    assert!(client.list_shares().await.unwrap().is_empty());
+    let err = client.get_share("share_1").await.unwrap_err();
+    assert!(
+        matches!(
+            err.downcast_ref::<delta_sharing::Error>(),
+            Some(delta_sharing::Error::Http(e)) if e.is_timeout()
+        ),
+        "Expected a timeout, got {}",
+        err
+    );
+}
+
+#[tokio::test]
+async fn client_builder_with_http_client() {
+    let server = MockServer::start().await;
+    let config = ProviderConfig {
+        share_credentials_version: 1,
+        endpoint: server.uri(),
+        bearer_token: "token".to_string(),
+    };
+    let client = Client::builder(config)
+        .http_client(reqwest::Client::new())
+        .build()
+        .unwrap();
+
+    Mock::given(path("/shares"))
+        .and(method("GET"))
+        .and(header("authorization", "Bearer token"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"items": []}"#))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    assert!(client.list_shares().await.unwrap().is_empty());
+}
+
+struct RotatingToken(AtomicUsize);
+
+#[async_trait::async_trait]
+impl CredentialProvider for RotatingToken {
+    async fn token(&self) -> Result<String, anyhow::Error> {
+        Ok(format!("token-{}", self.0.load(Ordering::SeqCst)))
+    }
+
+    async fn refresh(&self, _rejected: &str) -> Result<Option<String>, anyhow::Error> {
+        let next = self.0.fetch_add(1, Ordering::SeqCst) + 1;
+        Ok(Some(format!("token-{}", next)))
+    }
+}
+
+#[tokio::test]
+async fn credential_provider_refreshes_rejected_token() {
+    let server = MockServer::start().await;
+    let config = ProviderConfig {
+        share_credentials_version: 1,
+        endpoint: server.uri(),
+        bearer_token: "unused".to_string(),
+    };
+    let client = Client::builder(config)
+        .credential_provider(Arc::new(RotatingToken(AtomicUsize::new(1))))
+        .build()
+        .unwrap();
+
+    Mock::given(path("/shares"))
+        .and(header("authorization", "Bearer token-1"))
+        .respond_with(ResponseTemplate::new(401))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(path("/shares"))
+        .and(header("authorization", "Bearer token-2"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"items": []}"#))
+        .expect(2)
+        .mount(&server)
+        .await;
+
+    assert!(client.list_shares().await.unwrap().is_empty());
+    assert!(client.list_shares().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn static_token_is_not_retried() {
+    let app = common::create_test_app().await;
+    Mock::given(path("/shares"))
+        .respond_with(ResponseTemplate::new(401))
+        .expect(1)
+        .mount(&app.server)
+        .await;
+
+    let err = app.client.list_shares().await.err().unwrap();
+    assert!(
+        matches!(
+            err.downcast_ref::<delta_sharing::Error>(),
+            Some(delta_sharing::Error::Http(e)) if e.status() == Some(reqwest::StatusCode::UNAUTHORIZED)
+        ),
+        "Expected an unauthorized error, got {}",
+        err
+    );
+}
+
+#[tokio::test]
+async fn profile_file_token_is_reloaded() {
+    let server = MockServer::start().await;
+    let profile_path = env::temp_dir().join(format!("{}.share", uuid::Uuid::new_v4()));
+    let write_profile = |token: &str| {
+        let profile = format!(
+            r#"{{"shareCredentialsVersion": 1, "endpoint": "{}", "bearerToken": "{}"}}"#,
+            server.uri(),
+            token
+        );
+        fs::write(&profile_path, profile).unwrap();
+    };
+    write_profile("old");
+    let config = ProviderConfig {
+        share_credentials_version: 1,
+        endpoint: server.uri(),
+        bearer_token: "unused".to_string(),
+    };
+    let client = Client::builder(config)
+        .credential_provider(Arc::new(ProfileFileToken::new(&profile_path)))
+        .build()
+        .unwrap();
+
+    for token in ["old", "new"] {
+        Mock::given(path("/shares"))
+            .and(header(
+                "authorization",
+                format!("Bearer {}", token).as_str(),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"items": []}"#))
+            .expect(1)
+            .mount(&server)
+            .await;
+    }
+
+    assert!(client.list_shares().await.unwrap().is_empty());
+    write_profile("new");
+    // Make sure the change is visible even on file systems with a coarse modification time
+    fs::File::options()
+        .write(true)
+        .open(&profile_path)
+        .unwrap()
+        .set_modified(SystemTime::now() + Duration::from_secs(10))
+        .unwrap();
+    assert!(client.list_shares().await.unwrap().is_empty());
+    fs::remove_file(&profile_path).unwrap();
+}
+
+#[tokio::test]
+async fn oauth_client_credentials() {
+    let server = MockServer::start().await;
+    Mock::given(path("/oauth/token"))
+        .and(method("POST"))
+        .and(header("authorization", "Basic aWQ6c2VjcmV0"))
+        .and(body_string_contains("grant_type=client_credentials"))
+        .and(body_string_contains("scope=sharing"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(
+            r#"{"access_token": "oauth-token", "token_type": "Bearer", "expires_in": 3600}"#,
+        ))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(path("/shares"))
+        .and(header("authorization", "Bearer oauth-token"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"items": []}"#))
+        .expect(2)
+        .mount(&server)
+        .await;
+
+    let config = ProviderConfig {
+        share_credentials_version: 1,
+        endpoint: server.uri(),
+        bearer_token: "unused".to_string(),
+    };
+    let provider =
+        OAuthClientCredentials::new(format!("{}/oauth/token", server.uri()), "id", "secret")
+            .with_scope("sharing");
+    let client = Client::builder(config)
+        .credential_provider(Arc::new(provider))
+        .build()
+        .unwrap();
+
+    assert!(client.list_shares().await.unwrap().is_empty());
+    assert!(client.list_shares().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn get_share() {
+    let app = common::create_test_app().await;
+    Mock::given(path("/shares/share_1"))
+        .and(method("GET"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_string(r#"{"share": { "name": "share_1", "id": "1" }}"#),
+        )
+        .expect(1)
+        .mount(&app.server)
+        .await;
+    Mock::given(path("/shares/share_2"))
+        .and(method("GET"))
+        .respond_with(ResponseTemplate::new(404))
+        .expect(1)
+        .mount(&app.server)
+        .await;
+
+    let share = app.client.get_share("share_1").await.unwrap();
+    assert_eq!(share.name, "share_1", "Share name mismatch");
+    assert!(
+        app.client.get_share("share_2").await.is_err(),
+        "Missing share should fail"
+    );
+}
+
 #[tokio::test]
 async fn list_schemas() {
     let share = Share {
         name: "share_1".to_string(),
+        id: None,
     };
     let body = &format!(
         r#"{{ "items": [ {{ "name":"schema_1", "share": "{0}" }}, {{ "name": "schema_2", "share": "{0}" }} ] }}"#,
@@ -73,6 +333,7 @@ async fn list_tables() {
 async fn list_all_tables() {
     let share = Share {
         name: "share_1".to_string(),
+        id: None,
     };
     let body = &format!(
         r#"{{ "items": [ {{ "name":"table_1", "share": "{0}", "schema": "{1}" }}, {{ "name": "table_2", "share": "{0}", "schema": "{1}" }} ] }}"#,
@@ -91,12 +352,50 @@ async fn list_all_tables() {
     );
 }
 
+#[tokio::test]
+async fn list_all_tables_pages() {
+    let share = Share {
+        name: "share_1".to_string(),
+        id: Some("share-id".to_string()),
+    };
+    let app = common::create_test_app().await;
+    let url = format!("/shares/{}/all-tables", share.name);
+    Mock::given(path(url.clone()))
+        .and(method("GET"))
+        .and(query_param("pageToken", "page_2"))
+        .and(query_param("maxResults", "500"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(
+            r#"{ "items": [ { "name": "table_2", "schema": "schema_1", "share": "share_1", "shareId": "share-id", "id": "table-2" } ] }"#,
+        ))
+        .expect(1)
+        .mount(&app.server)
+        .await;
+    Mock::given(path(url))
+        .and(method("GET"))
+        .and(query_param("maxResults", "500"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(
+            r#"{ "items": [ { "name": "table_1", "schema": "schema_1", "share": "share_1", "shareId": "share-id", "id": "table-1" } ], "nextPageToken": "page_2" }"#,
+        ))
+        .expect(1)
+        .mount(&app.server)
+        .await;
+
+    let tables = app.client.list_all_tables(&share).await.unwrap();
+
+    let names = tables.iter().map(|t| t.name.as_str()).collect::<Vec<_>>();
+    assert_eq!(names, vec!["table_1", "table_2"]);
+    assert_eq!(tables[1].share_id.as_deref(), Some("share-id"));
+    assert_eq!(tables[1].id.as_deref(), Some("table-2"));
+}
+
 #[tokio::test]
 async fn get_table_metadata() {
     let table = Table {
         name: "table_1".to_string(),
         share: "share_1".to_string(),
         schema: "schema_1".to_string(),
+        share_id: None,
+        id: None,
     };
     let body = &format!(
         r#"{{ "protocol": {} }}
@@ -136,36 +435,212 @@ async fn get_table_metadata() {
     );
 }
 
+#[tokio::test]
+async fn get_table_metadata_at() {
+    let table = Table {
+        name: "table_1".to_string(),
+        share: "share_1".to_string(),
+        schema: "schema_1".to_string(),
+        share_id: None,
+        id: None,
+    };
+    let body = format!(
+        r#"{{ "protocol": {} }}
+        {{ "metaData": {} }}"#,
+        common::TEST_PROTOCOL_RESPONSE,
+        common::TEST_METADATA_RESPONSE,
+    );
+    let url = format!(
+        "shares/{}/schemas/{}/tables/{}/metadata",
+        table.share, table.schema, table.name
+    );
+
+    let app = common::create_test_app().await;
+    Mock::given(path(url.clone()))
+        .and(method("GET"))
+        .and(query_param("version", "2"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("delta-table-version", "2")
+                .set_body_string(body.clone()),
+        )
+        .expect(1)
+        .mount(&app.server)
+        .await;
+    Mock::given(path(url))
+        .and(method("GET"))
+        .and(query_param("timestamp", "2022-01-01T00:00:00Z"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("delta-table-version", "1")
+                .set_body_string(body),
+        )
+        .expect(1)
+        .mount(&app.server)
+        .await;
+
+    let (version, meta) = app
+        .client
+        .get_table_metadata_at(&table, Some(2), None)
+        .await
+        .unwrap();
+    assert_eq!(version, 2, "Table version mismatch");
+    assert_eq!(
+        meta.metadata.id, "cf9c9342-b773-4c7b-a217-037d02ffe5d8",
+        "Metadata ID mismatch"
+    );
+
+    let (version, _) = app
+        .client
+        .get_table_metadata_at(&table, None, Some("2022-01-01T00:00:00Z"))
+        .await
+        .unwrap();
+    assert_eq!(version, 1, "Table version mismatch");
+}
+
 #[tokio::test]
 async fn get_table_version() {
     let table = Table {
         name: "table_1".to_string(),
         share: "share_1".to_string(),
         schema: "schema_1".to_string(),
+        share_id: None,
+        id: None,
     };
-    let expected_version = "3";
     let url = format!(
-        "shares/{}/schemas/{}/tables/{}",
+        "shares/{}/schemas/{}/tables/{}/version",
         table.share, table.schema, table.name
     );
 
     let app = common::create_test_app().await;
-    let response =
-        ResponseTemplate::new(200).insert_header("delta-table-version", expected_version);
+    let response = ResponseTemplate::new(200).insert_header("delta-table-version", "3");
 
     Mock::given(path(url))
-        .and(method("HEAD"))
+        .and(method("GET"))
         .respond_with(response)
         .expect(1)
         .mount(&app.server)
         .await;
-    let version = app.client.get_table_version(&table).await;
+    let version = app.client.get_table_version(&table).await.unwrap();
 
-    assert_eq!(
-        &format!("{}", version),
-        expected_version,
-        "Table version mismatch"
+    assert_eq!(version, 3, "Table version mismatch");
+}
+
+#[tokio::test]
+async fn get_table_version_at() {
+    let table = Table {
+        name: "table_1".to_string(),
+        share: "share_1".to_string(),
+        schema: "schema_1".to_string(),
+        share_id: None,
+        id: None,
+    };
+    let url = format!(
+        "shares/{}/schemas/{}/tables/{}/version",
+        table.share, table.schema, table.name
+    );
+
+    let app = common::create_test_app().await;
+    Mock::given(path(url.clone()))
+        .and(method("GET"))
+        .and(query_param("startingTimestamp", "2022-01-01T00:00:00Z"))
+        .respond_with(ResponseTemplate::new(200).insert_header("delta-table-version", "2"))
+        .expect(1)
+        .mount(&app.server)
+        .await;
+    Mock::given(path(url))
+        .and(method("GET"))
+        .and(query_param("startingTimestamp", "2023-01-01T00:00:00Z"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.server)
+        .await;
+
+    let version = app
+        .client
+        .get_table_version_at(&table, "2022-01-01T00:00:00Z")
+        .await
+        .unwrap();
+    assert_eq!(version, 2, "Table version mismatch");
+
+    let err = app
+        .client
+        .get_table_version_at(&table, "2023-01-01T00:00:00Z")
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(err, delta_sharing::Error::MissingTableVersion),
+        "Error mismatch: {}",
+        err
+    );
+}
+
+#[tokio::test]
+async fn get_table_version_falls_back_to_head() {
+    let table = Table {
+        name: "table_1".to_string(),
+        share: "share_1".to_string(),
+        schema: "schema_1".to_string(),
+        share_id: None,
+        id: None,
+    };
+    let url = format!(
+        "shares/{}/schemas/{}/tables/{}",
+        table.share, table.schema, table.name
+    );
+
+    let app = common::create_test_app().await;
+    Mock::given(path(format!("{}/version", url)))
+        .and(method("GET"))
+        .respond_with(ResponseTemplate::new(404))
+        .expect(1)
+        .mount(&app.server)
+        .await;
+    Mock::given(path(url))
+        .and(method("HEAD"))
+        .respond_with(ResponseTemplate::new(200).insert_header("delta-table-version", "3"))
+        .expect(1)
+        .mount(&app.server)
+        .await;
+    let version = app.client.get_table_version(&table).await.unwrap();
+
+    assert_eq!(version, 3, "Table version mismatch");
+}
+
+#[tokio::test]
+async fn get_table_version_of_missing_table() {
+    let table = Table {
+        name: "table_1".to_string(),
+        share: "share_1".to_string(),
+        schema: "schema_1".to_string(),
+        share_id: None,
+        id: None,
+    };
+    let url = format!(
+        "shares/{}/schemas/{}/tables/{}",
+        table.share, table.schema, table.name
     );
+
+    let app = common::create_test_app().await;
+    Mock::given(path(format!("{}/version", url)))
+        .and(method("GET"))
+        .respond_with(ResponseTemplate::new(404).set_body_string(
+            r#"{ "errorCode": "RESOURCE_DOES_NOT_EXIST", "message": "Table not found" }"#,
+        ))
+        .expect(1)
+        .mount(&app.server)
+        .await;
+    Mock::given(path(url))
+        .and(method("HEAD"))
+        .respond_with(ResponseTemplate::new(200).insert_header("delta-table-version", "3"))
+        .expect(0)
+        .mount(&app.server)
+        .await;
+
+    match app.client.get_table_version(&table).await {
+        Err(delta_sharing::Error::NotFound(message)) => assert_eq!(message, "Table not found"),
+        res => panic!("Expected a not found error, got {:?}", res),
+    }
 }
 
 #[tokio::test]
@@ -174,6 +649,8 @@ async fn list_all_table_files() {
         name: "table_1".to_string(),
         share: "share_1".to_string(),
         schema: "schema_1".to_string(),
+        share_id: None,
+        id: None,
     };
     let body = &format!(
         r#"{{ "protocol": {} }}
@@ -201,6 +678,57 @@ async fn list_all_table_files() {
     assert_eq!(files.files[1].id, "2", "File id mismatch");
 }
 
+#[tokio::test]
+async fn list_table_files_with_large_files_and_unknown_fields() {
+    let table = Table {
+        name: "table_1".to_string(),
+        share: "share_1".to_string(),
+        schema: "schema_1".to_string(),
+        share_id: None,
+        id: None,
+    };
+    let body = &format!(
+        r#"{{ "protocol": {} }}
+           {{ "metaData": {} }}
+           {{ "file": {} }}
+           {{ "endStreamAction": {{ "refreshToken": "token" }} }}
+"#,
+        common::TEST_PROTOCOL_RESPONSE,
+        common::TEST_METADATA_RESPONSE.replace(
+            r#""partitionColumns""#,
+            r#""version": 3, "size": 5000000000, "numFiles": 1, "newField": true, "partitionColumns""#
+        ),
+        common::TEST_FILE_RESPONSE.replace(
+            r#""size": 2350"#,
+            r#""size": 5000000000, "version": 3, "timestamp": 1652140000000, "newField": {}"#
+        )
+    );
+
+    let url = format!(
+        "shares/{}/schemas/{}/tables/{}/query",
+        table.share, table.schema, table.name
+    );
+    let app = create_mocked_test_app(body, &url, method("POST")).await;
+    let files = app
+        .client
+        .list_table_files(&table, None, None, None)
+        .await
+        .unwrap();
+
+    let metadata = &files.metadata.metadata;
+    assert_eq!(metadata.version, Some(3), "Metadata version mismatch");
+    assert_eq!(metadata.size, Some(5000000000), "Metadata size mismatch");
+    assert_eq!(metadata.num_files, Some(1), "Metadata file count mismatch");
+    assert_eq!(files.files.len(), 1, "File count mismatch");
+    assert_eq!(files.files[0].size, 5000000000, "File size mismatch");
+    assert_eq!(files.files[0].version, Some(3), "File version mismatch");
+    assert_eq!(
+        files.files[0].timestamp,
+        Some(1652140000000),
+        "File timestamp mismatch"
+    );
+}
+
 #[tokio::test]
 async fn get_files() {
     use std::path::Path;
@@ -209,6 +737,8 @@ async fn get_files() {
         name: "table_1".to_string(),
         share: "share_1".to_string(),
         schema: "schema_1".to_string(),
+        share_id: None,
+        id: None,
     };
 
     let app = common::create_test_app().await;
@@ -245,8 +775,10 @@ async fn get_files() {
 
     let file_content = std::fs::read(parquet_local_path).unwrap();
     let file_response = ResponseTemplate::new(200).set_body_bytes(file_content);
+    // Presigned URLs must not receive the sharing server credentials
     Mock::given(path(file_url_path))
         .and(method("GET"))
+        .and(|req: &wiremock::Request| !req.headers.contains_key(&"authorization".into()))
         .respond_with(file_response)
         .expect(1)
         .mount(&app.server)
@@ -272,24 +804,275 @@ async fn get_files() {
 }
 
 #[tokio::test]
-async fn get_dataframe() {
+async fn get_files_downloads_added_files() {
     let table = Table {
         name: "table_1".to_string(),
         share: "share_1".to_string(),
         schema: "schema_1".to_string(),
+        share_id: None,
+        id: None,
     };
-
     let app = common::create_test_app().await;
-
-    // There are fiew pieces here that need to work in sequence
-    // 1. List table files
     let list_files_url = format!(
         "shares/{}/schemas/{}/tables/{}/query",
         table.share, table.schema, table.name
     );
     let mut file: File =
         serde_json::from_str(common::TEST_FILE_RESPONSE).expect("Invalid file info");
-    let file_url_path = "/shares/test.parquet";
+    file.url = format!("{}/shares/test.parquet", &app.server.uri());
+    let mut added = file.clone();
+    added.id = format!("{}-added", &file.id);
+    let listing = |files: &[&File]| {
+        let mut body = format!(
+            "{{ \"protocol\": {} }}\n{{ \"metaData\": {} }}",
+            common::TEST_PROTOCOL_RESPONSE,
+            common::TEST_METADATA_RESPONSE
+        );
+        for file in files {
+            body.push_str(&format!(
+                "\n{{ \"file\": {} }}",
+                serde_json::to_string(file).unwrap()
+            ));
+        }
+        ResponseTemplate::new(200).set_body_string(body)
+    };
+    // The second version of the table adds a file without changing the metadata
+    Mock::given(path(list_files_url.clone()))
+        .and(method("POST"))
+        .respond_with(listing(&[&file]))
+        .up_to_n_times(1)
+        .mount(&app.server)
+        .await;
+    Mock::given(path(list_files_url))
+        .and(method("POST"))
+        .respond_with(listing(&[&file, &added]))
+        .mount(&app.server)
+        .await;
+    let parquet_local_path =
+        std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test/test.parquet");
+    Mock::given(path("/shares/test.parquet"))
+        .and(method("GET"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_bytes(fs::read(parquet_local_path).unwrap()),
+        )
+        .expect(2)
+        .mount(&app.server)
+        .await;
+
+    let mut c = app.client;
+    c.data_root = common::get_random_location(Path::new(env!("CARGO_TARGET_TMPDIR")))
+        .to_str()
+        .unwrap()
+        .to_string();
+
+    assert_eq!(c.get_files(&table).await.unwrap().len(), 1);
+    let files = c.get_files(&table).await.unwrap();
+
+    assert_eq!(files.len(), 2, "File count mismatch");
+    assert!(files.iter().all(|f| f.exists()), "Added file should exist");
+}
+
+#[derive(Default)]
+struct RecordedMetrics {
+    requests: Mutex<Vec<String>>,
+    cache: Mutex<Vec<(String, bool)>>,
+    downloaded_bytes: AtomicUsize,
+    downloads: AtomicUsize,
+    retries: Mutex<Vec<String>>,
+}
+
+impl Metrics for RecordedMetrics {
+    fn request(&self, operation: &str, _duration: Duration) {
+        self.requests.lock().unwrap().push(operation.to_string());
+    }
+
+    fn cache_hit(&self, table: &str) {
+        self.cache.lock().unwrap().push((table.to_string(), true));
+    }
+
+    fn cache_miss(&self, table: &str) {
+        self.cache.lock().unwrap().push((table.to_string(), false));
+    }
+
+    fn download(&self, _table: &str, bytes: u64, _duration: Duration) {
+        self.downloaded_bytes
+            .fetch_add(bytes as usize, Ordering::SeqCst);
+        self.downloads.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn retry(&self, operation: &str) {
+        self.retries.lock().unwrap().push(operation.to_string());
+    }
+}
+
+#[tokio::test]
+async fn metrics() {
+    let table = Table {
+        name: "table_1".to_string(),
+        share: "share_1".to_string(),
+        schema: "schema_1".to_string(),
+        share_id: None,
+        id: None,
+    };
+    let server = MockServer::start().await;
+    let mut file: File =
+        serde_json::from_str(common::TEST_FILE_RESPONSE).expect("Invalid file info");
+    file.url = format!("{}/shares/test.parquet", &server.uri());
+    let list_files_body = format!(
+        r#"{{ "protocol": {} }}
+           {{ "metaData": {} }}
+           {{ "file": {} }}"#,
+        common::TEST_PROTOCOL_RESPONSE,
+        common::TEST_METADATA_RESPONSE,
+        serde_json::to_string(&file).unwrap()
+    );
+    Mock::given(path(
+        "/shares/share_1/schemas/schema_1/tables/table_1/query",
+    ))
+    .and(method("POST"))
+    .respond_with(ResponseTemplate::new(200).set_body_string(list_files_body))
+    .expect(3)
+    .mount(&server)
+    .await;
+    let file_content =
+        fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("resources/test/test.parquet"))
+            .unwrap();
+    let file_size = file_content.len();
+    Mock::given(path("/shares/test.parquet"))
+        .and(method("GET"))
+        .respond_with(RangeResponder(file_content))
+        .expect(2)
+        .mount(&server)
+        .await;
+
+    let config = ProviderConfig {
+        share_credentials_version: 1,
+        endpoint: server.uri(),
+        bearer_token: "token".to_string(),
+    };
+    let metrics = Arc::new(RecordedMetrics::default());
+    let client = Client::builder(config)
+        .data_root(
+            common::get_random_location(Path::new(env!("CARGO_TARGET_TMPDIR")))
+                .to_str()
+                .unwrap(),
+        )
+        .metrics(metrics.clone())
+        .build()
+        .unwrap();
+
+    client.get_files(&table).await.unwrap();
+    client.get_files(&table).await.unwrap();
+    // Range requests of direct reads are reported as downloads too
+    client
+        .read_dataframe(&table, &Default::default())
+        .await
+        .unwrap();
+
+    assert_eq!(
+        *metrics.requests.lock().unwrap(),
+        vec!["query_table", "query_table", "query_table"]
+    );
+    let name = table.fully_qualified_name();
+    assert_eq!(
+        *metrics.cache.lock().unwrap(),
+        vec![(name.clone(), false), (name, true)]
+    );
+    assert_eq!(
+        metrics.downloaded_bytes.load(Ordering::SeqCst),
+        2 * file_size
+    );
+}
+
+#[tokio::test]
+async fn get_files_concurrently() {
+    let table = Table {
+        name: "table_1".to_string(),
+        share: "share_1".to_string(),
+        schema: "schema_1".to_string(),
+        share_id: None,
+        id: None,
+    };
+
+    let app = common::create_test_app().await;
+
+    let list_files_url = format!(
+        "shares/{}/schemas/{}/tables/{}/query",
+        table.share, table.schema, table.name
+    );
+    let mut file: File =
+        serde_json::from_str(common::TEST_FILE_RESPONSE).expect("Invalid file info");
+    let file_url_path = "/shares/test.parquet";
+    file.url = format!("{}{}", &app.server.uri(), &file_url_path);
+    let list_files_body = &format!(
+        r#"{{ "protocol": {} }}
+           {{ "metaData": {} }}
+           {{ "file": {} }}"#,
+        common::TEST_PROTOCOL_RESPONSE,
+        common::TEST_METADATA_RESPONSE,
+        serde_json::to_string(&file).unwrap()
+    );
+    Mock::given(path(list_files_url))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(list_files_body))
+        .expect(2)
+        .mount(&app.server)
+        .await;
+
+    // Both tasks ask for the table while the file is still being downloaded,
+    // the second one should wait and use the downloaded file
+    let parquet_local_path =
+        std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test/test.parquet");
+    Mock::given(path(file_url_path))
+        .and(method("GET"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(std::fs::read(parquet_local_path).unwrap())
+                .set_delay(std::time::Duration::from_millis(200)),
+        )
+        .expect(1)
+        .mount(&app.server)
+        .await;
+
+    let mut c = app.client;
+    c.data_root = common::get_random_location(Path::new(env!("CARGO_TARGET_TMPDIR")))
+        .to_str()
+        .unwrap()
+        .to_string();
+
+    let tasks = (0..2)
+        .map(|_| {
+            let c = c.clone();
+            let table = table.clone();
+            tokio::spawn(async move { c.get_files(&table).await.unwrap() })
+        })
+        .collect::<Vec<_>>();
+    for task in tasks {
+        assert_eq!(task.await.unwrap().len(), 1, "File count mismatch");
+    }
+}
+
+#[tokio::test]
+async fn get_dataframe() {
+    let table = Table {
+        name: "table_1".to_string(),
+        share: "share_1".to_string(),
+        schema: "schema_1".to_string(),
+        share_id: None,
+        id: None,
+    };
+
+    let app = common::create_test_app().await;
+
+    // There are fiew pieces here that need to work in sequence
+    // 1. List table files
+    let list_files_url = format!(
+        "shares/{}/schemas/{}/tables/{}/query",
+        table.share, table.schema, table.name
+    );
+    let mut file: File =
+        serde_json::from_str(common::TEST_FILE_RESPONSE).expect("Invalid file info");
+    let file_url_path = "/shares/test.parquet";
     file.url = format!("{}{}", &app.server.uri(), &file_url_path);
     let list_files_body = &format!(
         r#"{{ "protocol": {} }}
@@ -333,6 +1116,14 @@ async fn get_dataframe() {
     let df = c.get_dataframe(&table).await.unwrap().collect().unwrap();
     assert_eq!(df.shape(), (5, 3), "Dataframe shape mismatch");
 
+    // Files in the table directory which are not listed, e.g. of an older version, are ignored
+    let table_dir = Path::new(&c.data_root).join(table.fully_qualified_name());
+    fs::copy(
+        table_dir.join(format!("{}.snappy.parquet", &file.id)),
+        table_dir.join("stale.snappy.parquet"),
+    )
+    .unwrap();
+
     // Get the data again, this time it should be served from the local cache (enforced by Expections set on Mocks)
     let df1 = c.get_dataframe(&table).await.unwrap().collect().unwrap();
     assert_eq!(df1.shape(), (5, 3), "Dataframe shape mismatch");
@@ -342,3 +1133,707 @@ async fn get_dataframe() {
         "Row value mismatch"
     );
 }
+
+/// Serves the test parquet file honouring the HTTP `Range` header
+struct RangeResponder(Vec<u8>);
+
+impl wiremock::Respond for RangeResponder {
+    fn respond(&self, request: &wiremock::Request) -> ResponseTemplate {
+        let range = match request.headers.get(&"range".into()) {
+            Some(range) => range,
+            None => return ResponseTemplate::new(200).set_body_bytes(self.0.clone()),
+        };
+        let range = range
+            .as_str()
+            .trim_start_matches("bytes=")
+            .split('-')
+            .map(|v| v.parse::<usize>().unwrap())
+            .collect::<Vec<_>>();
+        ResponseTemplate::new(206).set_body_bytes(&self.0[range[0]..=range[1]])
+    }
+}
+
+#[tokio::test]
+async fn read_dataframe() {
+    use delta_sharing::remote::ReadOptions;
+
+    let table = Table {
+        name: "table_1".to_string(),
+        share: "share_1".to_string(),
+        schema: "schema_1".to_string(),
+        share_id: None,
+        id: None,
+    };
+
+    let app = common::create_test_app().await;
+    let parquet_local_path =
+        std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test/test.parquet");
+    let file_content = std::fs::read(parquet_local_path).unwrap();
+
+    let list_files_url = format!(
+        "shares/{}/schemas/{}/tables/{}/query",
+        table.share, table.schema, table.name
+    );
+    let mut file: File =
+        serde_json::from_str(common::TEST_FILE_RESPONSE).expect("Invalid file info");
+    let file_url_path = "/shares/test.parquet";
+    file.url = format!("{}{}", &app.server.uri(), &file_url_path);
+    file.size = file_content.len() as i64;
+    let list_files_body = &format!(
+        r#"{{ "protocol": {} }}
+           {{ "metaData": {} }}
+           {{ "file": {} }}"#,
+        common::TEST_PROTOCOL_RESPONSE,
+        common::TEST_METADATA_RESPONSE,
+        serde_json::to_string(&file).unwrap()
+    );
+    Mock::given(path(list_files_url))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(list_files_body))
+        .expect(2)
+        .mount(&app.server)
+        .await;
+    // The footer and the metadata of the small file are fetched with one range request
+    Mock::given(path(file_url_path))
+        .and(method("GET"))
+        .respond_with(RangeResponder(file_content))
+        .expect(2)
+        .mount(&app.server)
+        .await;
+
+    let df = app
+        .client
+        .read_dataframe(&table, &ReadOptions::default())
+        .await
+        .unwrap();
+    assert_eq!(df.shape(), (5, 3), "Dataframe shape mismatch");
+
+    let options = ReadOptions {
+        columns: Some(vec!["name".to_string()]),
+        limit: Some(2),
+    };
+    let df = app.client.read_dataframe(&table, &options).await.unwrap();
+    assert_eq!(df.shape(), (2, 1), "Dataframe shape mismatch");
+    assert_eq!(
+        df.get_row(1).0[0],
+        polars::datatypes::AnyValue::Utf8("Two"),
+        "Row value mismatch"
+    );
+}
+
+#[tokio::test]
+async fn range_reader_empty_response() {
+    use delta_sharing::remote::HttpRangeReader;
+    use tokio::io::AsyncReadExt;
+
+    let server = MockServer::start().await;
+    Mock::given(path("/test.parquet"))
+        .respond_with(ResponseTemplate::new(206))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let mut reader = HttpRangeReader::new(
+        reqwest::Client::new(),
+        format!("{}/test.parquet", server.uri()),
+        100,
+    );
+    let mut buf = [0; 10];
+    let err = tokio::time::timeout(Duration::from_secs(5), reader.read(&mut buf))
+        .await
+        .expect("The reader must not request the range again")
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+}
+
+#[tokio::test]
+async fn export_table() {
+    use arrow::array::StringArray;
+    use delta_sharing::export::{Compression, ExportFormat, ExportOptions};
+    use flate2::read::GzDecoder;
+    use polars::prelude::{ParquetReader, SerReader};
+    use std::io::Read;
+
+    let table = Table {
+        name: "table_1".to_string(),
+        share: "share_1".to_string(),
+        schema: "schema_1".to_string(),
+        share_id: None,
+        id: None,
+    };
+
+    let app = common::create_test_app().await;
+    let parquet_local_path =
+        std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test/test.parquet");
+    let file_content = std::fs::read(parquet_local_path).unwrap();
+    let mut file: File =
+        serde_json::from_str(common::TEST_FILE_RESPONSE).expect("Invalid file info");
+    let file_url_path = "/shares/test.parquet";
+    file.url = format!("{}{}", &app.server.uri(), &file_url_path);
+    file.size = file_content.len() as i64;
+    file.partition_values
+        .insert("day".to_string(), serde_json::json!("2021-04-28"));
+    // The id and value columns are stored with narrower types than the table schema
+    let metadata = r#"{ "id": "cf9c9342-b773-4c7b-a217-037d02ffe5d8", "format": { "provider": "parquet" }, "schemaString": "{\"type\":\"struct\",\"fields\":[{\"name\":\"id\",\"type\":\"long\",\"nullable\":true,\"metadata\":{}},{\"name\":\"name\",\"type\":\"string\",\"nullable\":true,\"metadata\":{}},{\"name\":\"value\",\"type\":\"double\",\"nullable\":true,\"metadata\":{}},{\"name\":\"day\",\"type\":\"date\",\"nullable\":true,\"metadata\":{}}]}", "partitionColumns": ["day"], "configuration": {} }"#;
+    let list_files_body = &format!(
+        r#"{{ "protocol": {} }}
+           {{ "metaData": {} }}
+           {{ "file": {} }}"#,
+        common::TEST_PROTOCOL_RESPONSE,
+        metadata,
+        serde_json::to_string(&file).unwrap()
+    );
+    Mock::given(path(
+        "/shares/share_1/schemas/schema_1/tables/table_1/query",
+    ))
+    .and(method("POST"))
+    .respond_with(ResponseTemplate::new(200).set_body_string(list_files_body))
+    .expect(5)
+    .mount(&app.server)
+    .await;
+    Mock::given(path(file_url_path))
+        .and(method("GET"))
+        .respond_with(RangeResponder(file_content))
+        .mount(&app.server)
+        .await;
+    let location = common::get_random_location(Path::new(env!("CARGO_TARGET_TMPDIR")));
+    fs::create_dir_all(&location).unwrap();
+
+    let options = ExportOptions {
+        columns: Some(vec!["name".to_string(), "day".to_string()]),
+        filter: Some(Arc::new(|batch| {
+            let names = batch
+                .column(0)
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .unwrap();
+            Ok(names.iter().map(|n| n.map(|n| n != "Two")).collect())
+        })),
+        ..Default::default()
+    };
+    let csv_path = location.join("table.csv");
+    let rows = app
+        .client
+        .export_table(&table, ExportFormat::Csv, &csv_path, &options)
+        .await
+        .unwrap();
+    assert_eq!(rows, 4, "Exported row count mismatch");
+    let csv = fs::read_to_string(&csv_path).unwrap();
+    assert!(
+        csv.starts_with("name,day\nOne,2021-04-28\nThree,2021-04-28\n"),
+        "CSV mismatch: {}",
+        csv
+    );
+    assert_eq!(csv.lines().count(), 5, "CSV line count mismatch");
+
+    let options = ExportOptions {
+        compression: Some(Compression::Gzip),
+        ..Default::default()
+    };
+    let json_path = location.join("table.json.gz");
+    app.client
+        .export_table(&table, ExportFormat::NdJson, &json_path, &options)
+        .await
+        .unwrap();
+    let mut json = String::new();
+    GzDecoder::new(fs::File::open(&json_path).unwrap())
+        .read_to_string(&mut json)
+        .unwrap();
+    let rows = json
+        .lines()
+        .map(|l| serde_json::from_str::<serde_json::Value>(l).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(rows.len(), 5, "JSON row count mismatch");
+    assert_eq!(rows[1]["name"], "Two", "JSON value mismatch");
+    assert_eq!(
+        rows[1]["day"], "2021-04-28",
+        "JSON partition value mismatch"
+    );
+
+    let options = ExportOptions {
+        compression: Some(Compression::Zstd),
+        ..Default::default()
+    };
+    let parquet_path = location.join("table.parquet");
+    app.client
+        .export_table(&table, ExportFormat::Parquet, &parquet_path, &options)
+        .await
+        .unwrap();
+    let df = ParquetReader::new(fs::File::open(&parquet_path).unwrap())
+        .finish()
+        .unwrap();
+    assert_eq!(df.shape(), (5, 4), "Dataframe shape mismatch");
+    assert_eq!(
+        df.column("id").unwrap().dtype(),
+        &polars::prelude::DataType::Int64,
+        "Column type mismatch"
+    );
+    assert_eq!(
+        fs::read_dir(&location).unwrap().count(),
+        3,
+        "Only the exported files should be left"
+    );
+
+    let options = ExportOptions {
+        columns: Some(vec!["missing".to_string()]),
+        ..Default::default()
+    };
+    assert!(
+        app.client
+            .export_table(
+                &table,
+                ExportFormat::Csv,
+                &location.join("failed.csv"),
+                &options
+            )
+            .await
+            .is_err(),
+        "Unknown columns should be rejected"
+    );
+    let options = ExportOptions {
+        compression: Some(Compression::Zstd),
+        ..Default::default()
+    };
+    assert!(
+        app.client
+            .export_table(
+                &table,
+                ExportFormat::Csv,
+                &location.join("failed.csv"),
+                &options
+            )
+            .await
+            .is_err(),
+        "Zstd compressed CSV should be rejected"
+    );
+    assert_eq!(
+        fs::read_dir(&location).unwrap().count(),
+        3,
+        "A failed export should not leave any file"
+    );
+}
+
+#[tokio::test]
+async fn get_files_refreshes_expired_urls() {
+    use wiremock::matchers::body_partial_json;
+
+    let table = Table {
+        name: "table_1".to_string(),
+        share: "share_1".to_string(),
+        schema: "schema_1".to_string(),
+        share_id: None,
+        id: None,
+    };
+
+    let app = common::create_test_app().await;
+
+    let list_files_url = format!(
+        "shares/{}/schemas/{}/tables/{}/query",
+        table.share, table.schema, table.name
+    );
+    let list_files_body = |url_path: &str| {
+        let mut file: File =
+            serde_json::from_str(common::TEST_FILE_RESPONSE).expect("Invalid file info");
+        file.url = format!("{}{}", &app.server.uri(), url_path);
+        format!(
+            r#"{{ "protocol": {} }}
+               {{ "metaData": {} }}
+               {{ "file": {} }}"#,
+            common::TEST_PROTOCOL_RESPONSE,
+            common::TEST_METADATA_RESPONSE,
+            serde_json::to_string(&file).unwrap()
+        )
+    };
+
+    // 1. The initial listing returns a URL which is rejected by the storage
+    Mock::given(path(&list_files_url))
+        .and(method("POST"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("delta-table-version", "3")
+                .set_body_string(list_files_body("/shares/expired.parquet")),
+        )
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&app.server)
+        .await;
+    Mock::given(path("/shares/expired.parquet"))
+        .and(method("GET"))
+        .respond_with(ResponseTemplate::new(403))
+        .expect(1)
+        .mount(&app.server)
+        .await;
+
+    // 2. The listing is re-issued for the same version and returns a fresh URL
+    Mock::given(path(&list_files_url))
+        .and(method("POST"))
+        .and(body_partial_json(serde_json::json!({ "version": 3 })))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("delta-table-version", "3")
+                .set_body_string(list_files_body("/shares/test.parquet")),
+        )
+        .expect(1)
+        .mount(&app.server)
+        .await;
+    let parquet_local_path =
+        std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test/test.parquet");
+    Mock::given(path("/shares/test.parquet"))
+        .and(method("GET"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_bytes(std::fs::read(parquet_local_path).unwrap()),
+        )
+        .expect(1)
+        .mount(&app.server)
+        .await;
+
+    let config = ProviderConfig {
+        share_credentials_version: 1,
+        endpoint: app.server.uri(),
+        bearer_token: "token".to_string(),
+    };
+    let metrics = Arc::new(RecordedMetrics::default());
+    let c = Client::builder(config)
+        .data_root(
+            common::get_random_location(Path::new(env!("CARGO_TARGET_TMPDIR")))
+                .to_str()
+                .unwrap(),
+        )
+        .metrics(metrics.clone())
+        .build()
+        .unwrap();
+
+    let files = c.get_files(&table).await.unwrap();
+
+    assert_eq!(files.len(), 1, "File count mismatch");
+    assert!(Path::exists(&files[0]), "File should exist");
+    // The denied and the refreshed download are reported as one download with a retry
+    assert_eq!(*metrics.retries.lock().unwrap(), ["download"]);
+    assert_eq!(metrics.downloads.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn list_table_files_delta_format() {
+    let table = Table {
+        name: "table_1".to_string(),
+        share: "share_1".to_string(),
+        schema: "schema_1".to_string(),
+        share_id: None,
+        id: None,
+    };
+    let body = &format!(
+        r#"{{ "protocol": {} }}
+           {{ "metaData": {{ "deltaMetadata": {} }} }}
+           {{ "file": {} }}"#,
+        common::TEST_DELTA_PROTOCOL_RESPONSE,
+        common::TEST_METADATA_RESPONSE,
+        common::TEST_DELTA_FILE_RESPONSE,
+    );
+
+    let url = format!(
+        "shares/{}/schemas/{}/tables/{}/query",
+        table.share, table.schema, table.name
+    );
+    let mut app = common::create_test_app().await;
+    Mock::given(path(url))
+        .and(method("POST"))
+        .and(|req: &wiremock::Request| {
+            req.headers
+                .get(&"delta-sharing-capabilities".into())
+                .map(|v| v.as_str().starts_with("responseformat=delta"))
+                .unwrap_or(false)
+        })
+        .respond_with(ResponseTemplate::new(200).set_body_string(body))
+        .expect(1)
+        .mount(&app.server)
+        .await;
+
+    app.client.response_format = ResponseFormat::Delta;
+    let files = app
+        .client
+        .list_table_files(&table, None, None, None)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        files.metadata.protocol.min_writer_version,
+        Some(2),
+        "Protocol mismatch"
+    );
+    assert_eq!(
+        files.metadata.metadata.id, "cf9c9342-b773-4c7b-a217-037d02ffe5d8",
+        "Metadata ID mismatch"
+    );
+    assert_eq!(files.files.len(), 1, "File count mismatch");
+    assert_eq!(files.files[0].url, "<url>", "File URL mismatch");
+    assert_eq!(files.files[0].size, 2350, "File size mismatch");
+    assert_eq!(
+        files.files[0].expiration_timestamp,
+        Some(4102444800000),
+        "File expiration mismatch"
+    );
+}
+
+#[tokio::test]
+async fn get_dataframe_with_deletion_vector() {
+    let table = Table {
+        name: "table_1".to_string(),
+        share: "share_1".to_string(),
+        schema: "schema_1".to_string(),
+        share_id: None,
+        id: None,
+    };
+
+    let app = common::create_test_app().await;
+
+    // The inline deletion vector marks rows 0 and 2 as deleted
+    let file_url_path = "/shares/test.parquet";
+    let file = common::TEST_DELTA_FILE_RESPONSE
+        .replace("<url>", &format!("{}{}", &app.server.uri(), &file_url_path))
+        .replace(
+            r#""stats""#,
+            r#""deletionVector": { "storageType": "i", "pathOrInlineDv": "^Bg9^0rr910000000000iXQKl0rr91000315c8Xg00062", "sizeInBytes": 36, "cardinality": 2 }, "stats""#,
+        );
+    let list_files_body = &format!(
+        r#"{{ "protocol": {} }}
+           {{ "metaData": {{ "deltaMetadata": {} }} }}
+           {{ "file": {} }}"#,
+        common::TEST_DELTA_PROTOCOL_RESPONSE,
+        common::TEST_METADATA_RESPONSE,
+        file
+    );
+    let list_files_url = format!(
+        "shares/{}/schemas/{}/tables/{}/query",
+        table.share, table.schema, table.name
+    );
+    Mock::given(path(list_files_url))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(list_files_body))
+        .expect(1)
+        .mount(&app.server)
+        .await;
+
+    let parquet_local_path =
+        std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test/test.parquet");
+    Mock::given(path(file_url_path))
+        .and(method("GET"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_bytes(std::fs::read(parquet_local_path).unwrap()),
+        )
+        .expect(1)
+        .mount(&app.server)
+        .await;
+
+    let mut c = app.client;
+    c.response_format = ResponseFormat::Delta;
+    c.data_root = common::get_random_location(Path::new(env!("CARGO_TARGET_TMPDIR")))
+        .to_str()
+        .unwrap()
+        .to_string();
+
+    let df = c.get_dataframe(&table).await.unwrap().collect().unwrap();
+    assert_eq!(df.shape(), (3, 3), "Dataframe shape mismatch");
+    assert_eq!(
+        df.get_row(0).0[1],
+        polars::datatypes::AnyValue::Utf8("Two"),
+        "Row value mismatch"
+    );
+}
+
+#[tokio::test]
+async fn get_dataframe_with_unsupported_protocol() {
+    let table = Table {
+        name: "table_1".to_string(),
+        share: "share_1".to_string(),
+        schema: "schema_1".to_string(),
+        share_id: None,
+        id: None,
+    };
+
+    let app = common::create_test_app().await;
+
+    let file_url_path = "/shares/test.parquet";
+    let file = common::TEST_DELTA_FILE_RESPONSE
+        .replace("<url>", &format!("{}{}", &app.server.uri(), &file_url_path));
+    let list_files_body = &format!(
+        r#"{{ "protocol": {{ "deltaProtocol": {{ "minReaderVersion": 3, "minWriterVersion": 7, "readerFeatures": ["deletionVectors", "timestampNtz"] }} }} }}
+           {{ "metaData": {{ "deltaMetadata": {} }} }}
+           {{ "file": {} }}"#,
+        common::TEST_METADATA_RESPONSE,
+        file
+    );
+    let list_files_url = format!(
+        "shares/{}/schemas/{}/tables/{}/query",
+        table.share, table.schema, table.name
+    );
+    Mock::given(path(list_files_url))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(list_files_body))
+        .expect(2)
+        .mount(&app.server)
+        .await;
+
+    let parquet_local_path =
+        std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test/test.parquet");
+    Mock::given(path(file_url_path))
+        .and(method("GET"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_bytes(std::fs::read(parquet_local_path).unwrap()),
+        )
+        .expect(1)
+        .mount(&app.server)
+        .await;
+
+    let mut c = app.client;
+    c.response_format = ResponseFormat::Delta;
+    c.data_root = common::get_random_location(Path::new(env!("CARGO_TARGET_TMPDIR")))
+        .to_str()
+        .unwrap()
+        .to_string();
+
+    let err = c.get_dataframe(&table).await.err().unwrap();
+    assert!(
+        matches!(
+            err.downcast_ref::<delta_sharing::Error>(),
+            Some(delta_sharing::Error::UnsupportedReaderFeatures(features)) if features == &["timestampNtz"]
+        ),
+        "Error mismatch: {}",
+        err
+    );
+
+    c.best_effort_read = true;
+    let df = c.get_dataframe(&table).await.unwrap().collect().unwrap();
+    assert_eq!(df.shape(), (5, 3), "Dataframe shape mismatch");
+}
+
+#[tokio::test]
+async fn get_dataframe_with_column_mapping() {
+    use delta_sharing::remote::ReadOptions;
+
+    let table = Table {
+        name: "table_1".to_string(),
+        share: "share_1".to_string(),
+        schema: "schema_1".to_string(),
+        share_id: None,
+        id: None,
+    };
+
+    let app = common::create_test_app().await;
+    let parquet_local_path =
+        std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test/test.parquet");
+    let file_content = std::fs::read(parquet_local_path).unwrap();
+
+    // Logical column names differ from the `id`, `name` and `value` columns stored in the file
+    let metadata = r#"{ "id": "cf9c9342-b773-4c7b-a217-037d02ffe5d8", "format": { "provider": "parquet" }, "schemaString": "{\"type\":\"struct\",\"fields\":[{\"name\":\"row_id\",\"type\":\"integer\",\"nullable\":true,\"metadata\":{\"delta.columnMapping.id\":1,\"delta.columnMapping.physicalName\":\"id\"}},{\"name\":\"label\",\"type\":\"string\",\"nullable\":true,\"metadata\":{\"delta.columnMapping.id\":2,\"delta.columnMapping.physicalName\":\"name\"}},{\"name\":\"amount\",\"type\":\"float\",\"nullable\":true,\"metadata\":{\"delta.columnMapping.id\":3,\"delta.columnMapping.physicalName\":\"value\"}}]}", "partitionColumns": [], "configuration": {"delta.columnMapping.mode": "name"} }"#;
+    let file_url_path = "/shares/test.parquet";
+    let mut file: File =
+        serde_json::from_str(common::TEST_FILE_RESPONSE).expect("Invalid file info");
+    file.url = format!("{}{}", &app.server.uri(), &file_url_path);
+    file.size = file_content.len() as i64;
+    let list_files_body = &format!(
+        r#"{{ "protocol": {} }}
+           {{ "metaData": {} }}
+           {{ "file": {} }}"#,
+        common::TEST_PROTOCOL_RESPONSE,
+        metadata,
+        serde_json::to_string(&file).unwrap()
+    );
+    let list_files_url = format!(
+        "shares/{}/schemas/{}/tables/{}/query",
+        table.share, table.schema, table.name
+    );
+    Mock::given(path(list_files_url))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(list_files_body))
+        .expect(2)
+        .mount(&app.server)
+        .await;
+    Mock::given(path(file_url_path))
+        .and(method("GET"))
+        .respond_with(RangeResponder(file_content))
+        .mount(&app.server)
+        .await;
+
+    let mut c = app.client;
+    c.data_root = common::get_random_location(Path::new(env!("CARGO_TARGET_TMPDIR")))
+        .to_str()
+        .unwrap()
+        .to_string();
+
+    let df = c.get_dataframe(&table).await.unwrap().collect().unwrap();
+    assert_eq!(
+        df.get_column_names(),
+        vec!["row_id", "label", "amount"],
+        "Column names mismatch"
+    );
+
+    let options = ReadOptions {
+        columns: Some(vec!["label".to_string()]),
+        limit: None,
+    };
+    let df = c.read_dataframe(&table, &options).await.unwrap();
+    assert_eq!(
+        df.get_column_names(),
+        vec!["label"],
+        "Column names mismatch"
+    );
+    assert_eq!(df.shape(), (5, 1), "Dataframe shape mismatch");
+}
+
+#[tokio::test]
+async fn watch_resumes_from_checkpoint() {
+    use delta_sharing::checkpoint::{Checkpoint, CheckpointStore, FileCheckpointStore};
+    use delta_sharing::watch::WatchOptions;
+    use futures::StreamExt;
+
+    let table = Table {
+        name: "table_1".to_string(),
+        share: "share_1".to_string(),
+        schema: "schema_1".to_string(),
+        share_id: None,
+        id: None,
+    };
+    let url = "/shares/share_1/schemas/schema_1/tables/table_1/version";
+    let app = common::create_test_app().await;
+    Mock::given(path(url))
+        .and(method("GET"))
+        .respond_with(ResponseTemplate::new(200).insert_header("delta-table-version", "1"))
+        .up_to_n_times(1)
+        .with_priority(1)
+        .mount(&app.server)
+        .await;
+    Mock::given(path(url))
+        .and(method("GET"))
+        .respond_with(ResponseTemplate::new(200).insert_header("delta-table-version", "3"))
+        .mount(&app.server)
+        .await;
+
+    let checkpoints = env::temp_dir().join(format!("{}.json", uuid::Uuid::new_v4()));
+    let store = Arc::new(FileCheckpointStore::new(&checkpoints));
+    let options = WatchOptions {
+        checkpoint_store: Some(store.clone()),
+        ..Default::default()
+    };
+    let interval = Duration::from_millis(10);
+    let events = app
+        .client
+        .watch_with_options(&table, interval, options.clone())
+        .take(3)
+        .map(|e| e.map(|e| (e.previous_version, e.version)))
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(events, [(None, 1), (Some(1), 2), (Some(2), 3)]);
+    // The last event was not followed by another request, so it may not have been processed
+    assert_eq!(
+        store.load("share_1.schema_1.table_1").await.unwrap(),
+        Some(Checkpoint::version(2))
+    );
+
+    let mut watch = Box::pin(app.client.watch_with_options(&table, interval, options));
+    let event = watch.next().await.unwrap().unwrap();
+    assert_eq!((event.previous_version, event.version), (Some(2), 3));
+    drop(watch);
+    fs::remove_file(checkpoints).ok();
+}
@@ -1,3 +1,5 @@
+#![allow(dead_code)]
+
 use std::path::{Path, PathBuf};
 
 use uuid::Uuid;
@@ -18,6 +20,10 @@ pub const TEST_PROTOCOL_RESPONSE: &str = r#"{ "minReaderVersion": 1 }"#;
 pub const TEST_METADATA_RESPONSE: &str = r#"{ "id": "cf9c9342-b773-4c7b-a217-037d02ffe5d8", "format": { "provider": "parquet" }, "schemaString": "{\"type\":\"struct\",\"fields\":[{\"name\":\"int_field_1\",\"type\":\"integer\",\"nullable\":true,\"metadata\":{}},{\"name\":\"double_field_1\",\"type\":\"double\",\"nullable\":true,\"metadata\":{}}]}", "partitionColumns": [], "configuration": {"conf_1_name": "conf_1_value"} }"#;
 pub const TEST_FILE_RESPONSE: &str = r#"{ "url": "<url>", "id": "1", "partitionValues": {}, "size": 2350, "stats": "{\"numRecords\":1}" }"#;
 
+pub const TEST_DELTA_PROTOCOL_RESPONSE: &str =
+    r#"{ "deltaProtocol": { "minReaderVersion": 1, "minWriterVersion": 2 } }"#;
+pub const TEST_DELTA_FILE_RESPONSE: &str = r#"{ "id": "1", "expirationTimestamp": 4102444800000, "deltaSingleAction": { "add": { "path": "<url>", "partitionValues": {}, "size": 2350, "modificationTime": 1652140000000, "dataChange": true, "stats": "{\"numRecords\":1}" } } }"#;
+
 pub async fn create_test_app() -> TestApp {
     let _ = env_logger::try_init();
 
@@ -29,8 +35,7 @@ pub async fn create_test_app() -> TestApp {
         bearer_token: Uuid::new_v4().to_string(),
     };
     let client = Client::new(config, None).await.unwrap();
-    let app = TestApp { client, server };
-    app
+    TestApp { client, server }
 }
 
 pub async fn create_mocked_test_app(
@@ -57,9 +62,9 @@ pub fn get_random_location(root: &Path) -> PathBuf {
     use rand::distributions::{Alphanumeric, DistString};
 
     let r = &mut rand::thread_rng();
-    let mut p = root.join(&Alphanumeric.sample_string(r, 10));
+    let mut p = root.join(Alphanumeric.sample_string(r, 10));
     while Path::exists(&p) {
         p = root.join(Alphanumeric.sample_string(r, 10));
     }
-    return p;
+    p
 }
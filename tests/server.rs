@@ -0,0 +1,818 @@
+use delta_sharing::cache::{CacheStore, MemoryCacheStore};
+use delta_sharing::checkpoint::{Checkpoint, CheckpointStore, MemoryCheckpointStore};
+use delta_sharing::incremental::IncrementalOptions;
+use delta_sharing::protocol::*;
+use delta_sharing::remote::ReadOptions;
+use delta_sharing::server::SharingServer;
+use delta_sharing::Client;
+use futures::TryStreamExt;
+use polars::prelude::{ParquetReader, SerReader};
+use reqwest::header::RANGE;
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::{env, fs};
+
+const TEST_FILE: &str = "resources/test/test.parquet";
+
+/// Creates `share_1.schema_1.parquet_table`, a directory of parquet files, and
+/// `share_1.schema_1.delta_table`, a Delta table whose second commit replaces its file
+fn create_tables() -> PathBuf {
+    let root = env::temp_dir().join(format!("sharing-server-{}", uuid::Uuid::new_v4()));
+    let test_file = Path::new(env!("CARGO_MANIFEST_DIR")).join(TEST_FILE);
+    let schema_dir = root.join("share_1").join("schema_1");
+    fs::create_dir_all(schema_dir.join("parquet_table")).unwrap();
+    fs::create_dir_all(root.join("share_2")).unwrap();
+    fs::copy(&test_file, schema_dir.join("parquet_table/part-0.parquet")).unwrap();
+
+    let delta = schema_dir.join("delta_table");
+    fs::create_dir_all(delta.join("_delta_log")).unwrap();
+    fs::copy(&test_file, delta.join("a.parquet")).unwrap();
+    fs::copy(&test_file, delta.join("b.parquet")).unwrap();
+    let size = fs::metadata(&test_file).unwrap().len();
+    let commit = |version: i64, timestamp: i64, actions: Vec<Value>| {
+        let mut lines = vec![json!({ "commitInfo": { "timestamp": timestamp } })];
+        lines.extend(actions);
+        let lines = lines.iter().map(|l| l.to_string()).collect::<Vec<_>>();
+        fs::write(
+            delta
+                .join("_delta_log")
+                .join(format!("{:020}.json", version)),
+            lines.join("\n"),
+        )
+        .unwrap();
+    };
+    let add = |path: &str| json!({ "add": { "path": path, "partitionValues": {}, "size": size, "dataChange": true } });
+    commit(
+        0,
+        1_640_995_200_000,
+        vec![
+            json!({ "protocol": { "minReaderVersion": 1, "minWriterVersion": 2 } }),
+            json!({ "metaData": {
+                "id": "delta-table-id",
+                "format": { "provider": "parquet", "options": {} },
+                "schemaString": r#"{"type":"struct","fields":[]}"#,
+                "partitionColumns": [],
+                "configuration": {},
+            } }),
+            add("a.parquet"),
+        ],
+    );
+    commit(
+        1,
+        1_641_081_600_000,
+        vec![
+            json!({ "remove": { "path": "a.parquet", "dataChange": true } }),
+            add("b.parquet"),
+        ],
+    );
+    root
+}
+
+fn table(name: &str) -> Table {
+    Table {
+        name: name.to_string(),
+        schema: "schema_1".to_string(),
+        share: "share_1".to_string(),
+        share_id: None,
+        id: None,
+    }
+}
+
+#[tokio::test]
+async fn end_to_end() {
+    let root = create_tables();
+    let server = SharingServer::builder(&root)
+        .bearer_token("token")
+        .start()
+        .await
+        .unwrap();
+    let client = Client::builder(server.profile())
+        .data_root(root.join("cache").to_string_lossy())
+        .build()
+        .unwrap();
+
+    let shares = client.list_shares().await.unwrap();
+    assert_eq!(
+        shares.iter().map(|s| s.name.as_str()).collect::<Vec<_>>(),
+        ["share_1", "share_2"]
+    );
+    let tables = client.list_all_tables(&shares[0]).await.unwrap();
+    assert_eq!(
+        tables.iter().map(|t| t.name.as_str()).collect::<Vec<_>>(),
+        ["delta_table", "parquet_table"]
+    );
+
+    let parquet_table = table("parquet_table");
+    assert_eq!(client.get_table_version(&parquet_table).await.unwrap(), 0);
+    let metadata = client.get_table_metadata(&parquet_table).await.unwrap();
+    assert_eq!(metadata.metadata.format.provider, "parquet");
+    let batches = client
+        .read_record_batches(&parquet_table, &ReadOptions::default())
+        .await
+        .unwrap();
+    let expected = ParquetReader::new(
+        fs::File::open(Path::new(env!("CARGO_MANIFEST_DIR")).join(TEST_FILE)).unwrap(),
+    )
+    .finish()
+    .unwrap();
+    assert_eq!(
+        batches.iter().map(|b| b.num_rows()).sum::<usize>(),
+        expected.height()
+    );
+    let df = client
+        .get_dataframe(&parquet_table)
+        .await
+        .unwrap()
+        .collect()
+        .unwrap();
+    assert_eq!(df.shape(), expected.shape());
+
+    let delta_table = table("delta_table");
+    assert_eq!(client.get_table_version(&delta_table).await.unwrap(), 1);
+    assert_eq!(
+        client
+            .get_table_version_at(&delta_table, "2022-01-01T12:00:00Z")
+            .await
+            .unwrap(),
+        1
+    );
+    let (version, metadata) = client
+        .get_table_metadata_at(&delta_table, None, Some("2022-01-01T12:00:00Z"))
+        .await
+        .unwrap();
+    assert_eq!(version, 0);
+    assert_eq!(metadata.metadata.id, "delta-table-id");
+    let files = client
+        .list_table_files(&delta_table, None, None, Some(0))
+        .await
+        .unwrap();
+    assert_eq!(files.version, Some(0));
+    assert_eq!(files.files.len(), 1);
+    assert!(files.files[0].url.contains("/a.parquet?"));
+    let files = client
+        .list_table_files(&delta_table, None, None, None)
+        .await
+        .unwrap();
+    assert_eq!(files.version, Some(1));
+    assert!(files.files[0].url.contains("/b.parquet?"));
+    assert!(client
+        .list_table_files(&delta_table, None, None, Some(2))
+        .await
+        .is_err());
+
+    let http = reqwest::Client::new();
+    let response = http
+        .get(&files.files[0].url)
+        .header(RANGE, "bytes=0-3")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(&response.bytes().await.unwrap()[..], b"PAR1");
+    let response = http
+        .get(&files.files[0].url)
+        .header(RANGE, "bytes=-4")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(&response.bytes().await.unwrap()[..], b"PAR1");
+    let tampered = files.files[0].url.replace("b.parquet", "a.parquet");
+    let response = http.get(&tampered).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    server.shutdown().await;
+    fs::remove_dir_all(root).ok();
+}
+
+#[tokio::test]
+async fn token_auth_and_pagination() {
+    let root = create_tables();
+    let server = SharingServer::builder(&root)
+        .bearer_token("token")
+        .max_page_size(1)
+        .start()
+        .await
+        .unwrap();
+    let unauthorized = |e: anyhow::Error| {
+        matches!(
+            e.downcast_ref::<delta_sharing::Error>(),
+            Some(delta_sharing::Error::Http(e)) if e.status() == Some(StatusCode::UNAUTHORIZED)
+        )
+    };
+    let mut profile = server.profile();
+    profile.bearer_token = "wrong".to_string();
+    let client = Client::builder(profile).build().unwrap();
+    assert!(unauthorized(client.list_shares().await.unwrap_err()));
+
+    // The server returns one item per page, the client follows the page tokens
+    let client = Client::builder(server.profile()).build().unwrap();
+    let shares = client.list_shares().await.unwrap();
+    assert_eq!(
+        shares.iter().map(|s| s.name.as_str()).collect::<Vec<_>>(),
+        ["share_1", "share_2"]
+    );
+    assert!(shares.iter().all(|s| s.id.is_some()));
+    let tables = client.list_all_tables(&shares[0]).await.unwrap();
+    assert_eq!(
+        tables.iter().map(|t| t.name.as_str()).collect::<Vec<_>>(),
+        ["delta_table", "parquet_table"]
+    );
+    assert_eq!(tables[0].share_id, shares[0].id);
+
+    match client
+        .get_share("share_3")
+        .await
+        .unwrap_err()
+        .downcast_ref::<delta_sharing::Error>()
+    {
+        Some(delta_sharing::Error::NotFound(_)) => {}
+        e => panic!("Expected a not found error, got {:?}", e),
+    }
+
+    server.shutdown().await;
+    fs::remove_dir_all(root).ok();
+}
+
+#[tokio::test]
+async fn signing_key() {
+    let root = create_tables();
+    let start = |key: &'static str| {
+        SharingServer::builder(&root)
+            .bearer_token("token")
+            .signing_key(key)
+            .start()
+    };
+    let server = start("key").await.unwrap();
+    let client = Client::builder(server.profile()).build().unwrap();
+    let files = client
+        .list_table_files(&table("delta_table"), None, None, None)
+        .await
+        .unwrap();
+    let path = &files.files[0].url[server.endpoint().len()..];
+    server.shutdown().await;
+
+    // URLs signed with the same key stay valid when the server restarts
+    let http = reqwest::Client::new();
+    let server = start("key").await.unwrap();
+    let url = format!("{}{}", server.endpoint(), path);
+    assert_eq!(
+        http.get(&url).send().await.unwrap().status(),
+        StatusCode::OK
+    );
+    server.shutdown().await;
+    let server = start("other key").await.unwrap();
+    let url = format!("{}{}", server.endpoint(), path);
+    assert_eq!(
+        http.get(&url).send().await.unwrap().status(),
+        StatusCode::BAD_REQUEST
+    );
+
+    server.shutdown().await;
+    fs::remove_dir_all(root).ok();
+}
+
+/// Z85 encoding of the deletion vector descriptors
+fn z85_encode(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] =
+        b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ.-:+=^!/*?&<>()[]{}@%$#";
+    let mut encoded = String::new();
+    for chunk in bytes.chunks(4) {
+        let mut value = u32::from_be_bytes(chunk.try_into().unwrap()) as usize;
+        let mut digits = [0u8; 5];
+        for d in digits.iter_mut().rev() {
+            *d = ALPHABET[value % 85];
+            value /= 85;
+        }
+        encoded.push_str(std::str::from_utf8(&digits).unwrap());
+    }
+    encoded
+}
+
+#[tokio::test]
+async fn deletion_vectors() {
+    let root = create_tables();
+    let dir = root.join("share_1/schema_1/dv_table");
+    fs::create_dir_all(dir.join("_delta_log")).unwrap();
+    fs::create_dir_all(dir.join("dv")).unwrap();
+    let test_file = Path::new(env!("CARGO_MANIFEST_DIR")).join(TEST_FILE);
+    fs::copy(&test_file, dir.join("a.parquet")).unwrap();
+
+    // Deletes the rows 0 and 2 of a.parquet, stored in dv/ next to the table
+    let mut data = 1681511377u32.to_le_bytes().to_vec();
+    [0u64, 2]
+        .into_iter()
+        .collect::<roaring::RoaringTreemap>()
+        .serialize_into(&mut data)
+        .unwrap();
+    let mut content = vec![1u8];
+    content.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    content.extend_from_slice(&data);
+    content.extend_from_slice(&crc32fast::hash(&data).to_be_bytes());
+    fs::write(
+        dir.join("dv/deletion_vector_11111111-1111-1111-1111-111111111111.bin"),
+        content,
+    )
+    .unwrap();
+    let actions = [
+        json!({ "protocol": {
+            "minReaderVersion": 3,
+            "minWriterVersion": 7,
+            "readerFeatures": ["deletionVectors"],
+            "writerFeatures": ["deletionVectors"],
+        } }),
+        json!({ "metaData": {
+            "id": "dv-table-id",
+            "format": { "provider": "parquet", "options": {} },
+            "schemaString": r#"{"type":"struct","fields":[]}"#,
+            "partitionColumns": [],
+            "configuration": {},
+        } }),
+        json!({ "add": {
+            "path": "a.parquet",
+            "partitionValues": {},
+            "size": fs::metadata(&test_file).unwrap().len(),
+            "dataChange": true,
+            "deletionVector": {
+                "storageType": "u",
+                "pathOrInlineDv": format!("dv{}", z85_encode(&[0x11; 16])),
+                "offset": 1,
+                "sizeInBytes": data.len(),
+                "cardinality": 2,
+            },
+        } }),
+    ];
+    fs::write(
+        dir.join("_delta_log").join(format!("{:020}.json", 0)),
+        actions.map(|a| a.to_string()).join("\n"),
+    )
+    .unwrap();
+    let server = SharingServer::builder(&root)
+        .bearer_token("token")
+        .start()
+        .await
+        .unwrap();
+    let dv_table = table("dv_table");
+    // Deletion vectors can not be described by the parquet response format
+    let parquet_client = Client::builder(server.profile())
+        .data_root(root.join("parquet-cache").to_string_lossy())
+        .build()
+        .unwrap();
+    assert!(parquet_client.get_table_metadata(&dv_table).await.is_err());
+    assert!(parquet_client
+        .list_table_files(&dv_table, None, None, None)
+        .await
+        .is_err());
+
+    let response = reqwest::Client::new()
+        .post(format!(
+            "{}/shares/share_1/schemas/schema_1/tables/dv_table/query",
+            server.profile().endpoint.trim_end_matches('/')
+        ))
+        .bearer_auth("token")
+        .header("delta-sharing-capabilities", "responseformat=parquet,delta")
+        .json(&json!({}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(
+        response.headers()["delta-sharing-capabilities"],
+        "responseformat=delta"
+    );
+    let lines = response.text().await.unwrap();
+    let lines = lines
+        .lines()
+        .map(|l| serde_json::from_str::<Value>(l).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(lines[0]["protocol"]["deltaProtocol"]["minReaderVersion"], 3);
+    assert_eq!(lines[1]["metaData"]["deltaMetadata"]["id"], "dv-table-id");
+    assert!(lines[2]["file"]["deltaSingleAction"]["add"]["deletionVector"].is_object());
+
+    let client = Client::builder(server.profile())
+        .data_root(root.join("cache").to_string_lossy())
+        .response_format(ResponseFormat::Delta)
+        .build()
+        .unwrap();
+    let files = client
+        .list_table_files(&dv_table, None, None, None)
+        .await
+        .unwrap();
+    assert_eq!(
+        files.metadata.protocol.reader_features,
+        Some(vec!["deletionVectors".to_string()])
+    );
+    let descriptor = files.files[0].deletion_vector.as_ref().unwrap();
+    assert_eq!(descriptor.storage_type, "p");
+    assert!(descriptor
+        .path_or_inline_dv
+        .contains("/dv/deletion_vector_11111111-1111-1111-1111-111111111111.bin?"));
+
+    let expected = ParquetReader::new(fs::File::open(&test_file).unwrap())
+        .finish()
+        .unwrap();
+    let df = client
+        .get_dataframe(&dv_table)
+        .await
+        .unwrap()
+        .collect()
+        .unwrap();
+    assert_eq!(df.height(), expected.height() - 2);
+
+    server.shutdown().await;
+    fs::remove_dir_all(root).ok();
+}
+
+#[tokio::test]
+async fn mirror_table() {
+    let root = create_tables();
+    let log = root.join("share_1/schema_1/delta_table/_delta_log");
+    let second_commit = log.join(format!("{:020}.json", 1));
+    let pending = root.join("pending.json");
+    fs::rename(&second_commit, &pending).unwrap();
+    let server = SharingServer::builder(&root).start().await.unwrap();
+    let client = Client::builder(server.profile())
+        .data_root(root.join("cache").to_string_lossy())
+        .build()
+        .unwrap();
+    let delta_table = table("delta_table");
+    let mirrors = root.join("mirrors");
+    let destination = mirrors.join("share/schema/mirror");
+
+    let summary = client
+        .mirror_table(&delta_table, &destination)
+        .await
+        .unwrap();
+    assert_eq!(
+        (summary.source_version, summary.version, summary.files_added),
+        (0, 0, 1)
+    );
+    // Only the commits named after their padded version are replayed
+    fs::write(destination.join("_delta_log/1.json"), "not a commit").unwrap();
+
+    fs::rename(&pending, &second_commit).unwrap();
+    let summary = client
+        .mirror_table(&delta_table, &destination)
+        .await
+        .unwrap();
+    assert_eq!(summary.source_version, 1);
+    assert_eq!(summary.version, 1);
+    assert_eq!((summary.files_added, summary.files_removed), (1, 1));
+    let summary = client
+        .mirror_table(&delta_table, &destination)
+        .await
+        .unwrap();
+    assert_eq!((summary.version, summary.files_added), (1, 0));
+    assert!(client
+        .mirror_table(&table("parquet_table"), &destination)
+        .await
+        .is_err());
+
+    // The mirror is a Delta table with the history of the synced versions
+    let mirror_server = SharingServer::builder(&mirrors).start().await.unwrap();
+    let mirror_client = Client::builder(mirror_server.profile())
+        .data_root(root.join("mirror-cache").to_string_lossy())
+        .build()
+        .unwrap();
+    let mirror = Table {
+        name: "mirror".to_string(),
+        schema: "schema".to_string(),
+        share: "share".to_string(),
+        share_id: None,
+        id: None,
+    };
+    assert_eq!(mirror_client.get_table_version(&mirror).await.unwrap(), 1);
+    let metadata = mirror_client.get_table_metadata(&mirror).await.unwrap();
+    assert_eq!(metadata.metadata.id, "delta-table-id");
+    for version in [0, 1] {
+        let source = client
+            .list_table_files(&delta_table, None, None, Some(version))
+            .await
+            .unwrap();
+        let mirrored = mirror_client
+            .list_table_files(&mirror, None, None, Some(version))
+            .await
+            .unwrap();
+        assert_eq!(mirrored.files.len(), 1);
+        assert!(mirrored.files[0]
+            .url
+            .contains(&format!("/{}.parquet?", source.files[0].id)));
+        assert_eq!(mirrored.files[0].size, source.files[0].size);
+    }
+
+    mirror_server.shutdown().await;
+    server.shutdown().await;
+    fs::remove_dir_all(root).ok();
+}
+
+#[tokio::test]
+async fn incremental_read() {
+    let root = create_tables();
+    let server = SharingServer::builder(&root).start().await.unwrap();
+    let client = Client::builder(server.profile())
+        .data_root(root.join("cache").to_string_lossy())
+        .build()
+        .unwrap();
+    let delta_table = table("delta_table");
+
+    let changes = client
+        .list_table_changes(&delta_table, 0, None)
+        .await
+        .unwrap();
+    assert_eq!(changes.metadata.metadata.version, Some(1));
+    let summary = changes
+        .changes
+        .iter()
+        .map(|c| match c {
+            FileChange::Add(_) => ("add", c.version()),
+            FileChange::Remove(_) => ("remove", c.version()),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        summary,
+        [("add", Some(0)), ("remove", Some(1)), ("add", Some(1))]
+    );
+    let delta_client = Client::builder(server.profile())
+        .data_root(root.join("delta-cache").to_string_lossy())
+        .response_format(ResponseFormat::Delta)
+        .build()
+        .unwrap();
+    let delta_changes = delta_client
+        .list_table_changes(&delta_table, 0, None)
+        .await
+        .unwrap();
+    assert_eq!(delta_changes.metadata.metadata, changes.metadata.metadata);
+    for (delta_change, change) in delta_changes.changes.iter().zip(&changes.changes) {
+        assert_eq!(
+            (delta_change.version(), &delta_change.file().id),
+            (change.version(), &change.file().id)
+        );
+    }
+    let changes = client
+        .list_table_changes(&delta_table, 1, Some(1))
+        .await
+        .unwrap();
+    assert_eq!(changes.changes.len(), 2);
+    assert!(client
+        .list_table_changes(&delta_table, 2, Some(1))
+        .await
+        .is_err());
+
+    let store = Arc::new(MemoryCheckpointStore::new());
+    let options = IncrementalOptions {
+        max_files_per_batch: Some(2),
+        checkpoint_store: Some(store.clone()),
+        ..Default::default()
+    };
+    let batches = client
+        .read_incremental(&delta_table, options.clone())
+        .try_collect::<Vec<_>>()
+        .await
+        .unwrap();
+    assert_eq!(batches.len(), 2);
+    assert_eq!(batches[0].changes.len(), 2);
+    assert_eq!(
+        batches[0].end,
+        Checkpoint {
+            version: 0,
+            index: 1
+        }
+    );
+    assert_eq!(batches[1].start, batches[0].end);
+    assert_eq!(batches[1].end, Checkpoint::version(1));
+    assert_eq!(batches[1].added().count(), 1);
+    let data = client
+        .read_micro_batch(&delta_table, &batches[1], &ReadOptions::default())
+        .await
+        .unwrap();
+    assert!(data.iter().map(|b| b.num_rows()).sum::<usize>() > 0);
+    assert_eq!(
+        store.load("share_1.schema_1.delta_table").await.unwrap(),
+        Some(Checkpoint::version(1))
+    );
+
+    // A restarted read resumes after the saved checkpoint
+    let batches = client
+        .read_incremental(&delta_table, options.clone())
+        .try_collect::<Vec<_>>()
+        .await
+        .unwrap();
+    assert!(batches.is_empty());
+    store
+        .save(
+            "share_1.schema_1.delta_table",
+            Checkpoint {
+                version: 0,
+                index: 1,
+            },
+        )
+        .await
+        .unwrap();
+    let batches = client
+        .read_incremental(&delta_table, options)
+        .try_collect::<Vec<_>>()
+        .await
+        .unwrap();
+    assert_eq!(batches.len(), 1);
+    assert_eq!(batches[0].changes.len(), 1);
+    assert!(matches!(batches[0].changes[0], FileChange::Add(_)));
+
+    server.shutdown().await;
+    fs::remove_dir_all(root).ok();
+}
+
+#[tokio::test]
+async fn watch_lists_version_changes() {
+    use delta_sharing::watch::WatchOptions;
+    use std::time::Duration;
+
+    let root = create_tables();
+    // The third commit adds a file next to the one of the second commit
+    let delta = root.join("share_1/schema_1/delta_table");
+    let test_file = Path::new(env!("CARGO_MANIFEST_DIR")).join(TEST_FILE);
+    fs::copy(&test_file, delta.join("c.parquet")).unwrap();
+    let size = fs::metadata(&test_file).unwrap().len();
+    let lines = [
+        json!({ "commitInfo": { "timestamp": 1_641_168_000_000i64 } }),
+        json!({ "add": { "path": "c.parquet", "partitionValues": {}, "size": size, "dataChange": true } }),
+    ];
+    fs::write(
+        delta.join("_delta_log").join(format!("{:020}.json", 2)),
+        lines.map(|l| l.to_string()).join("\n"),
+    )
+    .unwrap();
+    let server = SharingServer::builder(&root).start().await.unwrap();
+    let client = Client::builder(server.profile())
+        .data_root(root.join("cache").to_string_lossy())
+        .build()
+        .unwrap();
+    let delta_table = table("delta_table");
+
+    let store = Arc::new(MemoryCheckpointStore::new());
+    store
+        .save("share_1.schema_1.delta_table", Checkpoint::version(1))
+        .await
+        .unwrap();
+    let options = WatchOptions {
+        include_files: true,
+        checkpoint_store: Some(store),
+        ..Default::default()
+    };
+    let mut watch =
+        Box::pin(client.watch_with_options(&delta_table, Duration::from_millis(10), options));
+    let event = watch.try_next().await.unwrap().unwrap();
+    assert_eq!((event.version, event.previous_version), (2, Some(1)));
+    let previous = client
+        .list_table_files(&delta_table, None, None, Some(1))
+        .await
+        .unwrap();
+    let changes = event.changes.unwrap().changes;
+    assert_eq!(changes.len(), 1);
+    assert!(matches!(changes[0], FileChange::Add(_)));
+    assert_eq!(changes[0].version(), Some(2));
+    assert!(previous.files.iter().all(|f| f.id != changes[0].file().id));
+
+    drop(watch);
+    server.shutdown().await;
+    fs::remove_dir_all(root).ok();
+}
+
+/// Reads the Delta table with two clients sharing the cache store, the second one after the
+/// data file was deleted from the server
+async fn read_with_shared_cache(store: Arc<dyn CacheStore>) {
+    let root = create_tables();
+    let server = SharingServer::builder(&root).start().await.unwrap();
+    let client = |data_root: &str| {
+        Client::builder(server.profile())
+            .data_root(root.join(data_root).to_string_lossy())
+            .cache_store(store.clone())
+            .build()
+            .unwrap()
+    };
+    let delta_table = table("delta_table");
+
+    let df = client("first")
+        .get_dataframe(&delta_table)
+        .await
+        .unwrap()
+        .collect()
+        .unwrap();
+    assert!(!root.join("first").exists());
+
+    fs::remove_file(root.join("share_1/schema_1/delta_table/b.parquet")).unwrap();
+    let second = client("second");
+    let cached = second
+        .get_dataframe(&delta_table)
+        .await
+        .unwrap()
+        .collect()
+        .unwrap();
+    assert_eq!(cached.shape(), df.shape());
+    let files = second.get_files(&delta_table).await.unwrap();
+    assert_eq!(files.len(), 1);
+    assert!(files[0].starts_with(root.join("second")));
+    assert!(files[0].exists());
+
+    server.shutdown().await;
+    fs::remove_dir_all(root).ok();
+}
+
+#[tokio::test]
+async fn memory_cache_store() {
+    read_with_shared_cache(Arc::new(MemoryCacheStore::new())).await;
+}
+
+#[cfg(feature = "object-store")]
+#[tokio::test]
+async fn object_cache_store() {
+    use delta_sharing::cache::ObjectCacheStore;
+    use object_store::memory::InMemory;
+
+    read_with_shared_cache(Arc::new(ObjectCacheStore::new(Arc::new(InMemory::new())))).await;
+}
+
+#[tokio::test]
+async fn memory_cache() {
+    let root = create_tables();
+    let server = SharingServer::builder(&root).start().await.unwrap();
+    let data_root = root.join("read-only");
+    let file_size = fs::metadata(Path::new(env!("CARGO_MANIFEST_DIR")).join(TEST_FILE))
+        .unwrap()
+        .len();
+    let client = Client::builder(server.profile())
+        .data_root(data_root.to_string_lossy())
+        .memory_cache(file_size + 1024)
+        .build()
+        .unwrap();
+
+    // Each table fits into the cache alone, so switching tables evicts the files of the other
+    for name in ["delta_table", "parquet_table", "delta_table"] {
+        let df = client
+            .get_dataframe(&table(name))
+            .await
+            .unwrap()
+            .collect()
+            .unwrap();
+        assert!(df.height() > 0);
+    }
+    assert!(!data_root.exists());
+
+    server.shutdown().await;
+    fs::remove_dir_all(root).ok();
+}
+
+#[tokio::test]
+async fn verify_cache() {
+    let root = create_tables();
+    let server = SharingServer::builder(&root).start().await.unwrap();
+    let client = Client::builder(server.profile())
+        .data_root(root.join("cache").to_string_lossy())
+        .build()
+        .unwrap();
+    let parquet_table = table("parquet_table");
+    let files = client.get_files(&parquet_table).await.unwrap();
+    let listed = client
+        .list_table_files(&parquet_table, None, None, None)
+        .await
+        .unwrap();
+
+    let mut content = fs::read(&files[0]).unwrap();
+    content[4] ^= 0xff;
+    fs::write(&files[0], content).unwrap();
+    let verification = client.verify_cache(&parquet_table).await.unwrap();
+    assert_eq!(verification.files, 1);
+    assert_eq!(verification.repaired, [listed.files[0].id.clone()]);
+    assert!(ParquetReader::new(fs::File::open(&files[0]).unwrap())
+        .finish()
+        .is_ok());
+    let verification = client.verify_cache(&parquet_table).await.unwrap();
+    assert!(verification.repaired.is_empty());
+
+    // A client reading the cache for the first time downloads the corrupt files again
+    fs::write(&files[0], b"PAR1").unwrap();
+    let other = Client::builder(server.profile())
+        .data_root(root.join("cache").to_string_lossy())
+        .build()
+        .unwrap();
+    other.get_files(&parquet_table).await.unwrap();
+    assert_eq!(
+        fs::metadata(&files[0]).unwrap().len() as i64,
+        listed.files[0].size
+    );
+
+    // Files without a parquet footer are rejected
+    let source = root.join("share_1/schema_1/parquet_table/part-0.parquet");
+    let mut content = fs::read(&source).unwrap();
+    let len = content.len();
+    content[len - 1] = 0;
+    fs::write(&source, content).unwrap();
+    let fresh = Client::builder(server.profile())
+        .data_root(root.join("fresh-cache").to_string_lossy())
+        .build()
+        .unwrap();
+    assert!(fresh.get_files(&parquet_table).await.is_err());
+
+    server.shutdown().await;
+    fs::remove_dir_all(root).ok();
+}
@@ -0,0 +1,124 @@
+use arrow::array::{Int64Array, StringArray};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatch;
+use delta_sharing::remote::ReadOptions;
+use delta_sharing::testing::{write_parquet, MockSharingServer, TEST_BEARER_TOKEN};
+use delta_sharing::Client;
+use std::env;
+use std::sync::Arc;
+
+fn batch(schema: &Arc<Schema>, ids: Vec<i64>) -> RecordBatch {
+    let names = ids
+        .iter()
+        .map(|i| format!("name_{}", i))
+        .collect::<Vec<_>>();
+    RecordBatch::try_new(
+        schema.clone(),
+        vec![
+            Arc::new(Int64Array::from(ids)),
+            Arc::new(StringArray::from(
+                names.iter().map(|n| n.as_str()).collect::<Vec<_>>(),
+            )),
+        ],
+    )
+    .unwrap()
+}
+
+#[tokio::test]
+async fn mock_sharing_server() {
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Int64, false),
+        Field::new("name", DataType::Utf8, true),
+    ]));
+    let dir = env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+    std::fs::create_dir_all(&dir).unwrap();
+    let first = dir.join("first.parquet");
+    let second = dir.join("second.parquet");
+    write_parquet(&first, &[batch(&schema, vec![1, 2])]).unwrap();
+    write_parquet(&second, &[batch(&schema, vec![3])]).unwrap();
+
+    let server = MockSharingServer::builder()
+        .with_share("empty_share")
+        .with_table("share_1.schema_1.users", schema.clone(), [&first, &second])
+        .with_table("share_1.schema_2.empty", schema, Vec::<&str>::new())
+        .start()
+        .await
+        .unwrap();
+    assert_eq!(server.profile().bearer_token, TEST_BEARER_TOKEN);
+    let client = Client::builder(server.profile())
+        .data_root(dir.join("cache").to_string_lossy())
+        .build()
+        .unwrap();
+
+    let shares = client.list_shares().await.unwrap();
+    assert_eq!(
+        shares.iter().map(|s| s.name.as_str()).collect::<Vec<_>>(),
+        ["empty_share", "share_1"]
+    );
+    let schemas = client.list_schemas(&shares[1]).await.unwrap();
+    assert_eq!(schemas.len(), 2);
+    let tables = client.list_all_tables(&shares[1]).await.unwrap();
+    assert_eq!(tables.len(), 2);
+
+    let users = server.table("share_1.schema_1.users").unwrap();
+    let metadata = client.get_table_metadata(&users).await.unwrap();
+    assert!(metadata
+        .metadata
+        .schema_string
+        .contains(r#""name":"id","type":"long""#));
+    let files = client
+        .list_table_files(&users, None, None, None)
+        .await
+        .unwrap();
+    assert_eq!(files.version, Some(0));
+    assert_eq!(files.files.len(), 2);
+    assert_eq!(files.files[0].stats.as_deref(), Some(r#"{"numRecords":2}"#));
+    let batches = client
+        .read_record_batches(&users, &ReadOptions::default())
+        .await
+        .unwrap();
+    assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 3);
+    let df = client
+        .get_dataframe(&users)
+        .await
+        .unwrap()
+        .collect()
+        .unwrap();
+    assert_eq!(df.shape(), (3, 2));
+
+    let empty = server.table("share_1.schema_2.empty").unwrap();
+    let files = client
+        .list_table_files(&empty, None, None, None)
+        .await
+        .unwrap();
+    assert!(files.files.is_empty());
+    assert!(server.table("share_1.schema_1.missing").is_err());
+
+    let root = server.root().to_path_buf();
+    drop(server);
+    assert!(!root.exists());
+    std::fs::remove_dir_all(dir).ok();
+}
+
+#[tokio::test]
+async fn mock_table_schema_mismatch() {
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Int64, false),
+        Field::new("name", DataType::Utf8, true),
+    ]));
+    let dir = env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("users.parquet");
+    write_parquet(&file, &[batch(&schema, vec![1])]).unwrap();
+
+    let wrong_schema = Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Int32, false),
+        Field::new("name", DataType::Utf8, true),
+    ]));
+    let result = MockSharingServer::builder()
+        .with_table("share.schema.users", wrong_schema, [&file])
+        .start()
+        .await;
+    assert!(result.is_err());
+    std::fs::remove_dir_all(dir).ok();
+}